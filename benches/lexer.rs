@@ -0,0 +1,40 @@
+// Lexes generated scripts of doubling size; time per byte should stay flat if lexing is linear.
+// Run with `cargo bench --bench lexer`
+use std::hint::black_box;
+use std::time::Instant;
+
+use alethia::Lexer;
+
+fn generate_script(target_len: usize) -> String {
+    let mut script = String::with_capacity(target_len + 64);
+    let mut i = 0;
+    while script.len() < target_len {
+        script.push_str(&format!(
+            "let value_{i} = [{i}, 0x{i:X}, 1_000.5e-2, \"item {{value_{i}}}\"];\nprint value_{i}[0] * 2;\n"
+        ));
+        i += 1;
+    }
+    script
+}
+
+fn main() {
+    println!("{:>12} {:>10} {:>12} {:>10}", "bytes", "tokens", "time (ms)", "ns/byte");
+
+    for kb in [64, 128, 256, 512, 1024] {
+        let script = generate_script(kb * 1024);
+
+        let start = Instant::now();
+        let tokens = Lexer::new(black_box(&script))
+            .collect::<Result<Vec<_>, _>>()
+            .expect("generated script should lex");
+        let elapsed = start.elapsed();
+
+        println!(
+            "{:>12} {:>10} {:>12.2} {:>10.2}",
+            script.len(),
+            tokens.len(),
+            elapsed.as_secs_f64() * 1000.0,
+            elapsed.as_nanos() as f64 / script.len() as f64
+        );
+    }
+}
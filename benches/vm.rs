@@ -0,0 +1,37 @@
+// Times a recursive fibonacci(25) on the tree-walking interpreter and on the bytecode VM.
+// Run with `cargo bench --bench vm`
+use std::time::{Duration, Instant};
+
+use alethia::{Interpreter, Vm};
+
+const SCRIPT: &str = "
+func fibonacci(n) {
+    if n < 2 {
+        return n;
+    }
+    return fibonacci(n - 1) + fibonacci(n - 2);
+}
+fibonacci(25);
+";
+
+fn time(mut run: impl FnMut() -> Result<(), String>) -> Duration {
+    // best of three, to smooth out noise
+    (0..3)
+        .map(|_| {
+            let start = Instant::now();
+            run().expect("benchmark script should run");
+            start.elapsed()
+        })
+        .min()
+        .expect("at least one run")
+}
+
+fn main() {
+    let tree = time(|| alethia::run_source_with(SCRIPT.to_string(), &mut Interpreter::new()));
+    let vm = time(|| alethia::run_source_vm(SCRIPT.to_string(), &mut Vm::new()));
+
+    println!("{:>14} {:>12}", "engine", "time (ms)");
+    println!("{:>14} {:>12.2}", "tree-walker", tree.as_secs_f64() * 1000.0);
+    println!("{:>14} {:>12.2}", "bytecode VM", vm.as_secs_f64() * 1000.0);
+    println!("speedup: {:.1}x", tree.as_secs_f64() / vm.as_secs_f64());
+}
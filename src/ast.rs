@@ -1,17 +1,27 @@
-use crate::token::Token;
+use crate::token::{Span, Token};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum AstNode {
     Program(Vec<AstNode>),
-    VariableDeclaration { name: String, value: Box<AstNode> },
-    BinaryExpression{ left: Box<AstNode>, operator: Token, right: Box<AstNode> },
+    VariableDeclaration { name: String, value: Box<AstNode>, constant: bool },
+    DestructuringDeclaration { pattern: Pattern, value: Box<AstNode>, constant: bool },
+    DestructuringAssignment { pattern: Pattern, value: Box<AstNode> },
+    Assignment { target: Box<AstNode>, value: Box<AstNode> },
+    CompoundAssignment { target: Box<AstNode>, operator: Token, value: Box<AstNode> },
+    // operations that can fail keep the span of their operator, reported in tracebacks
+    BinaryExpression{ left: Box<AstNode>, operator: Token, right: Box<AstNode>, span: Span },
+    UnaryExpression { operator: Token, operand: Box<AstNode>, span: Span },
     NumberLiteral(f64),
     StringLiteral(String),
+    InterpolatedString(Vec<InterpolationPart>),
     Identifier(String),
+    // an identifier after the resolver has bound it to its declaration
+    Variable { name: String, binding: Binding },
     PrintStatement(Box<AstNode>),
     ArrayLiteral(Vec<AstNode>), 
     DictionaryLiteral(Vec<(String, AstNode)>),
-    IndexExpression(Box<AstNode>, Box<AstNode>),
+    // the span is the '['
+    IndexExpression(Box<AstNode>, Box<AstNode>, Span),
     Bool(bool),
     Block(Vec<AstNode>),
     IfStatement {
@@ -26,14 +36,201 @@ pub enum AstNode {
     Break,
     FunctionDeclaration {
         name: String,
-        params: Vec<String>,
+        params: Vec<Pattern>,
         body: Box<AstNode>,
     },
     FunctionCall {
         function: Box<AstNode>,
         arguments: Vec<AstNode>,
+        // where the called expression starts
+        span: Span,
     },
     Return(Option<Box<AstNode>>),
+    Match {
+        subject: Box<AstNode>,
+        arms: Vec<MatchArm>,
+        // the `match` keyword, where a value no arm matches is reported
+        span: Span,
+    },
+    StructDeclaration {
+        name: String,
+        fields: Vec<String>,
+    },
+    StructLiteral {
+        name: String,
+        fields: Vec<(String, AstNode)>,
+    },
+    FieldAccess {
+        object: Box<AstNode>,
+        field: String,
+        // the '.'
+        span: Span,
+    },
+    ImplBlock {
+        type_name: String,
+        // FunctionDeclaration nodes
+        methods: Vec<AstNode>,
+    },
+    MethodCall {
+        object: Box<AstNode>,
+        method: String,
+        arguments: Vec<AstNode>,
+        span: Span,
+    },
+    EnumDeclaration {
+        name: String,
+        // variant name and the names of its payload fields
+        variants: Vec<(String, Vec<String>)>,
+    },
+    IsPattern {
+        value: Box<AstNode>,
+        pattern: Pattern,
+    },
+    Throw(Box<AstNode>, Span),
+    // `expr?`: the value inside an ok result, or an early return of an error result
+    Propagate(Box<AstNode>),
+    TryStatement {
+        body: Box<AstNode>,
+        // the pattern the caught error is bound to, and the handler
+        catch: Option<(Option<Pattern>, Box<AstNode>)>,
+        finally: Option<Box<AstNode>>,
+    },
     Nil,
 
+}
+
+// Where the resolver found a variable's declaration: a slot in the frame of the function
+// using it, or a global slot
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Binding {
+    Local(usize),
+    Global(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub guard: Option<AstNode>,
+    pub body: AstNode,
+}
+
+// Patterns used by `let`, destructuring assignment, function parameters and `match` arms
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    Identifier(String),
+    // a binding name after resolution
+    Variable { name: String, binding: Binding },
+    Wildcard,
+    // a number, string, bool or nil literal
+    Literal(Box<AstNode>),
+    Range { start: f64, end: f64, inclusive: bool },
+    // `rest` is always an identifier pattern
+    Array { elements: Vec<Pattern>, rest: Option<Box<Pattern>> },
+    Dictionary(Vec<(String, Pattern)>),
+    Or(Vec<Pattern>),
+    // an enum variant such as Shape.Circle(r); without parentheses any payload matches
+    Variant { enum_name: String, variant: String, fields: Option<Vec<Pattern>> },
+}
+
+impl Pattern {
+    // Every variable name the pattern binds, in source order
+    pub fn bound_names(&self) -> Vec<&str> {
+        match self {
+            Pattern::Identifier(name) | Pattern::Variable { name, .. } => vec![name.as_str()],
+            Pattern::Array { elements, rest } => {
+                let mut names: Vec<&str> = elements.iter().flat_map(|p| p.bound_names()).collect();
+                names.extend(rest.iter().flat_map(|p| p.bound_names()));
+                names
+            }
+            Pattern::Dictionary(entries) => entries.iter().flat_map(|(_, p)| p.bound_names()).collect(),
+            // alternatives are required to bind the same names
+            Pattern::Or(alternatives) => alternatives.first().map_or_else(Vec::new, |p| p.bound_names()),
+            Pattern::Variant { fields, .. } => fields.iter().flatten().flat_map(|p| p.bound_names()).collect(),
+            Pattern::Wildcard | Pattern::Literal(_) | Pattern::Range { .. } => Vec::new(),
+        }
+    }
+
+    // Whether the pattern matches every possible value
+    pub fn is_irrefutable(&self) -> bool {
+        match self {
+            Pattern::Identifier(_) | Pattern::Variable { .. } | Pattern::Wildcard => true,
+            Pattern::Or(alternatives) => alternatives.iter().any(Pattern::is_irrefutable),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum InterpolationPart {
+    Literal(String),
+    Expression { expr: Box<AstNode>, format: FormatSpec },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Alignment {
+    Left,
+    Right,
+    Center,
+}
+
+// Parsed form of the `[[fill]align][width][.precision]` spec in "{value:spec}"
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct FormatSpec {
+    pub fill: Option<char>,
+    pub align: Option<Alignment>,
+    pub width: Option<usize>,
+    pub precision: Option<usize>,
+}
+
+impl FormatSpec {
+    pub fn parse(spec: &str) -> Result<FormatSpec, String> {
+        let chars: Vec<char> = spec.chars().collect();
+        let mut format = FormatSpec::default();
+        let mut i = 0;
+
+        let to_alignment = |c: char| match c {
+            '<' => Some(Alignment::Left),
+            '>' => Some(Alignment::Right),
+            '^' => Some(Alignment::Center),
+            _ => None,
+        };
+
+        // a fill character is only present when followed by an alignment
+        if chars.len() >= 2 && to_alignment(chars[1]).is_some() {
+            format.fill = Some(chars[0]);
+            format.align = to_alignment(chars[1]);
+            i = 2;
+        } else if let Some(align) = chars.first().and_then(|c| to_alignment(*c)) {
+            format.align = Some(align);
+            i = 1;
+        }
+
+        let start = i;
+        while i < chars.len() && chars[i].is_ascii_digit() {
+            i += 1;
+        }
+        if i > start {
+            let width: String = chars[start..i].iter().collect();
+            format.width = Some(width.parse().map_err(|_| format!("Invalid width in format spec '{}'", spec))?);
+        }
+
+        if i < chars.len() && chars[i] == '.' {
+            i += 1;
+            let start = i;
+            while i < chars.len() && chars[i].is_ascii_digit() {
+                i += 1;
+            }
+            if i == start {
+                return Err(format!("Expected precision after '.' in format spec '{}'", spec));
+            }
+            let precision: String = chars[start..i].iter().collect();
+            format.precision = Some(precision.parse().map_err(|_| format!("Invalid precision in format spec '{}'", spec))?);
+        }
+
+        if i != chars.len() {
+            return Err(format!("Invalid format spec '{}'", spec));
+        }
+
+        Ok(format)
+    }
 }
\ No newline at end of file
@@ -0,0 +1,495 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::capabilities::Capability;
+use crate::interpreter::{Interpreter, InterpreterError};
+use crate::value::{NativeFn, Value};
+
+// Native functions available to every script, bound in the global scope
+pub fn all() -> Vec<(&'static str, NativeFn)> {
+    vec![
+        ("type", type_of),
+        ("error", error),
+        ("ok", ok),
+        ("err", err),
+        ("parse_number", parse_number),
+        ("env", env),
+        ("now", now),
+        ("random", random),
+        ("run_process", run_process),
+        ("read_file", read_file),
+        ("try_read_file", try_read_file),
+        ("write_file", write_file),
+        ("append_file", append_file),
+        ("read_lines", read_lines),
+        ("file_exists", file_exists),
+        ("remove_file", remove_file),
+        ("list_dir", list_dir),
+        ("make_dir", make_dir),
+        ("path_join", path_join),
+        ("basename", basename),
+        ("extension", extension),
+        ("input", input),
+        ("read_line", read_line),
+        ("read_all_stdin", read_all_stdin),
+        ("stdin_lines", stdin_lines),
+    ]
+}
+
+// Native methods of the objects builtins return, as (type name, method name, function);
+// the receiver is the first argument
+fn type_methods() -> Vec<(&'static str, &'static str, NativeFn)> {
+    vec![("StdinLines", "next", stdin_lines_next)]
+}
+
+// The native method of a `Value::Native` object's type, shared by both engines
+pub fn type_method(type_name: &str, method: &str) -> Option<NativeFn> {
+    type_methods()
+        .into_iter()
+        .find(|(native_type, name, _)| *native_type == type_name && *name == method)
+        .map(|(_, _, func)| func)
+}
+
+fn expect_args(name: &str, args: &[Value], count: usize) -> Result<(), InterpreterError> {
+    if args.len() != count {
+        return Err(InterpreterError::Error(format!(
+            "{}() takes {} argument(s) but {} were given",
+            name,
+            count,
+            args.len()
+        )));
+    }
+    Ok(())
+}
+
+fn type_of(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, InterpreterError> {
+    expect_args("type", &args, 1)?;
+    Ok(Value::String(args[0].type_name().to_string()))
+}
+
+// error(message) or error(message, kind) builds an error value to throw
+fn error(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, InterpreterError> {
+    if args.is_empty() || args.len() > 2 {
+        return Err(InterpreterError::Error(format!(
+            "error() takes 1 or 2 arguments but {} were given",
+            args.len()
+        )));
+    }
+    let kind = match args.get(1) {
+        Some(kind) => expect_string("error", kind)?.to_string(),
+        None => "Error".to_string(),
+    };
+    Ok(Value::error(&kind, args[0].to_string(), Value::Nil))
+}
+
+fn ok(_: &mut Interpreter, mut args: Vec<Value>) -> Result<Value, InterpreterError> {
+    expect_args("ok", &args, 1)?;
+    Ok(Value::ok(args.remove(0)))
+}
+
+fn err(_: &mut Interpreter, mut args: Vec<Value>) -> Result<Value, InterpreterError> {
+    expect_args("err", &args, 1)?;
+    Ok(Value::err(args.remove(0)))
+}
+
+// parse_number("12.5") is ok(12.5); text that isn't a number gives an err with a ValueError
+fn parse_number(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, InterpreterError> {
+    expect_args("parse_number", &args, 1)?;
+    let text = expect_string("parse_number", &args[0])?;
+    Ok(match text.trim().parse::<f64>() {
+        Ok(n) => Value::ok(Value::Number(n)),
+        Err(_) => Value::err(Value::error(
+            "ValueError",
+            format!("Cannot parse '{}' as a number", text),
+            Value::Nil,
+        )),
+    })
+}
+
+// An error from the operating system as a script error, e.g. a FileNotFoundError
+fn io_error(action: &str, error: io::Error) -> InterpreterError {
+    let kind = match error.kind() {
+        io::ErrorKind::NotFound => "FileNotFoundError",
+        io::ErrorKind::AlreadyExists => "FileExistsError",
+        io::ErrorKind::PermissionDenied => "PermissionError",
+        _ => "IOError",
+    };
+    InterpreterError::runtime(kind, format!("{}: {}", action, error))
+}
+
+// env(name) is the environment variable's value, or nil if it isn't set
+fn env(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, InterpreterError> {
+    expect_args("env", &args, 1)?;
+    interpreter.capabilities().check(Capability::Env, "env()")?;
+    let name = expect_string("env", &args[0])?;
+    Ok(std::env::var(name).map_or(Value::Nil, Value::String))
+}
+
+// now() is the number of seconds since the Unix epoch
+fn now(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, InterpreterError> {
+    expect_args("now", &args, 0)?;
+    interpreter.capabilities().check(Capability::Clock, "now()")?;
+    let elapsed = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    Ok(Value::Number(elapsed.as_secs_f64()))
+}
+
+// random() is a number in [0, 1)
+fn random(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, InterpreterError> {
+    expect_args("random", &args, 0)?;
+    interpreter.capabilities().check(Capability::Random, "random()")?;
+    Ok(Value::Number(interpreter.next_random()))
+}
+
+// run_process(program, [args]) runs a program to completion and gives its exit status and output
+fn run_process(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, InterpreterError> {
+    expect_args("run_process", &args, 2)?;
+    interpreter.capabilities().check(Capability::Process, "run_process()")?;
+    let program = expect_string("run_process", &args[0])?;
+    let arguments = match &args[1] {
+        Value::Array(items) => items.iter().map(|item| item.to_string()).collect::<Vec<_>>(),
+        other => {
+            return Err(InterpreterError::Error(format!("run_process() expects an array of arguments, got {}", other.type_name())))
+        }
+    };
+    let output = Command::new(program)
+        .args(&arguments)
+        .output()
+        .map_err(|e| io_error(&format!("Cannot run '{}'", program), e))?;
+    let mut result = HashMap::new();
+    // a process killed by a signal has no exit code
+    let status = output.status.code().map_or(Value::Nil, |code| Value::Number(code as f64));
+    result.insert("status".to_string(), status);
+    result.insert("stdout".to_string(), Value::String(String::from_utf8_lossy(&output.stdout).into_owned()));
+    result.insert("stderr".to_string(), Value::String(String::from_utf8_lossy(&output.stderr).into_owned()));
+    Ok(Value::Dictionary(result))
+}
+
+// The path a file builtin was given, checked against the readable directories
+fn readable_path(interpreter: &Interpreter, name: &str, path: &Value) -> Result<PathBuf, InterpreterError> {
+    interpreter.capabilities().check_read(Path::new(expect_string(name, path)?))
+}
+
+fn writable_path(interpreter: &Interpreter, name: &str, path: &Value) -> Result<PathBuf, InterpreterError> {
+    interpreter.capabilities().check_write(Path::new(expect_string(name, path)?))
+}
+
+// read_file(path) is the whole file as a string
+fn read_file(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, InterpreterError> {
+    expect_args("read_file", &args, 1)?;
+    let path = readable_path(interpreter, "read_file", &args[0])?;
+    fs::read_to_string(&path)
+        .map(Value::String)
+        .map_err(|e| io_error(&format!("Cannot read '{}'", args[0]), e))
+}
+
+// try_read_file(path) is ok(contents), or err(error) where read_file would throw the error
+fn try_read_file(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, InterpreterError> {
+    expect_args("try_read_file", &args, 1)?;
+    match read_file(interpreter, args) {
+        Ok(contents) => Ok(Value::ok(contents)),
+        Err(InterpreterError::Throw(error)) => Ok(Value::err(error)),
+        Err(other) => Err(other),
+    }
+}
+
+// write_file(path, value) replaces the file's contents with the value as text, creating the file if needed
+fn write_file(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, InterpreterError> {
+    expect_args("write_file", &args, 2)?;
+    let path = writable_path(interpreter, "write_file", &args[0])?;
+    fs::write(&path, args[1].to_string()).map_err(|e| io_error(&format!("Cannot write '{}'", args[0]), e))?;
+    Ok(Value::Nil)
+}
+
+// append_file(path, value) adds the value as text to the end of the file, creating the file if needed
+fn append_file(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, InterpreterError> {
+    expect_args("append_file", &args, 2)?;
+    let path = writable_path(interpreter, "append_file", &args[0])?;
+    fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| file.write_all(args[1].to_string().as_bytes()))
+        .map_err(|e| io_error(&format!("Cannot write '{}'", args[0]), e))?;
+    Ok(Value::Nil)
+}
+
+// read_lines(path) is the file's lines without their line endings
+fn read_lines(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, InterpreterError> {
+    expect_args("read_lines", &args, 1)?;
+    let path = readable_path(interpreter, "read_lines", &args[0])?;
+    let text = fs::read_to_string(&path).map_err(|e| io_error(&format!("Cannot read '{}'", args[0]), e))?;
+    Ok(Value::Array(text.lines().map(|line| Value::String(line.to_string())).collect()))
+}
+
+// file_exists(path) is true for an existing file or directory
+fn file_exists(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, InterpreterError> {
+    expect_args("file_exists", &args, 1)?;
+    let path = readable_path(interpreter, "file_exists", &args[0])?;
+    Ok(Value::Bool(path.exists()))
+}
+
+fn remove_file(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, InterpreterError> {
+    expect_args("remove_file", &args, 1)?;
+    let path = interpreter.capabilities().check_remove(Path::new(expect_string("remove_file", &args[0])?))?;
+    fs::remove_file(&path).map_err(|e| io_error(&format!("Cannot remove '{}'", args[0]), e))?;
+    Ok(Value::Nil)
+}
+
+// list_dir(path) is the names of the directory's entries in sorted order
+fn list_dir(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, InterpreterError> {
+    expect_args("list_dir", &args, 1)?;
+    let path = readable_path(interpreter, "list_dir", &args[0])?;
+    let cannot_list = |e| io_error(&format!("Cannot list '{}'", args[0]), e);
+    let mut names = Vec::new();
+    for entry in fs::read_dir(&path).map_err(cannot_list)? {
+        names.push(entry.map_err(cannot_list)?.file_name().to_string_lossy().into_owned());
+    }
+    names.sort();
+    Ok(Value::Array(names.into_iter().map(Value::String).collect()))
+}
+
+// make_dir(path) creates the directory and any missing parents
+fn make_dir(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, InterpreterError> {
+    expect_args("make_dir", &args, 1)?;
+    let path = writable_path(interpreter, "make_dir", &args[0])?;
+    fs::create_dir_all(&path).map_err(|e| io_error(&format!("Cannot create '{}'", args[0]), e))?;
+    Ok(Value::Nil)
+}
+
+// path_join("data", "2024", "jan.csv") joins its arguments with the platform's separator
+fn path_join(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, InterpreterError> {
+    if args.is_empty() {
+        return Err(InterpreterError::Error("path_join() takes at least 1 argument but 0 were given".to_string()));
+    }
+    let mut path = PathBuf::new();
+    for part in &args {
+        path.push(expect_string("path_join", part)?);
+    }
+    Ok(Value::String(path.to_string_lossy().into_owned()))
+}
+
+// basename("data/jan.csv") is "jan.csv"
+fn basename(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, InterpreterError> {
+    expect_args("basename", &args, 1)?;
+    let path = Path::new(expect_string("basename", &args[0])?);
+    Ok(Value::String(path.file_name().map_or(String::new(), |name| name.to_string_lossy().into_owned())))
+}
+
+// extension("data/jan.csv") is "csv"; a path without one gives ""
+fn extension(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, InterpreterError> {
+    expect_args("extension", &args, 1)?;
+    let path = Path::new(expect_string("extension", &args[0])?);
+    Ok(Value::String(path.extension().map_or(String::new(), |ext| ext.to_string_lossy().into_owned())))
+}
+
+fn input_line(interpreter: &mut Interpreter) -> Result<Value, InterpreterError> {
+    match interpreter.read_input_line() {
+        Ok(line) => Ok(line.map_or(Value::Nil, Value::String)),
+        Err(e) => Err(io_error("Cannot read input", e)),
+    }
+}
+
+// input(prompt) shows the prompt and reads a line; nil once the input has ended
+fn input(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, InterpreterError> {
+    expect_args("input", &args, 1)?;
+    interpreter.write_prompt(&args[0].to_string())?;
+    input_line(interpreter)
+}
+
+// read_line() is the next line of stdin without its line ending, or nil at the end
+fn read_line(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, InterpreterError> {
+    expect_args("read_line", &args, 0)?;
+    input_line(interpreter)
+}
+
+// read_all_stdin() is the rest of stdin, or nil if nothing is left
+fn read_all_stdin(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, InterpreterError> {
+    expect_args("read_all_stdin", &args, 0)?;
+    match interpreter.read_all_input() {
+        Ok(text) => Ok(text.map_or(Value::Nil, Value::String)),
+        Err(e) => Err(io_error("Cannot read input", e)),
+    }
+}
+
+// stdin_lines() is an iterator whose next() gives the following line of stdin, or nil at the end
+fn stdin_lines(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, InterpreterError> {
+    expect_args("stdin_lines", &args, 0)?;
+    Ok(Value::Native { type_name: "StdinLines" })
+}
+
+fn stdin_lines_next(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, InterpreterError> {
+    // the receiver only
+    expect_args("next", &args, 1)?;
+    input_line(interpreter)
+}
+
+// Built-in methods that change their receiver, e.g. arr.push(1); the receiver is written back afterwards
+pub fn mutates(method: &str) -> bool {
+    matches!(method, "push" | "pop" | "insert" | "remove" | "clear")
+}
+
+// Calls a built-in method on a core value such as arr.push(1), s.upper() or d.keys().
+// Returns None if the receiver's type has no method with that name
+pub fn call_method(receiver: &mut Value, method: &str, args: Vec<Value>) -> Option<Result<Value, InterpreterError>> {
+    match receiver {
+        Value::Array(items) => array_method(items, method, args),
+        Value::String(s) => string_method(s, method, args),
+        Value::Dictionary(dict) => dictionary_method(dict, method, args),
+        Value::Enum { name, variant, values } if name == "Result" => result_method(variant, values, method, args),
+        _ => None,
+    }
+}
+
+fn expect_number(method: &str, value: &Value) -> Result<f64, InterpreterError> {
+    match value {
+        Value::Number(n) => Ok(*n),
+        other => Err(InterpreterError::Error(format!("{}() expects a number, got {}", method, other.type_name()))),
+    }
+}
+
+fn expect_string<'a>(method: &str, value: &'a Value) -> Result<&'a str, InterpreterError> {
+    match value {
+        Value::String(s) => Ok(s),
+        other => Err(InterpreterError::Error(format!("{}() expects a string, got {}", method, other.type_name()))),
+    }
+}
+
+// Converts a script index into a position below `bound`
+fn expect_index(method: &str, value: &Value, bound: usize) -> Result<usize, InterpreterError> {
+    let index = expect_number(method, value)?;
+    if index < 0.0 || index.fract() != 0.0 || index as usize >= bound {
+        return Err(InterpreterError::Error(format!("{}() index {} out of bounds", method, index)));
+    }
+    Ok(index as usize)
+}
+
+fn array_method(items: &mut Vec<Value>, method: &str, args: Vec<Value>) -> Option<Result<Value, InterpreterError>> {
+    let result = match method {
+        "len" => expect_args(method, &args, 0).map(|_| Value::Number(items.len() as f64)),
+        "push" => expect_args(method, &args, 1).map(|_| {
+            items.extend(args);
+            Value::Nil
+        }),
+        "pop" => expect_args(method, &args, 0).map(|_| items.pop().unwrap_or(Value::Nil)),
+        "insert" => expect_args(method, &args, 2)
+            .and_then(|_| expect_index(method, &args[0], items.len() + 1))
+            .map(|index| {
+                items.insert(index, args[1].clone());
+                Value::Nil
+            }),
+        "remove" => expect_args(method, &args, 1)
+            .and_then(|_| expect_index(method, &args[0], items.len()))
+            .map(|index| items.remove(index)),
+        "clear" => expect_args(method, &args, 0).map(|_| {
+            items.clear();
+            Value::Nil
+        }),
+        "contains" => expect_args(method, &args, 1).map(|_| Value::Bool(items.contains(&args[0]))),
+        "index_of" => expect_args(method, &args, 1).map(|_| {
+            items
+                .iter()
+                .position(|item| *item == args[0])
+                .map_or(Value::Nil, |index| Value::Number(index as f64))
+        }),
+        "reverse" => expect_args(method, &args, 0).map(|_| Value::Array(items.iter().rev().cloned().collect())),
+        "join" => expect_args(method, &args, 1)
+            .and_then(|_| expect_string(method, &args[0]))
+            .map(|separator| {
+                let parts: Vec<String> = items.iter().map(|item| item.to_string()).collect();
+                Value::String(parts.join(separator))
+            }),
+        _ => return None,
+    };
+    Some(result)
+}
+
+fn string_method(s: &str, method: &str, args: Vec<Value>) -> Option<Result<Value, InterpreterError>> {
+    let result = match method {
+        "len" => expect_args(method, &args, 0).map(|_| Value::Number(s.chars().count() as f64)),
+        "upper" => expect_args(method, &args, 0).map(|_| Value::String(s.to_uppercase())),
+        "lower" => expect_args(method, &args, 0).map(|_| Value::String(s.to_lowercase())),
+        "trim" => expect_args(method, &args, 0).map(|_| Value::String(s.trim().to_string())),
+        "chars" => expect_args(method, &args, 0)
+            .map(|_| Value::Array(s.chars().map(|c| Value::String(c.to_string())).collect())),
+        "split" => expect_args(method, &args, 1)
+            .and_then(|_| expect_string(method, &args[0]))
+            .map(|separator| {
+                Value::Array(s.split(separator).map(|part| Value::String(part.to_string())).collect())
+            }),
+        "contains" => expect_args(method, &args, 1)
+            .and_then(|_| expect_string(method, &args[0]))
+            .map(|needle| Value::Bool(s.contains(needle))),
+        "starts_with" => expect_args(method, &args, 1)
+            .and_then(|_| expect_string(method, &args[0]))
+            .map(|prefix| Value::Bool(s.starts_with(prefix))),
+        "ends_with" => expect_args(method, &args, 1)
+            .and_then(|_| expect_string(method, &args[0]))
+            .map(|suffix| Value::Bool(s.ends_with(suffix))),
+        "replace" => expect_args(method, &args, 2)
+            .and_then(|_| Ok((expect_string(method, &args[0])?, expect_string(method, &args[1])?)))
+            .map(|(from, to)| Value::String(s.replace(from, to))),
+        _ => return None,
+    };
+    Some(result)
+}
+
+fn dictionary_method(dict: &mut HashMap<String, Value>, method: &str, args: Vec<Value>) -> Option<Result<Value, InterpreterError>> {
+    let result = match method {
+        "len" => expect_args(method, &args, 0).map(|_| Value::Number(dict.len() as f64)),
+        // keys come back sorted so scripts get a stable order
+        "keys" => expect_args(method, &args, 0).map(|_| {
+            let mut keys: Vec<&String> = dict.keys().collect();
+            keys.sort();
+            Value::Array(keys.into_iter().map(|k| Value::String(k.clone())).collect())
+        }),
+        "values" => expect_args(method, &args, 0).map(|_| {
+            let mut entries: Vec<(&String, &Value)> = dict.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            Value::Array(entries.into_iter().map(|(_, v)| v.clone()).collect())
+        }),
+        // get() reports a missing key as an err result instead of raising a KeyError
+        "get" => expect_args(method, &args, 1)
+            .and_then(|_| expect_string(method, &args[0]))
+            .map(|key| match dict.get(key) {
+                Some(value) => Value::ok(value.clone()),
+                None => Value::err(Value::error(
+                    "KeyError",
+                    format!("Key '{}' not found in dictionary", key),
+                    Value::Nil,
+                )),
+            }),
+        "has" => expect_args(method, &args, 1)
+            .and_then(|_| expect_string(method, &args[0]))
+            .map(|key| Value::Bool(dict.contains_key(key))),
+        "remove" => expect_args(method, &args, 1)
+            .and_then(|_| expect_string(method, &args[0]))
+            .map(|key| dict.remove(key).unwrap_or(Value::Nil)),
+        "clear" => expect_args(method, &args, 0).map(|_| {
+            dict.clear();
+            Value::Nil
+        }),
+        _ => return None,
+    };
+    Some(result)
+}
+
+fn result_method(variant: &str, values: &[Value], method: &str, args: Vec<Value>) -> Option<Result<Value, InterpreterError>> {
+    let is_ok = variant == "Ok";
+    let inner = values.first().cloned().unwrap_or(Value::Nil);
+    let result = match method {
+        "is_ok" => expect_args(method, &args, 0).map(|_| Value::Bool(is_ok)),
+        "is_err" => expect_args(method, &args, 0).map(|_| Value::Bool(!is_ok)),
+        // unwrap() on an err raises its error as an exception
+        "unwrap" => expect_args(method, &args, 0).and_then(|_| match (is_ok, inner) {
+            (true, value) => Ok(value),
+            (false, error) if error.error_field("kind").is_some() => Err(InterpreterError::Throw(error)),
+            (false, error) => Err(InterpreterError::runtime("Error", error.to_string())),
+        }),
+        "unwrap_or" => expect_args(method, &args, 1).map(|_| if is_ok { inner } else { args[0].clone() }),
+        _ => return None,
+    };
+    Some(result)
+}
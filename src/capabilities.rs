@@ -0,0 +1,154 @@
+use std::fmt;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use crate::interpreter::InterpreterError;
+
+// What a script may do outside the interpreter. Everything is denied by default, so untrusted
+// scripts only get what the host grants
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Capabilities {
+    // directories whose files, and subdirectories' files, may be read
+    pub read: Vec<PathBuf>,
+    // directories under which files may be created, changed or removed
+    pub write: Vec<PathBuf>,
+    pub env: bool,
+    pub process: bool,
+    pub clock: bool,
+    pub random: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Capability {
+    FsRead,
+    FsWrite,
+    Env,
+    Process,
+    Clock,
+    Random,
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Capability::FsRead => "fs-read",
+            Capability::FsWrite => "fs-write",
+            Capability::Env => "env",
+            Capability::Process => "process",
+            Capability::Clock => "clock",
+            Capability::Random => "random",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+fn denied(action: &str, capability: Capability) -> InterpreterError {
+    InterpreterError::runtime(
+        "PermissionError",
+        format!("Permission denied: {} needs the {} capability", action, capability),
+    )
+}
+
+// The absolute form of `path` with symbolic links, `.` and `..` resolved, so a script can't leave an
+// allowed directory through them. Parts that don't exist yet, such as a file about to be created,
+// are appended as written
+fn resolve_path(path: &Path) -> io::Result<PathBuf> {
+    let path = std::env::current_dir()?.join(path);
+    let mut existing = path.as_path();
+    let mut missing = Vec::new();
+    while !existing.exists() {
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                missing.push(name);
+                existing = parent;
+            }
+            // `..` at the end of a path that doesn't exist
+            _ => return Err(io::Error::new(io::ErrorKind::NotFound, "path does not exist")),
+        }
+    }
+    let mut resolved = existing.canonicalize()?;
+    for name in missing.into_iter().rev() {
+        match Path::new(name).components().next() {
+            Some(Component::Normal(_)) => resolved.push(name),
+            _ => return Err(io::Error::new(io::ErrorKind::NotFound, "path does not exist")),
+        }
+    }
+    Ok(resolved)
+}
+
+impl Capabilities {
+    // Grants everything, for scripts the host trusts
+    pub fn all() -> Self {
+        let root = PathBuf::from(std::path::MAIN_SEPARATOR_STR);
+        Self {
+            read: vec![root.clone()],
+            write: vec![root],
+            env: true,
+            process: true,
+            clock: true,
+            random: true,
+        }
+    }
+
+    // Fails with a PermissionError unless `capability` was granted. `action` says what the script
+    // tried to do, e.g. "env()"
+    pub fn check(&self, capability: Capability, action: &str) -> Result<(), InterpreterError> {
+        let granted = match capability {
+            Capability::FsRead => !self.read.is_empty(),
+            Capability::FsWrite => !self.write.is_empty(),
+            Capability::Env => self.env,
+            Capability::Process => self.process,
+            Capability::Clock => self.clock,
+            Capability::Random => self.random,
+        };
+        match granted {
+            true => Ok(()),
+            false => Err(denied(action, capability)),
+        }
+    }
+
+    // The resolved path to read, if it lies within one of the readable directories
+    pub fn check_read(&self, path: &Path) -> Result<PathBuf, InterpreterError> {
+        Self::check_path(&self.read, path, Capability::FsRead)
+    }
+
+    // The resolved path to write, if it lies within one of the writable directories
+    pub fn check_write(&self, path: &Path) -> Result<PathBuf, InterpreterError> {
+        Self::check_path(&self.write, path, Capability::FsWrite)
+    }
+
+    // The path to remove, if it lies within one of the writable directories. Only its directory is
+    // resolved, so removing a symbolic link removes the link rather than the file it points to
+    pub fn check_remove(&self, path: &Path) -> Result<PathBuf, InterpreterError> {
+        let resolved = match (path.parent(), path.file_name()) {
+            (Some(parent), Some(name)) => resolve_path(parent).map(|parent| parent.join(name)),
+            // `..` or a root, which can't name a file
+            _ => Err(io::Error::new(io::ErrorKind::NotFound, "path does not exist")),
+        };
+        Self::check_resolved(&self.write, path, resolved, Capability::FsWrite)
+    }
+
+    fn check_path(roots: &[PathBuf], path: &Path, capability: Capability) -> Result<PathBuf, InterpreterError> {
+        Self::check_resolved(roots, path, resolve_path(path), capability)
+    }
+
+    fn check_resolved(
+        roots: &[PathBuf],
+        path: &Path,
+        resolved: io::Result<PathBuf>,
+        capability: Capability,
+    ) -> Result<PathBuf, InterpreterError> {
+        let action = match capability {
+            Capability::FsWrite => format!("writing '{}'", path.display()),
+            _ => format!("reading '{}'", path.display()),
+        };
+        let resolved = resolved.map_err(|_| denied(&action, capability))?;
+        let allowed = roots
+            .iter()
+            .filter_map(|root| resolve_path(root).ok())
+            .any(|root| resolved.starts_with(root));
+        match allowed {
+            true => Ok(resolved),
+            false => Err(denied(&action, capability)),
+        }
+    }
+}
@@ -0,0 +1,613 @@
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use crate::ast::{AstNode, FormatSpec, InterpolationPart, Pattern};
+use crate::builtins;
+use crate::token::Token;
+use crate::value::Value;
+
+// One bytecode instruction. Operands index the chunk's constant pool, a frame's local slots,
+// the program's global slots or absolute positions in the code
+#[derive(Debug, Clone, Copy)]
+pub enum Op {
+    Constant(usize),
+    Nil,
+    Pop,
+    // discards values left on the stack, e.g. when `break` leaves an expression early
+    PopN(usize),
+    Dup,
+    GetLocal(usize),
+    SetLocal(usize),
+    GetGlobal(usize),
+    DefineGlobal(usize),
+    SetGlobal(usize),
+    // arr[i] on a variable, without copying the container onto the stack
+    IndexLocal(usize),
+    IndexGlobal(usize),
+    // arr[i] = value on a variable, changing the container in place
+    SetIndexLocal(usize),
+    SetIndexGlobal(usize),
+    Index,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    Equal,
+    Less,
+    Greater,
+    And,
+    Or,
+    Negate,
+    Array(usize),
+    // pops `n` key/value pairs
+    Dictionary(usize),
+    // formats the top of the stack with the chunk's format spec at this index
+    Format(usize),
+    Concat(usize),
+    Print,
+    Jump(usize),
+    JumpIfFalse(usize),
+    Call(usize),
+    // built-in method call; the method name is a constant
+    Invoke { method: usize, argc: usize },
+    // method call on a variable, so methods such as push() change it in place
+    InvokeLocal { slot: usize, method: usize, argc: usize },
+    InvokeGlobal { slot: usize, method: usize, argc: usize },
+    Return,
+    // `return f(args)`: the call replaces the returning function's frame
+    TailCall(usize),
+}
+
+impl Op {
+    // How many values the instruction adds to (or removes from) the stack
+    fn stack_effect(&self) -> isize {
+        match *self {
+            Op::Constant(_) | Op::Nil | Op::Dup | Op::GetLocal(_) | Op::GetGlobal(_) => 1,
+            Op::SetLocal(_) | Op::DefineGlobal(_) | Op::SetGlobal(_) => 0,
+            Op::IndexLocal(_) | Op::IndexGlobal(_) | Op::Negate | Op::Format(_) | Op::Print | Op::Jump(_) => 0,
+            Op::Pop | Op::JumpIfFalse(_) | Op::Return | Op::Index => -1,
+            Op::SetIndexLocal(_) | Op::SetIndexGlobal(_) => -1,
+            Op::Add | Op::Subtract | Op::Multiply | Op::Divide | Op::Modulo => -1,
+            Op::Equal | Op::Less | Op::Greater | Op::And | Op::Or => -1,
+            Op::PopN(n) => -(n as isize),
+            Op::Array(n) | Op::Concat(n) => 1 - n as isize,
+            Op::Dictionary(n) => 1 - 2 * n as isize,
+            Op::Call(argc) | Op::Invoke { argc, .. } => -(argc as isize),
+            Op::TailCall(argc) => -(argc as isize) - 1,
+            Op::InvokeLocal { argc, .. } | Op::InvokeGlobal { argc, .. } => 1 - argc as isize,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Chunk {
+    pub code: Vec<Op>,
+    pub constants: Vec<Value>,
+    pub formats: Vec<FormatSpec>,
+}
+
+#[derive(Debug)]
+pub struct Function {
+    pub name: String,
+    pub arity: usize,
+    // parameters first, then every other local of the function
+    pub locals: usize,
+    pub chunk: Chunk,
+}
+
+// A compiled script: the top-level code, every function it declares, and the names of
+// its global slots
+#[derive(Debug)]
+pub struct Program {
+    pub main: Rc<Function>,
+    pub functions: Vec<Rc<Function>>,
+    pub globals: Vec<String>,
+}
+
+struct Loop {
+    // stack depth when the loop started; `break` pops back to it
+    depth: usize,
+    breaks: Vec<usize>,
+}
+
+// Compilation state of the function being compiled
+struct FunctionState {
+    name: String,
+    chunk: Chunk,
+    // None for the top level, where every variable is global
+    locals: Option<HashMap<String, usize>>,
+    local_count: usize,
+    depth: usize,
+    loops: Vec<Loop>,
+}
+
+enum Variable {
+    Local(usize),
+    Global(usize),
+}
+
+// Compiles a program into bytecode for the `Vm`. Variables are resolved lexically:
+// a function sees its own locals and the globals, and names assigned at the top level
+// are globals everywhere
+pub struct Compiler {
+    globals: HashMap<String, usize>,
+    global_names: Vec<String>,
+    top_level_names: HashSet<String>,
+    functions: Vec<Rc<Function>>,
+    states: Vec<FunctionState>,
+}
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn unsupported(what: &str) -> String {
+    format!("{} not supported by the bytecode VM", what)
+}
+
+impl Compiler {
+    pub fn new() -> Self {
+        Self {
+            globals: HashMap::new(),
+            global_names: Vec::new(),
+            top_level_names: HashSet::new(),
+            functions: Vec::new(),
+            states: Vec::new(),
+        }
+    }
+
+    pub fn compile_program(mut self, program: &AstNode) -> Result<Program, String> {
+        let statements = match program {
+            AstNode::Program(statements) => statements.as_slice(),
+            other => std::slice::from_ref(other),
+        };
+        for statement in statements {
+            match statement {
+                AstNode::VariableDeclaration { name, .. } | AstNode::FunctionDeclaration { name, .. } => {
+                    self.top_level_names.insert(name.clone());
+                }
+                AstNode::Assignment { target, .. } => {
+                    if let AstNode::Identifier(name) = &**target {
+                        self.top_level_names.insert(name.clone());
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        self.states.push(FunctionState {
+            name: "<script>".to_string(),
+            chunk: Chunk::default(),
+            locals: None,
+            local_count: 0,
+            depth: 0,
+            loops: Vec::new(),
+        });
+        self.compile_sequence(statements)?;
+        self.emit(Op::Return);
+        let main = self.finish_function(0);
+
+        Ok(Program {
+            main: Rc::new(main),
+            functions: self.functions,
+            globals: self.global_names,
+        })
+    }
+
+    fn state(&mut self) -> &mut FunctionState {
+        self.states.last_mut().expect("compiler always has a function")
+    }
+
+    fn emit(&mut self, op: Op) -> usize {
+        let state = self.state();
+        state.depth = (state.depth as isize + op.stack_effect()) as usize;
+        state.chunk.code.push(op);
+        state.chunk.code.len() - 1
+    }
+
+    fn add_constant(&mut self, value: Value) -> usize {
+        let constants = &mut self.state().chunk.constants;
+        constants.push(value);
+        constants.len() - 1
+    }
+
+    fn here(&mut self) -> usize {
+        self.state().chunk.code.len()
+    }
+
+    fn patch_jump(&mut self, at: usize) {
+        let target = self.here();
+        match &mut self.state().chunk.code[at] {
+            Op::Jump(to) | Op::JumpIfFalse(to) => *to = target,
+            _ => unreachable!("only jumps are patched"),
+        }
+    }
+
+    fn global_slot(&mut self, name: &str) -> usize {
+        if let Some(slot) = self.globals.get(name) {
+            return *slot;
+        }
+        self.global_names.push(name.to_string());
+        self.globals.insert(name.to_string(), self.global_names.len() - 1);
+        self.global_names.len() - 1
+    }
+
+    fn declare_local(&mut self, name: &str) -> Option<usize> {
+        let state = self.state();
+        let locals = state.locals.as_mut()?;
+        if let Some(slot) = locals.get(name) {
+            return Some(*slot);
+        }
+        locals.insert(name.to_string(), state.local_count);
+        state.local_count += 1;
+        Some(state.local_count - 1)
+    }
+
+    fn resolve(&mut self, name: &str) -> Variable {
+        if let Some(slot) = self.state().locals.as_ref().and_then(|locals| locals.get(name)) {
+            return Variable::Local(*slot);
+        }
+        Variable::Global(self.global_slot(name))
+    }
+
+    // Like the tree-walker, assigning to an unknown name inside a function creates a local
+    // unless the top level assigns that name too
+    fn resolve_assignment(&mut self, name: &str) -> Variable {
+        match self.resolve(name) {
+            Variable::Global(_) if !self.top_level_names.contains(name) => match self.declare_local(name) {
+                Some(slot) => Variable::Local(slot),
+                None => Variable::Global(self.global_slot(name)),
+            },
+            variable => variable,
+        }
+    }
+
+    fn finish_function(&mut self, arity: usize) -> Function {
+        let state = self.states.pop().expect("compiler always has a function");
+        Function {
+            name: state.name,
+            arity,
+            locals: state.local_count,
+            chunk: state.chunk,
+        }
+    }
+
+    // Statements leave one value each; all but the last are discarded
+    fn compile_sequence(&mut self, statements: &[AstNode]) -> Result<(), String> {
+        if statements.is_empty() {
+            self.compile_constant(Value::Number(0.0));
+            return Ok(());
+        }
+        for (i, statement) in statements.iter().enumerate() {
+            self.compile(statement)?;
+            if i + 1 < statements.len() {
+                self.emit(Op::Pop);
+            }
+        }
+        Ok(())
+    }
+
+    // Emits code that leaves the node's value on the stack, matching what
+    // `Interpreter::evaluate` returns for it
+    fn compile(&mut self, node: &AstNode) -> Result<(), String> {
+        match node {
+            AstNode::Program(statements) | AstNode::Block(statements) => self.compile_sequence(statements)?,
+            AstNode::NumberLiteral(n) => self.compile_constant(Value::Number(*n)),
+            AstNode::StringLiteral(s) => self.compile_constant(Value::String(s.clone())),
+            AstNode::Bool(b) => self.compile_constant(Value::Bool(*b)),
+            AstNode::Nil => {
+                self.emit(Op::Nil);
+            }
+            AstNode::InterpolatedString(parts) => {
+                for part in parts {
+                    match part {
+                        InterpolationPart::Literal(text) => self.compile_constant(Value::String(text.clone())),
+                        InterpolationPart::Expression { expr, format } => {
+                            self.compile(expr)?;
+                            let formats = &mut self.state().chunk.formats;
+                            formats.push(format.clone());
+                            let index = formats.len() - 1;
+                            self.emit(Op::Format(index));
+                        }
+                    }
+                }
+                self.emit(Op::Concat(parts.len()));
+            }
+            AstNode::Identifier(name) | AstNode::Variable { name, .. } => match self.resolve(name) {
+                Variable::Local(slot) => {
+                    self.emit(Op::GetLocal(slot));
+                }
+                Variable::Global(slot) => {
+                    self.emit(Op::GetGlobal(slot));
+                }
+            },
+            AstNode::ArrayLiteral(elements) => {
+                for element in elements {
+                    self.compile(element)?;
+                }
+                self.emit(Op::Array(elements.len()));
+            }
+            AstNode::DictionaryLiteral(pairs) => {
+                for (key, value) in pairs {
+                    self.compile_constant(Value::String(key.clone()));
+                    self.compile(value)?;
+                }
+                self.emit(Op::Dictionary(pairs.len()));
+            }
+            AstNode::IndexExpression(object, index, _) => match &**object {
+                AstNode::Identifier(name) => {
+                    let variable = self.resolve(name);
+                    self.compile(index)?;
+                    match variable {
+                        Variable::Local(slot) => self.emit(Op::IndexLocal(slot)),
+                        Variable::Global(slot) => self.emit(Op::IndexGlobal(slot)),
+                    };
+                }
+                object => {
+                    self.compile(object)?;
+                    self.compile(index)?;
+                    self.emit(Op::Index);
+                }
+            },
+            AstNode::BinaryExpression { left, operator, right, .. } => {
+                self.compile(left)?;
+                self.compile(right)?;
+                let op = match operator {
+                    Token::Plus => Op::Add,
+                    Token::Minus => Op::Subtract,
+                    Token::Multiply => Op::Multiply,
+                    Token::Divide => Op::Divide,
+                    Token::Modulo => Op::Modulo,
+                    Token::Equal => Op::Equal,
+                    Token::LessThan => Op::Less,
+                    Token::GreaterThan => Op::Greater,
+                    Token::And => Op::And,
+                    Token::Or => Op::Or,
+                    other => return Err(unsupported(&format!("Operator {:?} is", other))),
+                };
+                self.emit(op);
+            }
+            AstNode::UnaryExpression { operator: Token::Minus, operand, .. } => {
+                self.compile(operand)?;
+                self.emit(Op::Negate);
+            }
+            AstNode::UnaryExpression { operator, .. } => {
+                return Err(unsupported(&format!("Unary operator {:?} is", operator)));
+            }
+            AstNode::PrintStatement(expr) => {
+                self.compile(expr)?;
+                self.emit(Op::Print);
+            }
+            AstNode::VariableDeclaration { name, value, .. } => {
+                self.compile(value)?;
+                match self.declare_local(name) {
+                    Some(slot) => self.emit(Op::SetLocal(slot)),
+                    None => {
+                        let slot = self.global_slot(name);
+                        self.emit(Op::DefineGlobal(slot))
+                    }
+                };
+            }
+            AstNode::Assignment { target, value } => self.compile_assignment(target, value, None)?,
+            AstNode::CompoundAssignment { target, operator, value } => {
+                self.compile_assignment(target, value, Some(operator))?
+            }
+            AstNode::IfStatement { condition, consequence, alternative } => {
+                self.compile(condition)?;
+                let to_else = self.emit(Op::JumpIfFalse(0));
+                let depth = self.state().depth;
+                self.compile(consequence)?;
+                let to_end = self.emit(Op::Jump(0));
+                self.patch_jump(to_else);
+                self.state().depth = depth;
+                match alternative {
+                    Some(alternative) => self.compile(alternative)?,
+                    None => {
+                        self.emit(Op::Nil);
+                    }
+                }
+                self.patch_jump(to_end);
+            }
+            AstNode::WhileStatement { condition, body } => {
+                let start = self.here();
+                let depth = self.state().depth;
+                self.compile(condition)?;
+                let to_exit = self.emit(Op::JumpIfFalse(0));
+                self.state().loops.push(Loop { depth, breaks: Vec::new() });
+                self.compile(body)?;
+                self.emit(Op::Pop);
+                self.emit(Op::Jump(start));
+                let finished = self.state().loops.pop().expect("loop was pushed");
+                self.patch_jump(to_exit);
+                for at in finished.breaks {
+                    self.patch_jump(at);
+                }
+                self.compile_constant(Value::Number(0.0));
+            }
+            AstNode::Break => {
+                let state = self.state();
+                let before = state.depth;
+                let depth = match state.loops.last() {
+                    Some(current) => current.depth,
+                    None => return Err("Break statement outside of loop".to_string()),
+                };
+                if before > depth {
+                    self.emit(Op::PopN(before - depth));
+                }
+                let at = self.emit(Op::Jump(0));
+                self.state().loops.last_mut().expect("inside a loop").breaks.push(at);
+                // code after `break` is unreachable; count it as leaving a value like any other statement
+                self.state().depth = before + 1;
+            }
+            AstNode::Return(value) => {
+                if self.state().locals.is_none() {
+                    return Err("Return statement outside of function".to_string());
+                }
+                let depth = self.state().depth;
+                match value.as_deref() {
+                    Some(AstNode::FunctionCall { function, arguments, .. }) => {
+                        self.compile(function)?;
+                        for argument in arguments {
+                            self.compile(argument)?;
+                        }
+                        self.emit(Op::TailCall(arguments.len()));
+                    }
+                    Some(value) => {
+                        self.compile(value)?;
+                        self.emit(Op::Return);
+                    }
+                    None => {
+                        self.emit(Op::Nil);
+                        self.emit(Op::Return);
+                    }
+                }
+                self.state().depth = depth + 1;
+            }
+            AstNode::FunctionDeclaration { name, params, body } => {
+                let index = self.compile_function(name, params, body)?;
+                self.compile_constant(Value::CompiledFunction { name: name.clone(), index });
+                match self.declare_local(name) {
+                    Some(slot) => self.emit(Op::SetLocal(slot)),
+                    None => {
+                        let slot = self.global_slot(name);
+                        self.emit(Op::DefineGlobal(slot))
+                    }
+                };
+            }
+            AstNode::FunctionCall { function, arguments, .. } => {
+                self.compile(function)?;
+                for argument in arguments {
+                    self.compile(argument)?;
+                }
+                self.emit(Op::Call(arguments.len()));
+            }
+            AstNode::MethodCall { object, method, arguments, .. } => {
+                let receiver = match &**object {
+                    AstNode::Identifier(name) => Some(self.resolve(name)),
+                    // the changed receiver could not be written back
+                    _ if builtins::mutates(method) => {
+                        return Err(unsupported(&format!("Calling '{}' on anything but a variable is", method)));
+                    }
+                    object => {
+                        self.compile(object)?;
+                        None
+                    }
+                };
+                for argument in arguments {
+                    self.compile(argument)?;
+                }
+                let method = self.add_constant(Value::String(method.clone()));
+                let argc = arguments.len();
+                self.emit(match receiver {
+                    Some(Variable::Local(slot)) => Op::InvokeLocal { slot, method, argc },
+                    Some(Variable::Global(slot)) => Op::InvokeGlobal { slot, method, argc },
+                    None => Op::Invoke { method, argc },
+                });
+            }
+            AstNode::DestructuringDeclaration { .. } | AstNode::DestructuringAssignment { .. } => {
+                return Err(unsupported("Destructuring is"));
+            }
+            AstNode::Match { .. } | AstNode::IsPattern { .. } => return Err(unsupported("Pattern matching is")),
+            AstNode::StructDeclaration { .. }
+            | AstNode::StructLiteral { .. }
+            | AstNode::FieldAccess { .. }
+            | AstNode::ImplBlock { .. } => return Err(unsupported("Structs are")),
+            AstNode::EnumDeclaration { .. } => return Err(unsupported("Enums are")),
+            AstNode::Throw(..) | AstNode::TryStatement { .. } => return Err(unsupported("Exceptions are")),
+            AstNode::Propagate(_) => return Err(unsupported("The '?' operator is")),
+        }
+        Ok(())
+    }
+
+    fn compile_constant(&mut self, value: Value) {
+        let index = self.add_constant(value);
+        self.emit(Op::Constant(index));
+    }
+
+    fn compile_assignment(&mut self, target: &AstNode, value: &AstNode, operator: Option<&Token>) -> Result<(), String> {
+        let binary = |operator: &Token| match operator {
+            Token::Plus => Ok(Op::Add),
+            Token::Minus => Ok(Op::Subtract),
+            Token::Multiply => Ok(Op::Multiply),
+            Token::Divide => Ok(Op::Divide),
+            Token::Modulo => Ok(Op::Modulo),
+            other => Err(unsupported(&format!("Compound operator {:?} is", other))),
+        };
+
+        match target {
+            AstNode::Identifier(name) => {
+                let variable = self.resolve_assignment(name);
+                if let Some(operator) = operator {
+                    match variable {
+                        Variable::Local(slot) => self.emit(Op::GetLocal(slot)),
+                        Variable::Global(slot) => self.emit(Op::GetGlobal(slot)),
+                    };
+                    self.compile(value)?;
+                    self.emit(binary(operator)?);
+                } else {
+                    self.compile(value)?;
+                }
+                match variable {
+                    Variable::Local(slot) => self.emit(Op::SetLocal(slot)),
+                    Variable::Global(slot) => self.emit(Op::SetGlobal(slot)),
+                };
+            }
+            AstNode::IndexExpression(object, index, _) => {
+                let name = match &**object {
+                    AstNode::Identifier(name) => name,
+                    _ => return Err(unsupported("Assigning to nested elements is")),
+                };
+                let variable = self.resolve(name);
+                self.compile(index)?;
+                if let Some(operator) = operator {
+                    self.emit(Op::Dup);
+                    match variable {
+                        Variable::Local(slot) => self.emit(Op::IndexLocal(slot)),
+                        Variable::Global(slot) => self.emit(Op::IndexGlobal(slot)),
+                    };
+                    self.compile(value)?;
+                    self.emit(binary(operator)?);
+                } else {
+                    self.compile(value)?;
+                }
+                match variable {
+                    Variable::Local(slot) => self.emit(Op::SetIndexLocal(slot)),
+                    Variable::Global(slot) => self.emit(Op::SetIndexGlobal(slot)),
+                };
+            }
+            AstNode::FieldAccess { .. } => return Err(unsupported("Structs are")),
+            _ => return Err("Invalid assignment target".to_string()),
+        }
+        Ok(())
+    }
+
+    fn compile_function(&mut self, name: &str, params: &[Pattern], body: &AstNode) -> Result<usize, String> {
+        let mut locals = HashMap::new();
+        for (slot, param) in params.iter().enumerate() {
+            match param {
+                Pattern::Identifier(param) => {
+                    locals.insert(param.clone(), slot);
+                }
+                _ => return Err(unsupported("Destructuring parameters are")),
+            }
+        }
+
+        self.states.push(FunctionState {
+            name: name.to_string(),
+            chunk: Chunk::default(),
+            locals: Some(locals),
+            local_count: params.len(),
+            depth: 0,
+            loops: Vec::new(),
+        });
+        let compiled = self.compile(body);
+        self.emit(Op::Return);
+        let function = self.finish_function(params.len());
+        compiled?;
+
+        self.functions.push(Rc::new(function));
+        Ok(self.functions.len() - 1)
+    }
+}
@@ -1,11 +1,137 @@
 use std::collections::HashMap;
-use crate::ast::AstNode;
-use crate::token::Token;
+use std::fmt;
+use std::io::{self, BufRead, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use crate::ast::{AstNode, Binding, InterpolationPart, MatchArm, Pattern};
+use crate::resolver::{GlobalScope, ResolveOutput, Resolver};
+use crate::token::{Span, Token};
+use crate::builtins;
+use crate::capabilities::Capabilities;
+use crate::limits::{self, Budget, LimitError, Limits};
+use crate::output::{CapturedOutput, Output, Sink};
 use crate::value::Value;
 
 pub struct Interpreter {
-    pub variables: HashMap<String, Value>,
-    scopes: Vec<HashMap<String, Value>>,
+    // global variables by slot, None until first assigned
+    globals: Vec<Option<Value>>,
+    global_scope: GlobalScope,
+    // local slots of each running function; the first frame holds the top level's match bindings
+    frames: Vec<Vec<Option<Value>>>,
+    strict: bool,
+    optimize: bool,
+    // methods from `impl` blocks, keyed by type name then method name
+    methods: HashMap<String, HashMap<String, Value>>,
+    // the functions currently being executed, outermost first
+    call_stack: Vec<CallFrame>,
+    // where the call being made is written, recorded on the frame it pushes, or where the
+    // error being raised is written; None when the statement running has not said
+    position: Option<Span>,
+    max_call_depth: usize,
+    // address of the native stack where the running program started, 0 when none is running
+    stack_base: usize,
+    // how much native stack the running program may use below stack_base
+    native_stack_limit: usize,
+    // try statements entered by the running function; tail calls are disabled inside them
+    try_depth: usize,
+    // the calls that were running when the exception now propagating was raised
+    traceback: Option<Traceback>,
+    budget: Budget,
+    interrupt: InterruptHandle,
+    // where print statements and warnings are written
+    stdout: Sink,
+    stderr: Sink,
+    // buffers behind stdout and stderr when they are captured
+    captured: Option<(CapturedOutput, CapturedOutput)>,
+    // where the stdin builtins read from; None reads the process's stdin
+    stdin: Option<Source>,
+    // what builtins may do outside the interpreter
+    capabilities: Capabilities,
+    // xorshift state for random(), seeded on first use
+    random_state: u64,
+}
+
+// Input for input(), read_line() and the other stdin builtins
+pub type Source = Box<dyn BufRead + Send>;
+
+// Stops a running program from another thread or a signal handler. The program stops at its
+// next loop iteration or function call with an Interrupted error
+#[derive(Debug, Clone, Default)]
+pub struct InterruptHandle(Arc<AtomicBool>);
+
+impl InterruptHandle {
+    pub fn interrupt(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    // Clears an interrupt that no program has stopped for yet, returning whether there was one
+    pub fn reset(&self) -> bool {
+        // a plain load first, so the check on every loop iteration stays cheap
+        self.0.load(Ordering::Relaxed) && self.0.swap(false, Ordering::Relaxed)
+    }
+}
+
+// A running function and where it was called from
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallFrame {
+    pub name: String,
+    pub call_site: Span,
+}
+
+// The call stack at the point an uncaught exception was raised, outermost call first
+#[derive(Debug, Clone, PartialEq)]
+pub struct Traceback {
+    pub frames: Vec<CallFrame>,
+    // where the exception was raised in the innermost function, if known
+    pub position: Option<Span>,
+}
+
+// Identical consecutive lines beyond this many are summarised, e.g. in runaway recursion
+const TRACEBACK_REPEAT_LIMIT: usize = 3;
+
+impl fmt::Display for Traceback {
+    // Each line names a running function and where it made the next call; the last line
+    // is the function the exception was raised in and where
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Traceback (most recent call last):")?;
+        let callers = std::iter::once("<main>").chain(self.frames.iter().map(|frame| frame.name.as_str()));
+        let positions = self.frames.iter().map(|frame| Some(frame.call_site)).chain(std::iter::once(self.position));
+        let lines: Vec<String> = callers
+            .zip(positions)
+            .map(|(caller, span)| match span {
+                Some(span) => format!("line {}, column {}, in {}", span.line, span.column, caller),
+                None => format!("in {}", caller),
+            })
+            .collect();
+
+        let mut index = 0;
+        while index < lines.len() {
+            let repeats = lines[index..].iter().take_while(|line| **line == lines[index]).count();
+            for line in &lines[index..index + repeats.min(TRACEBACK_REPEAT_LIMIT)] {
+                writeln!(f, "  {}", line)?;
+            }
+            if repeats > TRACEBACK_REPEAT_LIMIT {
+                writeln!(f, "  [Previous line repeated {} more times]", repeats - TRACEBACK_REPEAT_LIMIT)?;
+            }
+            index += repeats;
+        }
+        Ok(())
+    }
+}
+
+// How many nested calls a script may make before a StackOverflowError is raised
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 10_000;
+
+// Native stack assumed to be available, the size std gives threads it spawns
+const DEFAULT_NATIVE_STACK_SIZE: usize = 2 * 1024 * 1024;
+
+// Native stack left unused for builtins and the frames between two checks
+const NATIVE_STACK_RESERVE: usize = 256 * 1024;
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 #[derive(Debug)]
@@ -13,281 +139,1321 @@ pub enum InterpreterError {
     Break,
     Return(Value),
     Error(String),
+    // an exception raised by `throw` or a built-in runtime error; always an `Error` value
+    Throw(Value),
+    // `return f(args)`: the function and arguments the returning call is replaced with
+    TailCall(Box<(Value, Vec<Value>)>),
+    // a resource limit was reached; try statements don't catch these
+    Limit(LimitError),
+    // the host stopped the program through an InterruptHandle; not catchable either
+    Interrupted,
+}
+
+impl From<LimitError> for InterpreterError {
+    fn from(error: LimitError) -> Self {
+        InterpreterError::Limit(error)
+    }
+}
+
+impl InterpreterError {
+    // A built-in runtime error of a specific kind, e.g. a KeyError. Its stack is filled
+    // in once it reaches the function it was raised in
+    pub fn runtime(kind: &str, message: String) -> InterpreterError {
+        InterpreterError::Throw(Value::error(kind, message, Value::Nil))
+    }
+}
+
+impl fmt::Display for InterpreterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InterpreterError::Break => write!(f, "Break statement outside of loop"),
+            InterpreterError::Return(val) => write!(f, "Return statement outside of function: {:?}", val),
+            InterpreterError::TailCall(call) => write!(f, "Tail call outside of function: {}", call.0),
+            InterpreterError::Error(msg) => write!(f, "{}", msg),
+            InterpreterError::Limit(error) => write!(f, "{}", error),
+            InterpreterError::Interrupted => write!(f, "Interrupted"),
+            InterpreterError::Throw(value) => match (value.error_field("kind"), value.error_field("message")) {
+                (Some(kind), Some(message)) => write!(f, "{}: {}", kind, message),
+                _ => write!(f, "Uncaught exception: {}", value),
+            },
+        }
+    }
+}
+
+// The variable at the root of an assignment target
+type Place<'a> = (&'a str, Binding);
+
+// One step from a variable to the element being assigned: `[index]` or `.field`
+enum PlaceKey {
+    Index(Value),
+    Field(String),
+}
+
+// Reads container[index]; shared with the bytecode VM
+pub(crate) fn index_value(container: &Value, index: &Value) -> Result<Value, InterpreterError> {
+    match (container, index) {
+        (Value::Array(arr), Value::Number(idx)) => {
+            if *idx < 0.0 || *idx as usize >= arr.len() {
+                // changing this to now return nil instead of an error so that we can handle writing
+                // other functions such as get length
+                return Ok(Value::Nil);
+            }
+            Ok(arr[*idx as usize].clone())
+        }
+        (Value::Dictionary(dict), Value::String(key)) => dict
+            .get(key)
+            .cloned()
+            .ok_or_else(|| InterpreterError::runtime("KeyError", format!("Key '{}' not found in dictionary", key))),
+        _ => Err(InterpreterError::Error("Indexing error: expected array or dictionary with correct key type".to_string())),
+    }
+}
+
+// Both engines raise this when a function is called with the wrong number of arguments
+pub(crate) fn arity_error(name: &str, expected: usize, given: usize) -> InterpreterError {
+    InterpreterError::runtime(
+        "TypeError",
+        format!("{}() takes {} argument(s) but {} were given", name, expected, given),
+    )
 }
 
 impl Interpreter {
     pub fn new() -> Self {
-        Self {
-            variables: HashMap::new(),
-            scopes : vec! [HashMap::new()],
+        let mut interpreter = Self {
+            globals: Vec::new(),
+            global_scope: GlobalScope::default(),
+            frames: vec![Vec::new()],
+            strict: false,
+            optimize: false,
+            methods: HashMap::new(),
+            call_stack: Vec::new(),
+            position: None,
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            stack_base: 0,
+            native_stack_limit: DEFAULT_NATIVE_STACK_SIZE - NATIVE_STACK_RESERVE,
+            try_depth: 0,
+            traceback: None,
+            budget: Budget::default(),
+            interrupt: InterruptHandle::default(),
+            stdout: Box::new(io::stdout()),
+            stderr: Box::new(io::stderr()),
+            captured: None,
+            stdin: None,
+            capabilities: Capabilities::default(),
+            random_state: 0,
+        };
+        for (name, func) in builtins::all() {
+            interpreter.set_global(name, Value::NativeFunction { name: name.to_string(), func });
+        }
+        interpreter.set_global(
+            "Result",
+            Value::EnumType {
+                name: "Result".to_string(),
+                variants: vec![("Ok".to_string(), 1), ("Err".to_string(), 1)],
+            },
+        );
+        interpreter
+    }
+
+    // An interpreter whose output is kept for take_output() instead of being printed
+    pub fn with_captured_output() -> Self {
+        let mut interpreter = Self::new();
+        let (stdout, stderr) = (CapturedOutput::default(), CapturedOutput::default());
+        interpreter.set_stdout(Box::new(stdout.clone()));
+        interpreter.set_stderr(Box::new(stderr.clone()));
+        interpreter.captured = Some((stdout, stderr));
+        interpreter
+    }
+
+    // Where print statements write
+    pub fn set_stdout(&mut self, sink: Sink) {
+        self.stdout = sink;
+    }
+
+    // Where warnings are written
+    pub fn set_stderr(&mut self, sink: Sink) {
+        self.stderr = sink;
+    }
+
+    pub(crate) fn stderr(&mut self) -> &mut Sink {
+        &mut self.stderr
+    }
+
+    // Where the stdin builtins read from instead of the process's stdin
+    pub fn set_stdin(&mut self, source: Source) {
+        self.stdin = Some(source);
+    }
+
+    // The next line of input without its line ending, or None at the end of the input
+    pub(crate) fn read_input_line(&mut self) -> io::Result<Option<String>> {
+        let mut line = String::new();
+        let read = match &mut self.stdin {
+            Some(source) => source.read_line(&mut line)?,
+            None => io::stdin().read_line(&mut line)?,
+        };
+        if read == 0 {
+            return Ok(None);
+        }
+        if line.ends_with('\n') {
+            line.pop();
+            if line.ends_with('\r') {
+                line.pop();
+            }
+        }
+        Ok(Some(line))
+    }
+
+    // The rest of the input, or None if there is none left
+    pub(crate) fn read_all_input(&mut self) -> io::Result<Option<String>> {
+        let mut text = String::new();
+        match &mut self.stdin {
+            Some(source) => source.read_to_string(&mut text)?,
+            None => io::stdin().read_to_string(&mut text)?,
+        };
+        Ok(Some(text).filter(|text| !text.is_empty()))
+    }
+
+    // Shows a prompt on the same line the user types on
+    pub(crate) fn write_prompt(&mut self, prompt: &str) -> Result<(), InterpreterError> {
+        self.budget.write_output(prompt.len())?;
+        write!(self.stdout, "{}", prompt)
+            .and_then(|_| self.stdout.flush())
+            .map_err(|e| InterpreterError::Error(format!("Cannot write output: {}", e)))
+    }
+
+    // The output captured since the last call, if the interpreter was made with_captured_output()
+    pub fn take_output(&mut self) -> Option<Output> {
+        self.captured.as_ref().map(|(stdout, stderr)| Output { stdout: stdout.take(), stderr: stderr.take() })
+    }
+
+    // A handle for stopping this interpreter's running program, e.g. from another thread
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.interrupt.clone()
+    }
+
+    fn check_interrupt(&self) -> Result<(), InterpreterError> {
+        match self.interrupt.reset() {
+            true => Err(InterpreterError::Interrupted),
+            false => Ok(()),
+        }
+    }
+
+    // What scripts may access outside the interpreter; nothing unless granted here
+    pub fn set_capabilities(&mut self, capabilities: Capabilities) {
+        self.capabilities = capabilities;
+    }
+
+    pub(crate) fn capabilities(&self) -> &Capabilities {
+        &self.capabilities
+    }
+
+    // A pseudo-random number in [0, 1)
+    pub(crate) fn next_random(&mut self) -> f64 {
+        if self.random_state == 0 {
+            let nanos = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_nanos() as u64);
+            // the state must never be zero
+            self.random_state = nanos | 1;
+        }
+        let mut x = self.random_state;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.random_state = x;
+        // the top 53 bits fill an f64's mantissa
+        (x >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    // Limits applied to each program run from now on
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.budget = Budget::new(limits);
+    }
+
+    // In strict mode assigning to a variable that was never declared is an error
+    // instead of implicitly declaring it
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    // Deeper calls raise a catchable StackOverflowError. Every call of the tree-walker also uses
+    // native stack, so a large depth needs a thread with a large stack
+    pub fn set_max_call_depth(&mut self, depth: usize) {
+        self.max_call_depth = depth;
+    }
+
+    // The stack size of the thread programs run on. A program that nests deeper than this stack
+    // allows raises a StackOverflowError before the maximum call depth is reached
+    pub fn set_native_stack_size(&mut self, bytes: usize) {
+        self.native_stack_limit = bytes.saturating_sub(NATIVE_STACK_RESERVE);
+    }
+
+    // Fails once the running program has used up its native stack. Stacks grow downwards on
+    // every platform Rust supports, so the distance from where the program started is its usage
+    #[inline]
+    fn check_native_stack(&self) -> Result<(), InterpreterError> {
+        let marker = 0u8;
+        let here = &marker as *const u8 as usize;
+        if self.stack_base.saturating_sub(here) > self.native_stack_limit {
+            return Err(InterpreterError::runtime(
+                "StackOverflowError",
+                "Stack overflow: nesting too deep for the native stack".to_string(),
+            ));
         }
+        Ok(())
+    }
+
+    // Runs the optimizer over programs before they are evaluated
+    pub fn set_optimize(&mut self, optimize: bool) {
+        self.optimize = optimize;
+    }
+
+    pub(crate) fn optimizes(&self) -> bool {
+        self.optimize
     }
 
-    fn is_truthy(&self, value: &Value) -> Result<bool, InterpreterError> {
+    // Binds the variables of a parsed program to slots. `evaluate` expects a resolved program;
+    // globals declared by earlier programs stay visible to later ones
+    pub fn resolve(&mut self, program: AstNode) -> ResolveOutput {
+        Resolver::new(&mut self.global_scope, self.strict).resolve_program(program)
+    }
+
+    pub(crate) fn is_truthy(&self, value: &Value) -> Result<bool, InterpreterError> {
         match value {
             Value::Bool(b) => Ok(*b),
             Value::String(s) if s == "true" => Ok(true),
             Value::String(s) if s == "false" => Ok(false),
-            _ => Err(InterpreterError::Error(format!("Cannot convert {:?} to boolean", value)))
+            _ => Err(InterpreterError::runtime("TypeError", format!("Cannot convert {:?} to boolean", value)))
         }
     }
 
-    fn push_scope(&mut self) {
-        self.scopes.push(HashMap::new());
+    fn set_global(&mut self, name: &str, value: Value) {
+        let slot = self.global_scope.slot(name);
+        self.store(Binding::Global(slot), value);
     }
 
-    fn pop_scope(&mut self) {
-        self.scopes.pop();
+    fn get_global(&self, name: &str) -> Option<&Value> {
+        self.global_scope.get(name).and_then(|slot| self.slot(Binding::Global(slot)))
     }
 
-    fn set_variable(&mut self, name: String, value: Value) {
-        // Scope search same as in get_variable
-        for scope in self.scopes.iter_mut().rev() {
-            if scope.contains_key(&name) {
-                scope.insert(name, value);
-                return;
-            }
-        }
-    
-        if let Some(scope) = self.scopes.last_mut() {
-            scope.insert(name, value);
-        } else {
-            self.variables.insert(name, value);
+    // Programs that were never resolved fall back to globals looked up by name
+    fn unresolved(&mut self, name: &str) -> Binding {
+        Binding::Global(self.global_scope.slot(name))
+    }
+
+    fn slot(&self, binding: Binding) -> Option<&Value> {
+        let slot = match binding {
+            Binding::Local(slot) => self.frames.last().and_then(|frame| frame.get(slot)),
+            Binding::Global(slot) => self.globals.get(slot),
+        };
+        slot.and_then(Option::as_ref)
+    }
+
+    fn slot_mut(&mut self, binding: Binding) -> Option<&mut Value> {
+        let slot = match binding {
+            Binding::Local(slot) => self.frames.last_mut().and_then(|frame| frame.get_mut(slot)),
+            Binding::Global(slot) => self.globals.get_mut(slot),
+        };
+        slot.and_then(Option::as_mut)
+    }
+
+    fn load(&self, name: &str, binding: Binding) -> Result<Value, InterpreterError> {
+        self.slot(binding)
+            .cloned()
+            .ok_or_else(|| InterpreterError::runtime("NameError", format!("Undefined variable: {}", name)))
+    }
+
+    fn store(&mut self, binding: Binding, value: Value) {
+        let (slots, slot) = match binding {
+            Binding::Local(slot) => (self.frames.last_mut().expect("interpreter always has a frame"), slot),
+            Binding::Global(slot) => (&mut self.globals, slot),
+        };
+        if slots.len() <= slot {
+            slots.resize(slot + 1, None);
         }
+        slots[slot] = Some(value);
     }
-    
 
-    fn get_variable(&self, name: &str) -> Option<Value> {
-        // search scopes from innermost to outermost
-        for scope in self.scopes.iter().rev() {
-            if let Some(val) = scope.get(name) {
-                return Some(val.clone());
+    pub(crate) fn apply_binary(operator: &Token, left_val: Value, right_val: Value) -> Result<Value, InterpreterError> {
+        match operator {
+            Token::Plus => left_val + right_val,
+            Token::Minus => left_val - right_val,
+            Token::Multiply => left_val * right_val,
+            Token::Modulo => match (&left_val, &right_val) {
+                (Value::Number(_), Value::Number(b)) if *b == 0.0 => {
+                    Err(InterpreterError::runtime("ZeroDivisionError", "Modulo by zero".to_string()))
+                }
+                (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a % b)),
+                _ => Err(InterpreterError::runtime("TypeError", "Cannot apply % to non-numeric values".to_string())),
+            },
+            Token::Divide => left_val / right_val,
+            Token::Equal => {
+                let is_equal = left_val == right_val;
+                Ok(Value::Bool(is_equal))
+            },
+            Token::LessThan => {
+                if let (Value::Number(lhs), Value::Number(rhs)) = (&left_val, &right_val) {
+                    Ok(Value::Bool(lhs < rhs))
+                } else {
+                    Err(InterpreterError::runtime("TypeError", "Cannot compare non-numeric values with <".to_string()))
+                }
+            },
+            Token::GreaterThan => {
+                if let (Value::Number(lhs), Value::Number(rhs)) = (&left_val, &right_val) {
+                    Ok(Value::Bool(lhs > rhs))
+                } else {
+                    Err(InterpreterError::runtime("TypeError", "Cannot compare non-numeric values with >".to_string()))
+                }
+            }
+            Token::And => {
+                match (&left_val, &right_val) {
+                    (Value::Bool(lhs), Value::Bool(rhs)) => Ok(Value::Bool(*lhs && *rhs)),
+                    (Value::String(ls), Value::String(rs)) if ls == "true" || ls == "false" => {
+                        let lhs = ls == "true";
+                        let rhs = rs == "true";
+                        Ok(Value::Bool(lhs && rhs))
+                    }
+                    _ => Err(InterpreterError::runtime("TypeError", "Cannot apply `and` to non-boolean values".to_string())),
+                }
             }
+            Token::Or => {
+                match (&left_val, &right_val) {
+                    (Value::Bool(lhs), Value::Bool(rhs)) => Ok(Value::Bool(*lhs || *rhs)),
+                    (Value::String(ls), Value::String(rs)) if ls == "true" || ls == "false" => {
+                        let lhs = ls == "true";
+                        let rhs = rs == "true";
+                        Ok(Value::Bool(lhs || rhs))
+                    }
+                    _ => Err(InterpreterError::runtime("TypeError", "Cannot apply `or` to non-boolean values".to_string())),
+                }
+            }
+            _ => Err(InterpreterError::Error(format!("Unsupported operator: {:?}", operator))),
         }
-        // if not just return global scope
-        self.variables.get(name).cloned()
     }
 
-    pub fn evaluate(&mut self, node: &AstNode) -> Result<Value, InterpreterError> {
-        match node {
-            AstNode::Program(statements) => {
-                let mut last_value = Value::Number(0.0);
-                for statement in statements {
-                    match self.evaluate(statement) {
-                        Ok(value) => last_value = value,
-                        Err(InterpreterError::Break) => return Err(InterpreterError::Error("Break statement outside of loop".to_string())),
-                        Err(e) => return Err(e),
+    fn bind_pattern(&mut self, pattern: &Pattern, value: Value) -> Result<(), InterpreterError> {
+        match pattern {
+            Pattern::Identifier(name) => {
+                let binding = self.unresolved(name);
+                self.store(binding, value);
+                Ok(())
+            }
+            Pattern::Variable { binding, .. } => {
+                self.store(*binding, value);
+                Ok(())
+            }
+            Pattern::Array { elements, rest } => {
+                let items = match value {
+                    Value::Array(items) => items,
+                    other => {
+                        return Err(InterpreterError::Error(format!(
+                            "Cannot destructure {} with an array pattern",
+                            other.type_name()
+                        )))
                     }
+                };
+                if rest.is_none() && items.len() != elements.len() {
+                    return Err(InterpreterError::Error(format!(
+                        "Array pattern expects {} elements but the array has {}",
+                        elements.len(),
+                        items.len()
+                    )));
                 }
-                Ok(last_value)
-            }
-            AstNode::VariableDeclaration { name, value } => {
-                let value = self.evaluate(value)?;
-                self.variables.insert(name.clone(), value.clone());
-                Ok(value)
-            }
-            AstNode::PrintStatement(expression) => {
-                let value = self.evaluate(expression)?;
-                // trying to avoid printing Value::Nil
-                if value != Value::Nil {
-                    println!("{}", value);
+                if items.len() < elements.len() {
+                    return Err(InterpreterError::Error(format!(
+                        "Array pattern expects at least {} elements but the array has {}",
+                        elements.len(),
+                        items.len()
+                    )));
                 }
-                Ok(Value::Number(0.0))
-            }
-            AstNode::BinaryExpression { left, operator, right } => {
-                let left_val = self.evaluate(left)?;
-                let right_val = self.evaluate(right)?;
-                match operator {
-                    Token::Plus => Ok(left_val + right_val),
-                    Token::Minus => Ok(left_val - right_val),
-                    Token::Multiply => Ok(left_val * right_val),
-                    Token::Divide => {
-                        if right_val == Value::Number(0.0) {
-                            Err(InterpreterError::Error("Division by zero".to_string()))
-                        } else {
-                            Ok(left_val / right_val)
-                        }
+
+                let mut items = items.into_iter();
+                for (element, item) in elements.iter().zip(items.by_ref()) {
+                    self.bind_pattern(element, item)?;
+                }
+                if let Some(rest) = rest {
+                    self.bind_pattern(rest, Value::Array(items.collect()))?;
+                }
+                Ok(())
+            }
+            Pattern::Dictionary(entries) => {
+                let dict = match value {
+                    Value::Dictionary(dict) => dict,
+                    other => {
+                        return Err(InterpreterError::Error(format!(
+                            "Cannot destructure {} with a dictionary pattern",
+                            other.type_name()
+                        )))
                     }
-                    Token::Equal => {
-                        let is_equal = left_val == right_val;
-                        Ok(Value::Bool(is_equal))
-                    },
-                    Token::LessThan => {
-                        if let (Value::Number(lhs), Value::Number(rhs)) = (&left_val, &right_val) {
-                            Ok(Value::Bool(lhs < rhs))
-                        } else {
-                            Err(InterpreterError::Error("Cannot compare non-numeric values with <".to_string()))
-                        }
-                    },
-                    Token::GreaterThan => {
-                        if let (Value::Number(lhs), Value::Number(rhs)) = (&left_val, &right_val) {
-                            Ok(Value::Bool(lhs > rhs))
-                        } else {
-                            Err(InterpreterError::Error("Cannot compare non-numeric values with >".to_string()))
-                        }
+                };
+                for (key, entry) in entries {
+                    let item = dict.get(key).cloned().ok_or_else(|| {
+                        InterpreterError::runtime("KeyError", format!("Key '{}' not found while destructuring dictionary", key))
+                    })?;
+                    self.bind_pattern(entry, item)?;
+                }
+                Ok(())
+            }
+            Pattern::Wildcard => Ok(()),
+            Pattern::Literal(_) | Pattern::Range { .. } | Pattern::Or(_) | Pattern::Variant { .. } => {
+                let mut bindings = Vec::new();
+                if !Self::match_pattern(pattern, &value, &mut bindings) {
+                    return Err(InterpreterError::Error(format!("Value {} does not match the pattern", value)));
+                }
+                for (leaf, value) in bindings {
+                    self.bind_pattern(leaf, value)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    // Tests a value against a pattern without binding anything, collecting the
+    // variable patterns that would be bound on success
+    fn match_pattern<'p>(pattern: &'p Pattern, value: &Value, bindings: &mut Vec<(&'p Pattern, Value)>) -> bool {
+        match (pattern, value) {
+            (Pattern::Wildcard, _) => true,
+            (Pattern::Identifier(_) | Pattern::Variable { .. }, _) => {
+                bindings.push((pattern, value.clone()));
+                true
+            }
+            (Pattern::Literal(literal), _) => {
+                let expected = match &**literal {
+                    AstNode::NumberLiteral(n) => Value::Number(*n),
+                    AstNode::StringLiteral(s) => Value::String(s.clone()),
+                    AstNode::Bool(b) => Value::Bool(*b),
+                    _ => Value::Nil,
+                };
+                expected == *value
+            }
+            (Pattern::Range { start, end, inclusive }, Value::Number(n)) => {
+                *n >= *start && if *inclusive { *n <= *end } else { *n < *end }
+            }
+            (Pattern::Array { elements, rest }, Value::Array(items)) => {
+                let length_ok = match rest {
+                    Some(_) => items.len() >= elements.len(),
+                    None => items.len() == elements.len(),
+                };
+                if !length_ok {
+                    return false;
+                }
+                if !elements
+                    .iter()
+                    .zip(items)
+                    .all(|(element, item)| Self::match_pattern(element, item, bindings))
+                {
+                    return false;
+                }
+                if let Some(rest) = rest {
+                    bindings.push((&**rest, Value::Array(items[elements.len()..].to_vec())));
+                }
+                true
+            }
+            (Pattern::Dictionary(entries), Value::Dictionary(dict)) => entries.iter().all(|(key, entry)| {
+                dict.get(key).is_some_and(|item| Self::match_pattern(entry, item, bindings))
+            }),
+            (Pattern::Variant { enum_name, variant, fields }, Value::Enum { name, variant: actual, values }) => {
+                if enum_name != name || variant != actual {
+                    return false;
+                }
+                match fields {
+                    Some(fields) => {
+                        fields.len() == values.len()
+                            && fields
+                                .iter()
+                                .zip(values)
+                                .all(|(field, item)| Self::match_pattern(field, item, bindings))
                     }
-                    Token::And => {
-                        match (&left_val, &right_val) {
-                            (Value::Bool(lhs), Value::Bool(rhs)) => Ok(Value::Bool(*lhs && *rhs)),
-                            (Value::String(ls), Value::String(rs)) if ls == "true" || ls == "false" => {
-                                let lhs = ls == "true";
-                                let rhs = rs == "true";
-                                Ok(Value::Bool(lhs && rhs))
-                            }
-                            _ => {
-                                println!("DEBUG: And operation failed. Left: {:?}, Right: {:?}", left_val, right_val);
-                                Err(InterpreterError::Error("Cannot apply `and` to non-boolean values".to_string()))
+                    None => true,
+                }
+            }
+            (Pattern::Or(alternatives), _) => alternatives.iter().any(|alternative| {
+                let mark = bindings.len();
+                let matched = Self::match_pattern(alternative, value, bindings);
+                if !matched {
+                    bindings.truncate(mark);
+                }
+                matched
+            }),
+            _ => false,
+        }
+    }
+
+    // Runs a match arm whose pattern already matched. Returns None if its guard fails
+    fn evaluate_arm(&mut self, arm: &MatchArm, bindings: Vec<(&Pattern, Value)>) -> Result<Option<Value>, InterpreterError> {
+        for (leaf, value) in bindings {
+            self.bind_pattern(leaf, value)?;
+        }
+        if let Some(guard) = &arm.guard {
+            let guard_value = self.evaluate(guard)?;
+            if !self.is_truthy(&guard_value)? {
+                return Ok(None);
+            }
+        }
+        self.evaluate(&arm.body).map(Some)
+    }
+
+    fn evaluate_arguments(&mut self, arguments: &[AstNode]) -> Result<Vec<Value>, InterpreterError> {
+        let mut arg_vals = Vec::new();
+        for arg in arguments {
+            arg_vals.push(self.evaluate(arg)?);
+        }
+        Ok(arg_vals)
+    }
+
+    fn evaluate_array(&mut self, elements: &[AstNode]) -> Result<Value, InterpreterError> {
+        let max_size = self.budget.max_collection_size();
+        let mut items = Vec::with_capacity(elements.len());
+        let mut size = 0usize;
+        for element in elements {
+            let item = self.evaluate(element)?;
+            // checked as it grows, so copies of a large element don't pile up first
+            if let Some(max) = max_size {
+                size = size.saturating_add(1 + limits::deep_size(&item, max));
+                limits::check_len(max_size, size)?;
+            }
+            items.push(item);
+        }
+        Ok(Value::Array(items))
+    }
+
+    // Calls a function value. Also returns the final value of a `self` parameter,
+    // so methods can update their receiver
+    fn invoke(&mut self, func: Value, arg_vals: Vec<Value>) -> Result<(Value, Option<Value>), InterpreterError> {
+        match func {
+            Value::Function { name, params, body } => {
+                self.check_interrupt()?;
+                if self.call_stack.len() >= self.max_call_depth {
+                    return Err(InterpreterError::runtime(
+                        "StackOverflowError",
+                        format!("Stack overflow: maximum call depth of {} exceeded", self.max_call_depth),
+                    ));
+                }
+                // each call gets a fresh frame of local slots
+                self.frames.push(Vec::new());
+                self.call_stack.push(CallFrame { name, call_site: self.position.unwrap_or_default() });
+                let outer_try_depth = std::mem::take(&mut self.try_depth);
+
+                let mut params = params;
+                let mut body = body;
+                let mut arg_vals = arg_vals;
+                let mut updated_self = None;
+                let mut first_call = true;
+                let result = loop {
+                    // bind parameters to arguments, then execute function body
+                    let mut outcome = Ok(());
+                    if params.len() != arg_vals.len() {
+                        let name = &self.call_stack.last().expect("the call pushed its frame").name;
+                        outcome = Err(arity_error(name, params.len(), arg_vals.len()));
+                    } else {
+                        for (param, arg_val) in params.iter().zip(arg_vals) {
+                            outcome = self.bind_pattern(param, arg_val);
+                            if outcome.is_err() {
+                                break;
                             }
                         }
                     }
-                    Token::Or => {
-                        match (&left_val, &right_val) {
-                            (Value::Bool(lhs), Value::Bool(rhs)) => Ok(Value::Bool(*lhs || *rhs)),
-                            (Value::String(ls), Value::String(rs)) if ls == "true" || ls == "false" => {
-                                let lhs = ls == "true";
-                                let rhs = rs == "true";
-                                Ok(Value::Bool(lhs || rhs))
-                            }
-                            _ => {
-                                println!("DEBUG: Or operation failed. Left: {:?}, Right: {:?}", left_val, right_val);
-                                Err(InterpreterError::Error("Cannot apply `or` to non-boolean values".to_string()))
+                    let result = outcome.and_then(|_| self.evaluate(&body));
+                    // only the function originally called can update its receiver
+                    if first_call {
+                        updated_self = self.self_param(&params);
+                        first_call = false;
+                    }
+                    match result {
+                        // the tail call reuses this call's place on the stack
+                        Err(InterpreterError::TailCall(call)) => match *call {
+                            // a tail-recursive loop never makes a nested call, so it is checked here
+                            _ if self.interrupt.reset() => break Err(InterpreterError::Interrupted),
+                            (Value::Function { name, params: next_params, body: next_body }, args) => {
+                                *self.frames.last_mut().expect("the call pushed a frame") = Vec::new();
+                                // the replaced function's caller stays the call site
+                                self.call_stack.last_mut().expect("the call pushed its frame").name = name;
+                                params = next_params;
+                                body = next_body;
+                                arg_vals = args;
                             }
-                        }
+                            (func, args) => break self.invoke(func, args).map(|(result, _)| result),
+                        },
+                        Err(InterpreterError::Return(val)) => break Ok(val),
+                        other => break other,
                     }
-                    _ => Err(InterpreterError::Error(format!("Unsupported operator: {:?}", operator))),
+                };
+
+                // errors record the stack of the function they were raised in
+                let result = result.map_err(|e| self.attach_stack(e));
+                self.try_depth = outer_try_depth;
+                let frame = self.call_stack.pop().expect("the call pushed its frame");
+                // errors raised later without a position of their own are reported at this call
+                self.position = Some(frame.call_site);
+                self.frames.pop();
+                Ok((result?, updated_self))
+            }
+            Value::NativeFunction { func, .. } => Ok((func(self, arg_vals)?, None)),
+            Value::EnumConstructor { name, variant, arity } => {
+                if arg_vals.len() != arity {
+                    return Err(InterpreterError::Error(format!(
+                        "{}.{} takes {} value(s) but {} were given",
+                        name,
+                        variant,
+                        arity,
+                        arg_vals.len()
+                    )));
                 }
+                Ok((Value::Enum { name, variant, values: arg_vals }, None))
             }
-            AstNode::Bool(value) => Ok(Value::Bool(*value)),
-            AstNode::StringLiteral(value) => Ok(Value::String(value.clone())),
-            AstNode::NumberLiteral(value) => Ok(Value::Number(*value)),
-            AstNode::Identifier(name) => {
-                self.get_variable(name)
-                    .ok_or_else(|| InterpreterError::Error(format!("Undefined variable: {}", name)))
+            other => Err(InterpreterError::runtime("TypeError", format!("{} is not a function", other.type_name()))),
+        }
+    }
+
+    fn self_param(&self, params: &[Pattern]) -> Option<Value> {
+        params.iter().find_map(|param| match param {
+            Pattern::Variable { name, binding } if name == "self" => self.slot(*binding).cloned(),
+            _ => None,
+        })
+    }
+
+    fn current_stack(&self) -> Value {
+        Value::Array(self.call_stack.iter().map(|frame| Value::String(frame.name.clone())).collect())
+    }
+
+    fn record_traceback(&mut self) {
+        self.traceback = Some(Traceback { frames: self.call_stack.clone(), position: self.position });
+    }
+
+    // The traceback of the last exception that was not caught
+    pub fn take_traceback(&mut self) -> Option<Traceback> {
+        self.traceback.take()
+    }
+
+    // Turns a plain runtime error into an exception and records the current call stack
+    // on exceptions that don't have one yet
+    fn attach_stack(&mut self, error: InterpreterError) -> InterpreterError {
+        match error {
+            InterpreterError::Error(message) => {
+                self.record_traceback();
+                InterpreterError::Throw(Value::error("RuntimeError", message, self.current_stack()))
             }
-            AstNode::ArrayLiteral(elements) => {
-                let mut vals = Vec::new();
-                for e in elements {
-                    vals.push(self.evaluate(e)?);
-                }
-                Ok(Value::Array(vals))
-            }
-            AstNode::IndexExpression(array_node, index_node) => {
-                let array_val = self.evaluate(array_node)?;
-                let index_val = self.evaluate(index_node)?;
-                match (array_val, index_val) {
-                    (Value::Array(arr), Value::Number(idx)) => {
-                        if idx < 0.0 || idx as usize >= arr.len() {
-                            // changing this to now return nil instead of an error so that we can handle writing
-                            // other functions such as get length
-                            // return Err(InterpreterError::Error(format!("Array index {} out of bounds", idx)));
-                            return Ok(Value::Nil);
-                        }
-                        Ok(arr[idx as usize].clone())
+            InterpreterError::Throw(Value::Struct { name, mut fields }) if name == "Error" => {
+                for (field, value) in fields.iter_mut() {
+                    if field == "stack" && *value == Value::Nil {
+                        *value = self.current_stack();
+                        self.record_traceback();
                     }
-                    (Value::Dictionary(dict), Value::String(key)) => {
-                        dict.get(&key)
-                            .cloned()
-                            .ok_or_else(|| InterpreterError::Error(format!("Key '{}' not found in dictionary", key)))
-                    }
-                    _ => Err(InterpreterError::Error("Indexing error: expected array or dictionary with correct key type".to_string())),
                 }
+                InterpreterError::Throw(Value::Struct { name, fields })
             }
-            AstNode::DictionaryLiteral(pairs) => {
-                let mut map = HashMap::new();
-                for (key, expr) in pairs {
-                    let value = self.evaluate(expr)?;
-                    map.insert(key.clone(), value);
+            other => other,
+        }
+    }
+
+    // Runs a try statement. The finally block runs however the body and handler exit;
+    // an error raised by the finally block replaces the original outcome
+    fn evaluate_try(
+        &mut self,
+        body: &AstNode,
+        catch: &Option<(Option<Pattern>, Box<AstNode>)>,
+        finally: &Option<Box<AstNode>>,
+    ) -> Result<Value, InterpreterError> {
+        self.try_depth += 1;
+        let result = self.evaluate_try_clauses(body, catch, finally);
+        self.try_depth -= 1;
+        // an exception that was caught or discarded by the finally block has no traceback to report
+        if !matches!(result, Err(InterpreterError::Error(_) | InterpreterError::Throw(_))) {
+            self.traceback = None;
+        }
+        result
+    }
+
+    fn evaluate_try_clauses(
+        &mut self,
+        body: &AstNode,
+        catch: &Option<(Option<Pattern>, Box<AstNode>)>,
+        finally: &Option<Box<AstNode>>,
+    ) -> Result<Value, InterpreterError> {
+        let mut result = self.evaluate(body);
+        if let Some((pattern, handler)) = catch {
+            result = match result {
+                Err(e @ (InterpreterError::Error(_) | InterpreterError::Throw(_))) => match self.attach_stack(e) {
+                    InterpreterError::Throw(error) => match pattern {
+                        Some(pattern) => self.bind_pattern(pattern, error),
+                        None => Ok(()),
+                    }
+                    .and_then(|_| self.evaluate(handler)),
+                    other => Err(other),
+                },
+                other => other,
+            };
+        }
+        if let Some(finally) = finally {
+            self.evaluate(finally)?;
+        }
+        result
+    }
+
+    // Resolves `receiver.method(args)`: user methods from `impl` blocks, static methods called on
+    // the struct itself, functions stored in dictionaries, then built-in methods on core values.
+    // Returns the result and, if the receiver changed, its new value
+    fn call_method(&mut self, receiver: Value, method: &str, arg_vals: Vec<Value>) -> Result<(Value, Option<Value>), InterpreterError> {
+        let user_method = match &receiver {
+            Value::Struct { name, .. } | Value::Enum { name, .. } => self.lookup_method(name, method),
+            _ => None,
+        };
+        if let Some(func) = user_method {
+            let mut full_args = vec![receiver.clone()];
+            full_args.extend(arg_vals);
+            let (result, updated_self) = self.invoke(func, full_args)?;
+            let updated = updated_self.filter(|value| *value != receiver);
+            return Ok((result, updated));
+        }
+
+        match &receiver {
+            Value::Native { type_name } => match builtins::type_method(type_name, method) {
+                Some(func) => {
+                    let mut full_args = vec![receiver.clone()];
+                    full_args.extend(arg_vals);
+                    Ok((func(self, full_args)?, None))
+                }
+                None => Err(InterpreterError::Error(format!("{} has no method '{}'", type_name, method))),
+            },
+            Value::Struct { name, fields } => {
+                // a field holding a function can be called directly
+                if let Some((_, func)) = fields.iter().find(|(field, _)| field == method) {
+                    let func = func.clone();
+                    return self.invoke(func, arg_vals).map(|(result, _)| (result, None));
                 }
-                Ok(Value::Dictionary(map))
+                Err(InterpreterError::Error(format!("{} has no method '{}'", name, method)))
             }
-            AstNode::IfStatement { condition, consequence, alternative } => {
-                let condition_value = self.evaluate(condition)?;
-                match self.is_truthy(&condition_value)? {
-                    true => self.evaluate(consequence),
-                    false => {
-                        if let Some(alt) = alternative {
-                            self.evaluate(alt)
-                        } else {
-                            Ok(Value::Number(0.0))
-                        }
+            // Shape.Circle(2) builds a variant; other names are static methods
+            Value::EnumType { name, variants } if variants.iter().any(|(variant, _)| variant == method) => {
+                let constructor = Self::enum_variant(name, variants, method)?;
+                self.invoke(constructor, arg_vals).map(|(result, _)| (result, None))
+            }
+            Value::StructType { name, .. } | Value::EnumType { name, .. } => match self.lookup_method(name, method) {
+                Some(func) => self.invoke(func, arg_vals).map(|(result, _)| (result, None)),
+                None => Err(InterpreterError::Error(format!("{} has no method '{}'", name, method))),
+            },
+            _ => {
+                if let Value::Dictionary(dict) = &receiver {
+                    if let Some(func @ (Value::Function { .. } | Value::NativeFunction { .. })) = dict.get(method) {
+                        let func = func.clone();
+                        return self.invoke(func, arg_vals).map(|(result, _)| (result, None));
                     }
                 }
+                let mut receiver = receiver;
+                match builtins::call_method(&mut receiver, method, arg_vals) {
+                    Some(result) => Ok((result?, builtins::mutates(method).then_some(receiver))),
+                    None => Err(InterpreterError::Error(format!("{} has no method '{}'", receiver.type_name(), method))),
+                }
             }
-            AstNode::WhileStatement { condition, body } => {
-                loop {
-                    let cond_val = self.evaluate(condition)?;
-                    if !self.is_truthy(&cond_val)? {
-                        break;
+        }
+    }
+
+    // `Enum.Variant` is the value itself for variants without a payload, otherwise its constructor
+    fn enum_variant(name: &str, variants: &[(String, usize)], variant: &str) -> Result<Value, InterpreterError> {
+        match variants.iter().find(|(v, _)| v == variant) {
+            Some((_, 0)) => Ok(Value::Enum {
+                name: name.to_string(),
+                variant: variant.to_string(),
+                values: Vec::new(),
+            }),
+            Some((_, arity)) => Ok(Value::EnumConstructor {
+                name: name.to_string(),
+                variant: variant.to_string(),
+                arity: *arity,
+            }),
+            None => Err(InterpreterError::Error(format!("{} has no variant '{}'", name, variant))),
+        }
+    }
+
+    fn lookup_method(&self, type_name: &str, method: &str) -> Option<Value> {
+        self.methods.get(type_name).and_then(|methods| methods.get(method)).cloned()
+    }
+
+    // Splits an assignment target such as grid[i][j] or p.x into its variable and evaluated keys
+    fn resolve_place<'a>(&mut self, target: &'a AstNode) -> Result<(Place<'a>, Vec<PlaceKey>), InterpreterError> {
+        match target {
+            AstNode::Variable { name, binding } => Ok(((name, *binding), Vec::new())),
+            AstNode::Identifier(name) => Ok(((name, self.unresolved(name)), Vec::new())),
+            AstNode::IndexExpression(object, index, _) => {
+                let (place, mut keys) = self.resolve_place(object)?;
+                keys.push(PlaceKey::Index(self.evaluate(index)?));
+                Ok((place, keys))
+            }
+            AstNode::FieldAccess { object, field, .. } => {
+                let (place, mut keys) = self.resolve_place(object)?;
+                keys.push(PlaceKey::Field(field.clone()));
+                Ok((place, keys))
+            }
+            _ => Err(InterpreterError::Error("Invalid assignment target".to_string())),
+        }
+    }
+
+    fn read_place(&self, (name, binding): Place, keys: &[PlaceKey]) -> Result<Value, InterpreterError> {
+        let mut value = self.load(name, binding)?;
+        for key in keys {
+            value = match (value, key) {
+                (Value::Array(mut arr), PlaceKey::Index(Value::Number(idx))) => {
+                    if *idx < 0.0 || *idx as usize >= arr.len() {
+                        return Err(InterpreterError::runtime("IndexError", format!("Array index {} out of bounds", idx)));
                     }
+                    arr.swap_remove(*idx as usize)
+                }
+                (Value::Dictionary(mut dict), PlaceKey::Index(Value::String(key)) | PlaceKey::Field(key)) => dict.remove(key)
+                    .ok_or_else(|| InterpreterError::runtime("KeyError", format!("Key '{}' not found in dictionary", key)))?,
+                (Value::Struct { name, fields }, PlaceKey::Field(field)) => fields.into_iter()
+                    .find(|(f, _)| f == field)
+                    .map(|(_, value)| value)
+                    .ok_or_else(|| InterpreterError::Error(format!("{} has no field '{}'", name, field)))?,
+                _ => return Err(InterpreterError::Error("Indexing error: expected array or dictionary with correct key type".to_string())),
+            };
+        }
+        Ok(value)
+    }
+
+    fn write_place(&mut self, (name, binding): Place, keys: &[PlaceKey], value: Value) -> Result<(), InterpreterError> {
+        self.budget.check_size(&value)?;
+        let max_size = self.budget.max_collection_size();
+        if keys.is_empty() {
+            self.store(binding, value);
+            return Ok(());
+        }
 
-                    match self.evaluate(body) {
-                        Ok(_) => continue,
-                        Err(InterpreterError::Break) => break,
-                        Err(e) => return Err(e),
+        let mut target = self.slot_mut(binding)
+            .ok_or_else(|| InterpreterError::runtime("NameError", format!("Undefined variable: {}", name)))?;
+        for key in keys {
+            target = match (target, key) {
+                (Value::Array(arr), PlaceKey::Index(Value::Number(idx))) => {
+                    let len = arr.len();
+                    arr.get_mut(*idx as usize)
+                        .filter(|_| *idx >= 0.0)
+                        .ok_or_else(|| InterpreterError::runtime("IndexError", format!("Array index {} out of bounds for length {}", idx, len)))?
+                }
+                (Value::Dictionary(dict), PlaceKey::Index(Value::String(key)) | PlaceKey::Field(key)) => {
+                    if !dict.contains_key(key) {
+                        limits::check_len(max_size, dict.len() + 1)?;
                     }
+                    dict.entry(key.clone()).or_insert(Value::Nil)
                 }
-                Ok(Value::Number(0.0))
+                (Value::Struct { name, fields }, PlaceKey::Field(field)) => match fields.iter_mut().find(|(f, _)| f == field) {
+                    Some((_, value)) => value,
+                    None => return Err(InterpreterError::Error(format!("{} has no field '{}'", name, field))),
+                },
+                _ => return Err(InterpreterError::Error("Indexing error: expected array or dictionary with correct key type".to_string())),
+            };
+        }
+        *target = value;
+        // storing a value inside another one, e.g. a[0] = a, can grow the container a lot
+        if let Some(container) = self.slot(binding) {
+            self.budget.check_size(container)?;
+        }
+        Ok(())
+    }
+
+    fn evaluate_program(&mut self, statements: &[AstNode]) -> Result<Value, InterpreterError> {
+        // a traceback left from an earlier program was never reported
+        self.traceback = None;
+        let marker = 0u8;
+        let outer_base = std::mem::replace(&mut self.stack_base, &marker as *const u8 as usize);
+        let result = self.evaluate_statements(statements);
+        self.stack_base = outer_base;
+        // exceptions raised outside any function have no stack attached on the way out
+        if matches!(result, Err(InterpreterError::Error(_) | InterpreterError::Throw(_))) && self.traceback.is_none() {
+            self.record_traceback();
+        }
+        result
+    }
+
+    fn evaluate_statements(&mut self, statements: &[AstNode]) -> Result<Value, InterpreterError> {
+        let mut last_value = Value::Number(0.0);
+        for statement in statements {
+            // errors the statement raises without a position are not reported at an earlier one
+            self.position = None;
+            match self.evaluate(statement) {
+                Ok(value) => last_value = value,
+                Err(InterpreterError::Break) => return Err(InterpreterError::Error("Break statement outside of loop".to_string())),
+                Err(e) => return Err(e),
             }
-            AstNode::Break => Err(InterpreterError::Break),
-            AstNode::FunctionDeclaration { name, params, body } => {
-                let func = Value::Function { 
-                    name: name.clone(), 
-                    params: params.clone(), 
-                    body: body.clone(),
-                };
-                self.set_variable(name.clone(), func.clone());
-                Ok(func)
+        }
+        Ok(last_value)
+    }
+
+    fn evaluate_print(&mut self, expression: &AstNode) -> Result<Value, InterpreterError> {
+        let value = self.evaluate(expression)?;
+        // trying to avoid printing Value::Nil
+        if value != Value::Nil {
+            let text = value.to_string();
+            self.budget.write_output(text.len() + 1)?;
+            self.write_line(&text)?;
+        }
+        Ok(Value::Number(0.0))
+    }
+
+    // Writes a line printed by the program to stdout
+    pub(crate) fn write_line(&mut self, text: &str) -> Result<(), InterpreterError> {
+        writeln!(self.stdout, "{}", text).map_err(|e| InterpreterError::Error(format!("Cannot write output: {}", e)))
+    }
+
+    fn evaluate_binary(&mut self, left: &AstNode, operator: &Token, right: &AstNode, span: Span) -> Result<Value, InterpreterError> {
+        let left_val = self.evaluate(left)?;
+        let right_val = self.evaluate(right)?;
+        self.budget.check_binary(operator, &left_val, &right_val)?;
+        Self::apply_binary(operator, left_val, right_val).inspect_err(|_| self.position = Some(span))
+    }
+
+    fn evaluate_unary(&mut self, operator: &Token, operand: &AstNode, span: Span) -> Result<Value, InterpreterError> {
+        let value = self.evaluate(operand)?;
+        match (operator, value) {
+            (Token::Minus, Value::Number(n)) => Ok(Value::Number(-n)),
+            (_, value) => {
+                self.position = Some(span);
+                Err(InterpreterError::runtime("TypeError", format!("Cannot apply {:?} to {}", operator, value)))
             }
-            AstNode::FunctionCall { function, arguments } => {
+        }
+    }
+
+    fn evaluate_index(&mut self, array_node: &AstNode, index_node: &AstNode, span: Span) -> Result<Value, InterpreterError> {
+        let array_val = self.evaluate(array_node)?;
+        let index_val = self.evaluate(index_node)?;
+        index_value(&array_val, &index_val).inspect_err(|_| self.position = Some(span))
+    }
+
+    fn evaluate_dictionary(&mut self, pairs: &[(String, AstNode)]) -> Result<Value, InterpreterError> {
+        let mut map = HashMap::new();
+        for (key, expr) in pairs {
+            let value = self.evaluate(expr)?;
+            map.insert(key.clone(), value);
+        }
+        Ok(Value::Dictionary(map))
+    }
+
+    fn evaluate_destructuring(&mut self, pattern: &Pattern, value: &AstNode) -> Result<Value, InterpreterError> {
+        let value = self.evaluate(value)?;
+        self.bind_pattern(pattern, value.clone())?;
+        Ok(value)
+    }
+
+    fn evaluate_assignment(&mut self, target: &AstNode, value: &AstNode) -> Result<Value, InterpreterError> {
+        let (place, keys) = self.resolve_place(target)?;
+        let value = self.evaluate(value)?;
+        self.write_place(place, &keys, value.clone())?;
+        Ok(value)
+    }
+
+    fn evaluate_call(&mut self, function: &AstNode, arguments: &[AstNode], span: Span) -> Result<Value, InterpreterError> {
+        let func = self.evaluate(function)?;
+        // arguments are evaluated in the caller's scope
+        let arg_vals = self.evaluate_arguments(arguments)?;
+        self.position = Some(span);
+        self.invoke(func, arg_vals).map(|(result, _)| result)
+    }
+
+    fn evaluate_return(&mut self, expr: Option<&AstNode>) -> Result<Value, InterpreterError> {
+        // `return f(args)` hands the call to the running invoke() instead of nesting another one,
+        // unless a try statement still has to see the call's errors
+        if let Some(AstNode::FunctionCall { function, arguments, span }) = expr {
+            if self.try_depth == 0 && !self.call_stack.is_empty() {
                 let func = self.evaluate(function)?;
-                match func {
-                    Value::Function { params, body, .. } => {
-                        // create new scope for function execution
-                        self.push_scope();
-
-                        // bind parameters to arguments
-                        for (param, arg) in params.iter().zip(arguments) {
-                            let arg_val = self.evaluate(arg)?;
-                            self.set_variable(param.clone(), arg_val);
-                        }
+                let arg_vals = self.evaluate_arguments(arguments)?;
+                self.position = Some(*span);
+                return Err(InterpreterError::TailCall(Box::new((func, arg_vals))));
+            }
+        }
+        let value = match expr {
+            Some(expr) => self.evaluate(expr)?,
+            None => Value::Nil,
+        };
+        Err(InterpreterError::Return(value))
+    }
 
-                        // execute function body
-                        let result = match self.evaluate(&body) {
-                            Ok(val) => val,
-                            Err(InterpreterError::Return(val)) => val,
-                            Err(e) => {
-                                self.pop_scope();
-                                return Err(e);
-                            }
-                        };
+    fn evaluate_compound_assignment(&mut self, target: &AstNode, operator: &Token, value: &AstNode) -> Result<Value, InterpreterError> {
+        // the target's index expressions are evaluated once, e.g. arr[f()] += 1
+        let (place, keys) = self.resolve_place(target)?;
+        let current = self.read_place(place, &keys)?;
+        let value = self.evaluate(value)?;
+        self.budget.check_binary(operator, &current, &value)?;
+        let result = Self::apply_binary(operator, current, value)?;
+        self.write_place(place, &keys, result.clone())?;
+        Ok(result)
+    }
 
-                        self.pop_scope();
-                        Ok(result)
-                    }
-                    _ => Err(InterpreterError::Error("Not a function".to_string())),
+    fn evaluate_interpolation(&mut self, parts: &[InterpolationPart]) -> Result<Value, InterpreterError> {
+        let mut result = String::new();
+        for part in parts {
+            match part {
+                InterpolationPart::Literal(text) => result.push_str(text),
+                InterpolationPart::Expression { expr, format } => {
+                    let value = self.evaluate(expr)?;
+                    result.push_str(&value.format_with(format));
                 }
             }
+        }
+        Ok(Value::String(result))
+    }
 
-            AstNode::Return(expr) => {
-                let value = if let Some(expr) = expr {
-                    self.evaluate(expr)?
+    fn evaluate_if(&mut self, condition: &AstNode, consequence: &AstNode, alternative: Option<&AstNode>) -> Result<Value, InterpreterError> {
+        let condition_value = self.evaluate(condition)?;
+        match self.is_truthy(&condition_value)? {
+            true => self.evaluate(consequence),
+            false => {
+                if let Some(alt) = alternative {
+                    self.evaluate(alt)
                 } else {
-                    Value::Nil
-                };
-                Err(InterpreterError::Return(value))
-            }
-            AstNode::Block(statements) => {
-                let mut last_value = Value::Number(0.0);
-                for statement in statements {
-                    match self.evaluate(statement) {
-                        Ok(value) => last_value = value,
-                        Err(InterpreterError::Break) => return Err(InterpreterError::Break),
-                        Err(InterpreterError::Return(val)) => return Err(InterpreterError::Return(val)),
-                        Err(e) => return Err(e),
-                    }
+                    // an if expression without else has no value when the condition fails
+                    Ok(Value::Nil)
                 }
-                Ok(last_value)
             }
+        }
+    }
+
+    fn evaluate_while(&mut self, condition: &AstNode, body: &AstNode) -> Result<Value, InterpreterError> {
+        loop {
+            self.check_interrupt()?;
+            let cond_val = self.evaluate(condition)?;
+            if !self.is_truthy(&cond_val)? {
+                break;
+            }
+
+            match self.evaluate(body) {
+                Ok(_) => continue,
+                Err(InterpreterError::Break) => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(Value::Number(0.0))
+    }
+
+    fn evaluate_method_call(&mut self, object: &AstNode, method: &str, arguments: &[AstNode], span: Span) -> Result<Value, InterpreterError> {
+        // receivers stored in a variable are written back if the method changes them
+        let place = match object {
+            AstNode::Identifier(_)
+            | AstNode::Variable { .. }
+            | AstNode::IndexExpression(..)
+            | AstNode::FieldAccess { .. } => Some(self.resolve_place(object)?),
+            _ => None,
+        };
+        let receiver = match &place {
+            Some((place, keys)) => self.read_place(*place, keys)?,
+            None => self.evaluate(object)?,
+        };
+        let arg_vals = self.evaluate_arguments(arguments)?;
+
+        self.position = Some(span);
+        let (result, updated) = self.call_method(receiver, method, arg_vals)?;
+        if let (Some((place, keys)), Some(updated)) = (place, updated) {
+            self.write_place(place, &keys, updated)?;
+        }
+        Ok(result)
+    }
+
+    fn evaluate_impl(&mut self, type_name: &str, methods: &[AstNode]) -> Result<Value, InterpreterError> {
+        match self.get_global(type_name) {
+            Some(Value::StructType { .. } | Value::EnumType { .. }) => {}
+            _ => return Err(InterpreterError::Error(format!("Cannot implement methods for unknown type {}", type_name))),
+        }
+        for method in methods {
+            if let AstNode::FunctionDeclaration { name, params, body } = method {
+                let func = Value::Function {
+                    name: format!("{}.{}", type_name, name),
+                    params: params.clone(),
+                    body: body.clone(),
+                };
+                self.methods.entry(type_name.to_string()).or_default().insert(name.clone(), func);
+            }
+        }
+        Ok(Value::Nil)
+    }
+
+    fn evaluate_block(&mut self, statements: &[AstNode]) -> Result<Value, InterpreterError> {
+        let mut last_value = Value::Number(0.0);
+        for statement in statements {
+            self.position = None;
+            match self.evaluate(statement) {
+                Ok(value) => last_value = value,
+                Err(InterpreterError::Break) => return Err(InterpreterError::Break),
+                Err(InterpreterError::Return(val)) => return Err(InterpreterError::Return(val)),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(last_value)
+    }
+
+    fn evaluate_match(&mut self, subject: &AstNode, arms: &[MatchArm], span: Span) -> Result<Value, InterpreterError> {
+        let value = self.evaluate(subject)?;
+        for arm in arms {
+            let mut bindings = Vec::new();
+            if !Self::match_pattern(&arm.pattern, &value, &mut bindings) {
+                continue;
+            }
+            // the resolver gives arm bindings slots of their own
+            if let Some(value) = self.evaluate_arm(arm, bindings)? {
+                return Ok(value);
+            }
+        }
+        self.position = Some(span);
+        Err(InterpreterError::Error(format!("No match arm matched value: {}", value)))
+    }
+
+    fn evaluate_struct_literal(&mut self, name: &str, fields: &[(String, AstNode)]) -> Result<Value, InterpreterError> {
+        let declared = match self.get_global(name) {
+            Some(Value::StructType { fields, .. }) => fields.clone(),
+            Some(other) => return Err(InterpreterError::Error(format!("{} is a {}, not a struct", name, other.type_name()))),
+            None => return Err(InterpreterError::Error(format!("Undefined struct: {}", name))),
+        };
+
+        let mut values = HashMap::new();
+        for (field, expr) in fields {
+            if !declared.contains(field) {
+                return Err(InterpreterError::Error(format!("{} has no field '{}'", name, field)));
+            }
+            let value = self.evaluate(expr)?;
+            values.insert(field.clone(), value);
+        }
+
+        let mut instance_fields = Vec::new();
+        for field in declared {
+            match values.remove(&field) {
+                Some(value) => instance_fields.push((field, value)),
+                None => return Err(InterpreterError::Error(format!("Missing field '{}' in {} literal", field, name))),
+            }
+        }
+        Ok(Value::Struct { name: name.to_string(), fields: instance_fields })
+    }
+
+    fn evaluate_field_access(&mut self, object: &AstNode, field: &str, span: Span) -> Result<Value, InterpreterError> {
+        let value = self.evaluate(object)?;
+        self.field(value, field).inspect_err(|_| self.position = Some(span))
+    }
+
+    fn field(&self, value: Value, field: &str) -> Result<Value, InterpreterError> {
+        match value {
+            Value::Struct { name, fields } => fields.into_iter()
+                .find(|(f, _)| f == field)
+                .map(|(_, value)| value)
+                .ok_or_else(|| InterpreterError::Error(format!("{} has no field '{}'", name, field))),
+            Value::Dictionary(mut dict) => dict.remove(field)
+                .ok_or_else(|| InterpreterError::runtime("KeyError", format!("Key '{}' not found in dictionary", field))),
+            Value::EnumType { name, variants } => Self::enum_variant(&name, &variants, field),
+            other => Err(InterpreterError::Error(format!("Cannot access field '{}' on {}", field, other.type_name()))),
+        }
+    }
+
+    fn evaluate_is(&mut self, value: &AstNode, pattern: &Pattern) -> Result<Value, InterpreterError> {
+        let value = self.evaluate(value)?;
+        let mut bindings = Vec::new();
+        if !Self::match_pattern(pattern, &value, &mut bindings) {
+            return Ok(Value::Bool(false));
+        }
+        for (leaf, value) in bindings {
+            self.bind_pattern(leaf, value)?;
+        }
+        Ok(Value::Bool(true))
+    }
+
+    fn evaluate_throw(&mut self, expr: &AstNode, span: Span) -> Result<Value, InterpreterError> {
+        let value = self.evaluate(expr)?;
+        self.position = Some(span);
+        // anything that isn't already an error value becomes the message of a new one
+        let error = match value.error_field("kind") {
+            Some(_) => value,
+            None => Value::error("Error", value.to_string(), Value::Nil),
+        };
+        Err(self.attach_stack(InterpreterError::Throw(error)))
+    }
+
+    fn evaluate_propagate(&mut self, expr: &AstNode) -> Result<Value, InterpreterError> {
+        match self.evaluate(expr)? {
+            Value::Enum { name, variant, mut values } if name == "Result" => match variant.as_str() {
+                "Ok" => Ok(values.pop().unwrap_or(Value::Nil)),
+                // the error result itself is returned from the enclosing function
+                _ => Err(InterpreterError::Return(Value::Enum { name, variant, values })),
+            },
+            other => Err(InterpreterError::runtime(
+                "TypeError",
+                format!("'?' expects an ok() or err() result, got {}", other.type_name()),
+            )),
+        }
+    }
+
+    #[inline]
+    pub fn evaluate(&mut self, node: &AstNode) -> Result<Value, InterpreterError> {
+        self.check_native_stack()?;
+        if self.budget.is_limited() {
+            return self.evaluate_limited(node);
+        }
+        self.evaluate_node(node)
+    }
+
+    fn evaluate_limited(&mut self, node: &AstNode) -> Result<Value, InterpreterError> {
+        // every program gets the full budget
+        if let AstNode::Program(_) = node {
+            self.budget.start();
+        }
+        self.budget.step()?;
+        let value = self.evaluate_node(node)?;
+        // new and grown values pass through here, except for containers changed in place by write_place
+        self.budget.check_size(&value)?;
+        Ok(value)
+    }
+
+    fn evaluate_node(&mut self, node: &AstNode) -> Result<Value, InterpreterError> {
+        match node {
+            AstNode::Program(statements) => self.evaluate_program(statements),
+            AstNode::VariableDeclaration { name, value, .. } => {
+                let target = AstNode::Identifier(name.clone());
+                self.evaluate_assignment(&target, value)
+            }
+            AstNode::PrintStatement(expression) => self.evaluate_print(expression),
+            AstNode::BinaryExpression { left, operator, right, span } => self.evaluate_binary(left, operator, right, *span),
+            AstNode::DestructuringDeclaration { pattern, value, .. } | AstNode::DestructuringAssignment { pattern, value } => {
+                self.evaluate_destructuring(pattern, value)
+            }
+            AstNode::Assignment { target, value } => self.evaluate_assignment(target, value),
+            AstNode::CompoundAssignment { target, operator, value } => self.evaluate_compound_assignment(target, operator, value),
+            AstNode::UnaryExpression { operator, operand, span } => self.evaluate_unary(operator, operand, *span),
+            AstNode::Bool(value) => Ok(Value::Bool(*value)),
+            AstNode::StringLiteral(value) => Ok(Value::String(value.clone())),
+            AstNode::InterpolatedString(parts) => self.evaluate_interpolation(parts),
+            AstNode::NumberLiteral(value) => Ok(Value::Number(*value)),
+            AstNode::Variable { name, binding } => self.load(name, *binding),
+            AstNode::Identifier(name) => {
+                let binding = self.unresolved(name);
+                self.load(name, binding)
+            }
+            AstNode::ArrayLiteral(elements) => self.evaluate_array(elements),
+            AstNode::IndexExpression(array_node, index_node, span) => self.evaluate_index(array_node, index_node, *span),
+            AstNode::DictionaryLiteral(pairs) => self.evaluate_dictionary(pairs),
+            AstNode::IfStatement { condition, consequence, alternative } => {
+                self.evaluate_if(condition, consequence, alternative.as_deref())
+            }
+            AstNode::WhileStatement { condition, body } => self.evaluate_while(condition, body),
+            AstNode::Break => Err(InterpreterError::Break),
+            // the resolver turns declarations into assignments of the declared value
+            AstNode::FunctionDeclaration { name, params, body } => Ok(Value::Function {
+                name: name.clone(),
+                params: params.clone(),
+                body: body.clone(),
+            }),
+            AstNode::FunctionCall { function, arguments, span } => self.evaluate_call(function, arguments, *span),
+            AstNode::MethodCall { object, method, arguments, span } => {
+                self.evaluate_method_call(object, method, arguments, *span)
+            }
+            AstNode::ImplBlock { type_name, methods } => self.evaluate_impl(type_name, methods),
+            AstNode::Return(expr) => self.evaluate_return(expr.as_deref()),
+            AstNode::Block(statements) => self.evaluate_block(statements),
+            AstNode::Match { subject, arms, span } => self.evaluate_match(subject, arms, *span),
+            AstNode::StructDeclaration { name, fields } => Ok(Value::StructType {
+                name: name.clone(),
+                fields: fields.clone(),
+            }),
+            AstNode::StructLiteral { name, fields } => self.evaluate_struct_literal(name, fields),
+            AstNode::FieldAccess { object, field, span } => self.evaluate_field_access(object, field, *span),
+            AstNode::EnumDeclaration { name, variants } => Ok(Value::EnumType {
+                name: name.clone(),
+                variants: variants.iter().map(|(variant, fields)| (variant.clone(), fields.len())).collect(),
+            }),
+            AstNode::IsPattern { value, pattern } => self.evaluate_is(value, pattern),
+            AstNode::Throw(expr, span) => self.evaluate_throw(expr, *span),
+            AstNode::TryStatement { body, catch, finally } => self.evaluate_try(body, catch, finally),
+            AstNode::Propagate(expr) => self.evaluate_propagate(expr),
             AstNode::Nil => Ok(Value::Nil),
         }
     }
@@ -1,33 +1,99 @@
-use crate::token::Token;
+use std::fmt;
+use std::str::CharIndices;
+use crate::token::{Span, SpannedToken, StringPart, Token};
 
-pub struct Lexer {
-    input: String,
+// Walks the source once by byte offset; `chars` always points just past `current_char`
+pub struct Lexer<'a> {
+    input: &'a str,
+    chars: CharIndices<'a>,
     position: usize,
     current_char: Option<char>, 
+    line: usize,
+    column: usize,
+    finished: bool,
 }
 
-impl Lexer {
-    pub fn new(input: String) -> Self {
-        let lexer = Self {
-            position: 0,
-            current_char: input.chars().next(),
+#[derive(Debug, Clone, PartialEq)]
+pub struct LexError {
+    pub message: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Lex error at line {}, column {}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(input: &'a str) -> Self {
+        let mut chars = input.char_indices();
+        let current_char = chars.next().map(|(_, c)| c);
+        Self {
             input,
-        };
-        lexer
+            chars,
+            position: 0,
+            current_char,
+            line: 1,
+            column: 1,
+            finished: false,
+        }
     }
 
     pub fn advance(&mut self) {
-        self.position += 1;
-        self.current_char = self.input.chars().nth(self.position);
+        if self.current_char == Some('\n') {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        match self.chars.next() {
+            Some((offset, c)) => {
+                self.position = offset;
+                self.current_char = Some(c);
+            }
+            None => {
+                self.position = self.input.len();
+                self.current_char = None;
+            }
+        }
+    }
+
+    fn error_at(&self, line: usize, column: usize, message: String) -> LexError {
+        LexError { message, line, column }
     }
 
     // peeks at the next character without consuming it
     pub fn peek(&self) -> Option<char> {
-        self.input.chars().nth(self.position + 1)
+        self.chars.clone().next().map(|(_, c)| c)
+    }
+
+    // Picks the compound assignment form of an operator when it is followed by '='
+    fn with_assign(&mut self, plain: Token, assign: Token) -> Token {
+        if self.current_char == Some('=') {
+            self.advance();
+            assign
+        } else {
+            plain
+        }
+    }
+
+    // Lexes the next token along with its byte range and starting line/column
+    pub fn next_spanned(&mut self) -> Result<SpannedToken, LexError> {
+        while matches!(self.current_char, Some(' ' | '\t' | '\n' | '\r')) {
+            self.advance();
+        }
+        let (start, line, column) = (self.position, self.line, self.column);
+        let token = self.next_token()?;
+        Ok(SpannedToken {
+            token,
+            span: Span { start, end: self.position, line, column },
+        })
     }
 
 
-    pub fn next_token(&mut self) -> Token {
+    pub fn next_token(&mut self) -> Result<Token, LexError> {
         while let Some(c) = self.current_char {
             match c {
                 // skip whitespace
@@ -39,119 +105,308 @@ impl Lexer {
                         // move forward twice to consume both '='
                         self.advance();
                         self.advance(); 
-                        return Token::Equal; 
+                        return Ok(Token::Equal); 
+                    }
+                    if self.peek() == Some('>') {
+                        self.advance();
+                        self.advance();
+                        return Ok(Token::FatArrow);
                     }
                     self.advance(); 
-                    return Token::Assign; 
+                    return Ok(Token::Assign); 
                 }
                 '+' => {
                     self.advance();
-                    return Token::Plus;
+                    return Ok(match self.current_char {
+                        Some('=') => { self.advance(); Token::PlusAssign }
+                        Some('+') => { self.advance(); Token::Increment }
+                        _ => Token::Plus,
+                    });
                 }
                 '-' => {
                     self.advance();
-                    return Token::Minus;
+                    return Ok(match self.current_char {
+                        Some('=') => { self.advance(); Token::MinusAssign }
+                        Some('-') => { self.advance(); Token::Decrement }
+                        _ => Token::Minus,
+                    });
                 }
                 '*' => {
                     self.advance();
-                    return Token::Multiply;
+                    return Ok(self.with_assign(Token::Multiply, Token::MultiplyAssign));
                 }
                 '/' => {
                     self.advance();
-                    return Token::Divide;
+                    return Ok(self.with_assign(Token::Divide, Token::DivideAssign));
+                }
+                '%' => {
+                    self.advance();
+                    return Ok(self.with_assign(Token::Modulo, Token::ModuloAssign));
                 }
                 '<' => {
                     self.advance();
-                    return Token::LessThan;
+                    return Ok(Token::LessThan);
                 }
                 '>' => {
                     self.advance();
-                    return Token::GreaterThan;
+                    return Ok(Token::GreaterThan);
                 }
                 '(' => {
                     self.advance();
-                    return Token::LeftParen;
+                    return Ok(Token::LeftParen);
                 }
                 ')' => {
                     self.advance();
-                    return Token::RightParen;
+                    return Ok(Token::RightParen);
                 }
                 '[' => {
                     self.advance();
-                    return Token::LeftBracket;
+                    return Ok(Token::LeftBracket);
                 }
                 ']' => {
                     self.advance();
-                    return Token::RightBracket;
+                    return Ok(Token::RightBracket);
                 }
                 ',' => {
                     self.advance();
-                    return Token::Comma;
+                    return Ok(Token::Comma);
                 }
                 '{' =>{
                     self.advance();
-                    return Token::LeftCurly;
+                    return Ok(Token::LeftCurly);
                 }
                 '}' =>{
                     self.advance();
-                    return Token::RightCurly;
+                    return Ok(Token::RightCurly);
                 }
                 ':' =>{
                     self.advance();
-                    return Token::Colon;
+                    return Ok(Token::Colon);
                 }
                 ';' =>{
                     self.advance();
-                    return Token::Semicolon;
+                    return Ok(Token::Semicolon);
+                }
+                '.' if self.peek() == Some('.') => {
+                    self.advance();
+                    self.advance();
+                    return Ok(match self.current_char {
+                        Some('.') => { self.advance(); Token::Ellipsis }
+                        Some('=') => { self.advance(); Token::DotDotEqual }
+                        _ => Token::DotDot,
+                    });
+                }
+                '.' => {
+                    self.advance();
+                    return Ok(Token::Dot);
+                }
+                '|' => {
+                    self.advance();
+                    return Ok(Token::Pipe);
+                }
+                '?' => {
+                    self.advance();
+                    return Ok(Token::Question);
                 }
                 '"' => return self.string_literal(),
                 '0'..='9' => return self.number(),
-                'a'..='z' | 'A'..='Z' | '_' => return self.identifier_or_keyword().expect("Some issue going on with creating a string?"),
+                'a'..='z' | 'A'..='Z' | '_' => return Ok(self.identifier_or_keyword().expect("Some issue going on with creating a string?")),
 
                 _ => {
                     self.advance();
-                    return Token::Unknown(c);
+                    return Ok(Token::Unknown(c));
                 }
 
             }
         }
-        Token::Eof
+        Ok(Token::Eof)
     }
 
-    pub fn string_literal(&mut self) -> Token {
+    pub fn string_literal(&mut self) -> Result<Token, LexError> {
+        let (line, column) = (self.line, self.column);
         self.advance();
+        let mut parts = Vec::new();
         let mut value = String::new();
         while let Some(c) = self.current_char {
-            if c == '"' {
+            match c {
+                '"' => {
+                    self.advance();
+                    if parts.is_empty() {
+                        return Ok(Token::StringLiteral(value));
+                    }
+                    if !value.is_empty() {
+                        parts.push(StringPart::Literal(value));
+                    }
+                    return Ok(Token::InterpolatedString(parts));
+                }
+                // '{{' and '}}' are escaped braces
+                '{' | '}' if self.peek() == Some(c) => {
+                    value.push(c);
+                    self.advance();
+                    self.advance();
+                }
+                '{' => {
+                    if !value.is_empty() {
+                        parts.push(StringPart::Literal(std::mem::take(&mut value)));
+                    }
+                    parts.push(self.interpolation()?);
+                }
+                _ => {
+                    value.push(c);
+                    self.advance();
+                }
+            }
+        }
+        Err(self.error_at(line, column, "Unterminated string literal".to_string()))
+    }
+
+    // Lexes the `{expr:spec}` part of an interpolated string in place, so its tokens keep their
+    // positions in the source. A ':' outside any brackets starts the format spec
+    fn interpolation(&mut self) -> Result<StringPart, LexError> {
+        let (line, column) = (self.line, self.column);
+        self.advance();
+        let mut tokens = Vec::new();
+        let mut format = None;
+        let mut depth: usize = 0;
+        let unclosed = |lexer: &Lexer| {
+            lexer.error_at(line, column, "Unclosed '{' in interpolated string".to_string())
+        };
+
+        loop {
+            while matches!(self.current_char, Some(' ' | '\t' | '\n' | '\r')) {
                 self.advance();
-                return Token::StringLiteral(value);
-            } else {
-                value.push(c);
+            }
+            match self.current_char {
+                None => return Err(unclosed(self)),
+                Some('}') if depth == 0 => break,
+                _ => {}
+            }
+            // a string left open inside the hole most likely means the hole was never closed
+            let at_quote = self.current_char == Some('"');
+            let token = self.next_spanned().map_err(|e| {
+                if at_quote && self.current_char.is_none() { unclosed(self) } else { e }
+            })?;
+            match token.token {
+                Token::LeftParen | Token::LeftBracket | Token::LeftCurly => depth += 1,
+                Token::RightParen | Token::RightBracket | Token::RightCurly => depth = depth.saturating_sub(1),
+                Token::Colon if depth == 0 => {
+                    let mut spec = String::new();
+                    while let Some(c) = self.current_char {
+                        if c == '}' || c == '"' {
+                            break;
+                        }
+                        spec.push(c);
+                        self.advance();
+                    }
+                    if self.current_char != Some('}') {
+                        return Err(unclosed(self));
+                    }
+                    format = Some(spec);
+                    break;
+                }
+                _ => {}
+            }
+            tokens.push(token);
+        }
+
+        if tokens.is_empty() {
+            return Err(self.error_at(line, column, "Empty expression in interpolated string".to_string()));
+        }
+        // the expression ends at the closing brace
+        let span = Span { start: self.position, end: self.position, line: self.line, column: self.column };
+        tokens.push(SpannedToken { token: Token::Eof, span });
+        self.advance();
+
+        Ok(StringPart::Expression { tokens, format })
+    }
+
+    pub fn number(&mut self) -> Result<Token, LexError> {
+        let (line, column) = (self.line, self.column);
+
+        let radix = match (self.current_char, self.peek()) {
+            (Some('0'), Some('x' | 'X')) => Some(16),
+            (Some('0'), Some('b' | 'B')) => Some(2),
+            (Some('0'), Some('o' | 'O')) => Some(8),
+            _ => None,
+        };
+
+        let value = match radix {
+            Some(radix) => {
                 self.advance();
+                self.advance();
+                let digits = self.digits(radix, line, column)?;
+                if digits.is_empty() {
+                    return Err(self.error_at(line, column, "Expected digits after numeric prefix".to_string()));
+                }
+                u64::from_str_radix(&digits, radix)
+                    .map_err(|_| self.error_at(line, column, format!("Numeric literal out of range: {}", digits)))?
+                    as f64
+            }
+            None => {
+                let mut number = self.digits(10, line, column)?;
+
+                if self.current_char == Some('.') && self.peek() != Some('.') {
+                    self.advance();
+                    match self.current_char {
+                        Some(c) if c.is_ascii_digit() => {}
+                        _ => return Err(self.error_at(line, column, "Expected digit after decimal point".to_string())),
+                    }
+                    number.push('.');
+                    number.push_str(&self.digits(10, line, column)?);
+                }
+
+                if let Some(e @ ('e' | 'E')) = self.current_char {
+                    number.push(e);
+                    self.advance();
+                    if let Some(sign @ ('+' | '-')) = self.current_char {
+                        number.push(sign);
+                        self.advance();
+                    }
+                    match self.current_char {
+                        Some(c) if c.is_ascii_digit() => {}
+                        _ => return Err(self.error_at(line, column, "Expected digit in exponent".to_string())),
+                    }
+                    number.push_str(&self.digits(10, line, column)?);
+                }
+
+                number
+                    .parse::<f64>()
+                    .map_err(|_| self.error_at(line, column, format!("Invalid numeric literal: {}", number)))?
+            }
+        };
+
+        // a literal must not run straight into letters or another decimal point (e.g. 12abc, 1.2.3)
+        match self.current_char {
+            Some('.') if self.peek().is_some_and(|c| c.is_ascii_digit()) => {
+                Err(self.error_at(line, column, "Unexpected '.' in numeric literal".to_string()))
+            }
+            Some(c) if c.is_alphanumeric() || c == '_' => {
+                Err(self.error_at(line, column, format!("Invalid character '{}' in numeric literal", c)))
             }
+            _ => Ok(Token::Number(value)),
         }
-        // If we reach here, string wasn't closed properly
-        // can also return an error
-        Token::Unknown('"')
     }
 
-    pub fn number(&mut self) -> Token {
-        let mut number = String::new();
-        let mut has_decimal = false;
-    
+    // Reads digits of the given radix, dropping '_' separators which must sit between two digits
+    fn digits(&mut self, radix: u32, line: usize, column: usize) -> Result<String, LexError> {
+        let mut digits = String::new();
         while let Some(c) = self.current_char {
-            if c.is_numeric() {
-                number.push(c);
+            if c.is_digit(radix) {
+                digits.push(c);
                 self.advance();
-            } else if c == '.' && !has_decimal {
-                has_decimal = true;
-                number.push(c);
+            } else if c == '_' {
+                let next_is_digit = self.peek().is_some_and(|n| n.is_digit(radix));
+                if digits.is_empty() || !next_is_digit {
+                    return Err(self.error_at(line, column, "Misplaced '_' in numeric literal".to_string()));
+                }
                 self.advance();
+            } else if c.is_ascii_digit() {
+                return Err(self.error_at(line, column, format!("Invalid digit '{}' for base {} literal", c, radix)));
             } else {
                 break;
             }
         }
-        Token::Number(number.parse::<f64>().unwrap())
+        Ok(digits)
     }
 
     pub fn identifier_or_keyword(&mut self) -> Option<Token> {
@@ -166,6 +421,16 @@ impl Lexer {
         }
         Some(match identifier.as_str() {
             "let" => Token::Let,
+            "const" => Token::Const,
+            "match" => Token::Match,
+            "struct" => Token::Struct,
+            "impl" => Token::Impl,
+            "enum" => Token::Enum,
+            "is" => Token::Is,
+            "throw" => Token::Throw,
+            "try" => Token::Try,
+            "catch" => Token::Catch,
+            "finally" => Token::Finally,
             "print" => Token::Print,
             "true" => Token::True,
             "false" => Token::False,
@@ -185,4 +450,18 @@ impl Lexer {
 
 }
 
+impl Iterator for Lexer<'_> {
+    type Item = Result<SpannedToken, LexError>;
 
+    // Yields every token including the final Eof, and stops after Eof or the first error
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        let result = self.next_spanned();
+        if matches!(result, Ok(SpannedToken { token: Token::Eof, .. }) | Err(_)) {
+            self.finished = true;
+        }
+        Some(result)
+    }
+}
@@ -4,67 +4,139 @@ mod lexer;
 mod parser;
 mod value;
 mod interpreter;
+mod resolver;
+mod optimizer;
+mod limits;
+mod output;
+mod capabilities;
+mod builtins;
+mod compiler;
+mod vm;
 
 pub use interpreter::Interpreter;
-pub use lexer::Lexer;
-pub use parser::Parser;
-pub use ast::AstNode;
-pub use token::Token;
-pub use interpreter::InterpreterError;
+pub use lexer::{LexError, Lexer};
+pub use parser::{ParseError, ParseOutput, Parser};
+pub use ast::{AstNode, Binding, Pattern};
+pub use token::{Span, SpannedToken, Token};
+pub use interpreter::{CallFrame, InterpreterError, InterruptHandle, Source, Traceback, DEFAULT_MAX_CALL_DEPTH};
+pub use resolver::{GlobalScope, ResolveOutput, Resolver};
+pub use optimizer::Optimizer;
+pub use limits::{LimitError, Limits};
+pub use output::{CapturedOutput, Output, Sink};
+pub use capabilities::{Capabilities, Capability};
+pub use compiler::Compiler;
+pub use vm::Vm;
+
+use std::io::Write;
+
+fn tokenize(source: &str) -> Result<Vec<SpannedToken>, String> {
+    Lexer::new(source)
+        .map(|result| result.map_err(|e| e.to_string()))
+        .collect()
+}
+
+fn format_parse_errors(errors: &[ParseError]) -> String {
+    errors.iter().map(|e| e.to_string()).collect::<Vec<_>>().join("\n")
+}
 
 pub fn run_source(source: String) -> Result<(), String> {
-    let mut lexer = Lexer::new(source);
-    let mut tokens = Vec::new();
-    
-    loop {
-        let token = lexer.next_token();
-        tokens.push(token.clone());
-        if token == Token::Eof { 
-            break; 
-        }
-    }
-    
-    let mut parser = Parser::new(tokens);
-    let ast = parser.parse_program()?;
-    
     let mut interpreter = Interpreter::new();
-    interpreter.evaluate(&ast).map_err(|e| match e {
-        InterpreterError::Break => "Break statement outside of loop".to_string(),
-        InterpreterError::Return(val) => format!("Return statement outside of function: {:?}", val),
-        InterpreterError::Error(msg) => msg,
-    })?;
+    run_source_with(source, &mut interpreter)
+}
+
+// Parses a whole program, writing any warnings to `warnings`
+fn parse_source(source: &str, warnings: &mut dyn Write) -> Result<AstNode, String> {
+    let tokens = tokenize(source)?;
+
+    let mut parser = Parser::new(tokens);
+    let output = parser.parse_program_with_diagnostics();
+    if !output.errors.is_empty() {
+        return Err(format_parse_errors(&output.errors));
+    }
+    for warning in &output.warnings {
+        // a warning that can't be written is not worth failing the program for
+        let _ = writeln!(warnings, "Warning at line {}, column {}: {}", warning.span.line, warning.span.column, warning.message);
+    }
+    Ok(output.program)
+}
+
+// Binds the program's variables to slots, writing any warnings to the interpreter's stderr, then optimizes it if enabled.
+// Optimizing after resolving keeps declarations in pruned code visible to the resolver
+fn resolve(ast: AstNode, interpreter: &mut Interpreter) -> Result<AstNode, String> {
+    let output = interpreter.resolve(ast);
+    report(&output, interpreter.stderr())?;
+    if interpreter.optimizes() {
+        return Ok(Optimizer::new().optimize_program(output.program));
+    }
+    Ok(output.program)
+}
+
+// Writes the resolver's warnings to `warnings`, failing with its errors if there are any
+fn report(output: &ResolveOutput, warnings: &mut dyn Write) -> Result<(), String> {
+    for warning in &output.warnings {
+        let _ = writeln!(warnings, "Warning: {}", warning);
+    }
+    if !output.errors.is_empty() {
+        return Err(output.errors.join("\n"));
+    }
     Ok(())
 }
 
-pub fn run_line(source: String, interpreter: &mut Interpreter) -> Result<(), String> {
-    let mut lexer = Lexer::new(source);
-    let mut tokens = Vec::new();
-
-    loop {
-        let token = lexer.next_token();
-        if token == Token::Eof {
-            tokens.push(token);
-            break;
-        }
-        tokens.push(token);
+// Runs a program on an existing interpreter, keeping its settings, globals and output sinks,
+// e.g. one made with Interpreter::with_captured_output()
+pub fn run_source_with(source: String, interpreter: &mut Interpreter) -> Result<(), String> {
+    let ast = parse_source(&source, interpreter.stderr())?;
+    let ast = resolve(ast, interpreter)?;
+    interpreter.evaluate(&ast).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// Runs a program on the bytecode VM instead of the tree-walking interpreter
+pub fn run_source_vm(source: String, vm: &mut Vm) -> Result<(), String> {
+    let mut ast = parse_source(&source, vm.stderr())?;
+    let output = vm.check(&ast);
+    report(&output, vm.stderr())?;
+    if vm.optimizes() {
+        ast = Optimizer::new().optimize_program(ast);
     }
+    let program = Compiler::new().compile_program(&ast)?;
+    vm.run(program).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+pub fn run_line(source: String, interpreter: &mut Interpreter) -> Result<(), String> {
+    let tokens = tokenize(&source)?;
 
     let mut parser = Parser::new(tokens);
     let mut ast_nodes = Vec::new();
 
     while parser.current_token() != &Token::Eof {
+        // the trailing semicolon is optional on the last statement of a line
+        if *parser.current_token() == Token::Semicolon {
+            parser.advance();
+            continue;
+        }
         match parser.parse_statement() {
             Ok(ast) => ast_nodes.push(ast),
-            Err(err) => return Err(format!("Parsing error: {}", err)),
+            Err(message) => {
+                let span = parser.error_span();
+                let mut errors = parser.take_errors();
+                errors.push(ParseError { message, span });
+                return Err(format_parse_errors(&errors));
+            }
         }
     }
 
-    let program_ast = AstNode::Program(ast_nodes);
+    let errors = parser.take_errors();
+    if !errors.is_empty() {
+        return Err(format_parse_errors(&errors));
+    }
+
+    let program_ast = resolve(AstNode::Program(ast_nodes), interpreter)?;
 
     match interpreter.evaluate(&program_ast) {
         Ok(_) => Ok(()),
-        Err(InterpreterError::Break) => Err("Break statement outside of loop".to_string()),
-        Err(InterpreterError::Return(val)) => Err(format!("Return statement outside of function: {:?}", val)),
-        Err(InterpreterError::Error(msg)) => Err(format!("Runtime error: {}", msg)),
+        Err(e @ (InterpreterError::Error(_) | InterpreterError::Throw(_))) => Err(format!("Runtime error: {}", e)),
+        Err(e) => Err(e.to_string()),
     }
 }
\ No newline at end of file
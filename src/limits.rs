@@ -0,0 +1,174 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+use crate::token::Token;
+use crate::value::Value;
+
+// Caps on the work a program may do, for running untrusted scripts. None means unlimited
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Limits {
+    // evaluation steps: nodes evaluated by the interpreter or instructions run by the VM
+    pub max_steps: Option<u64>,
+    // wall-clock time for one program, measured from when it starts running
+    pub timeout: Option<Duration>,
+    // approximate size of any one value: bytes of its strings and dictionary keys plus its
+    // elements, entries and fields, counted through nested values
+    pub max_collection_size: Option<usize>,
+    // bytes written by print, including the newlines
+    pub max_output_bytes: Option<usize>,
+}
+
+// A limit a program ran into. Scripts cannot catch these, so the host always sees them
+#[derive(Debug, Clone, PartialEq)]
+pub enum LimitError {
+    Steps(u64),
+    Timeout(Duration),
+    CollectionSize(usize),
+    Output(usize),
+}
+
+impl fmt::Display for LimitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LimitError::Steps(max) => write!(f, "Step limit of {} exceeded", max),
+            LimitError::Timeout(timeout) => write!(f, "Time limit of {:?} exceeded", timeout),
+            LimitError::CollectionSize(max) => write!(f, "Collection size limit of {} exceeded", max),
+            LimitError::Output(max) => write!(f, "Output limit of {} bytes exceeded", max),
+        }
+    }
+}
+
+// Reading the clock on every step would slow evaluation down, so it is read every this many steps
+const DEADLINE_CHECK_INTERVAL: u64 = 1024;
+
+// The limits of a running program and how much of them it has used
+#[derive(Debug, Default)]
+pub(crate) struct Budget {
+    limits: Limits,
+    steps: u64,
+    deadline: Option<Instant>,
+    output_bytes: usize,
+}
+
+impl Budget {
+    pub(crate) fn new(limits: Limits) -> Self {
+        Self { limits, ..Self::default() }
+    }
+
+    // Whether any limit is set; without one nothing needs tracking
+    #[inline]
+    pub(crate) fn is_limited(&self) -> bool {
+        self.limits != Limits::default()
+    }
+
+    pub(crate) fn max_collection_size(&self) -> Option<usize> {
+        self.limits.max_collection_size
+    }
+
+    // Called when a program starts; every program gets the full budget
+    pub(crate) fn start(&mut self) {
+        self.steps = 0;
+        self.output_bytes = 0;
+        self.deadline = self.limits.timeout.map(|timeout| Instant::now() + timeout);
+    }
+
+    #[inline]
+    pub(crate) fn step(&mut self) -> Result<(), LimitError> {
+        if self.limits.max_steps.is_none() && self.deadline.is_none() {
+            return Ok(());
+        }
+        self.steps += 1;
+        if let Some(max) = self.limits.max_steps {
+            if self.steps > max {
+                return Err(LimitError::Steps(max));
+            }
+        }
+        if let (Some(deadline), Some(timeout)) = (self.deadline, self.limits.timeout) {
+            if self.steps.is_multiple_of(DEADLINE_CHECK_INTERVAL) && Instant::now() >= deadline {
+                return Err(LimitError::Timeout(timeout));
+            }
+        }
+        Ok(())
+    }
+
+    #[inline]
+    pub(crate) fn check_size(&self, value: &Value) -> Result<(), LimitError> {
+        match self.limits.max_collection_size {
+            Some(max) => check_len(Some(max), deep_size(value, max)),
+            None => Ok(()),
+        }
+    }
+
+    // Checks the size of `left operator right` before it is built, for the operators whose
+    // result can be far larger than their operands
+    pub(crate) fn check_binary(&self, operator: &Token, left: &Value, right: &Value) -> Result<(), LimitError> {
+        let Some(max) = self.limits.max_collection_size else {
+            return Ok(());
+        };
+        let size = match (operator, left, right) {
+            (Token::Multiply, Value::String(s), Value::Number(n)) | (Token::Multiply, Value::Number(n), Value::String(s)) => {
+                s.len().saturating_mul(n.max(0.0) as usize)
+            }
+            (Token::Plus, Value::String(_), _) | (Token::Plus, _, Value::String(_)) => {
+                deep_size(left, max).saturating_add(deep_size(right, max))
+            }
+            _ => return Ok(()),
+        };
+        check_len(Some(max), size)
+    }
+
+    // Accounts for text about to be printed; nothing is printed once the limit would be passed
+    pub(crate) fn write_output(&mut self, bytes: usize) -> Result<(), LimitError> {
+        self.output_bytes += bytes;
+        match self.limits.max_output_bytes {
+            Some(max) if self.output_bytes > max => Err(LimitError::Output(max)),
+            _ => Ok(()),
+        }
+    }
+}
+
+// The size `max_collection_size` limits. Counting stops once it passes `max`, so checking a
+// value costs no more than the limit allows it to hold
+pub(crate) fn deep_size(value: &Value, max: usize) -> usize {
+    let mut size = 0usize;
+    // nested values are walked without recursion, since they can be nested arbitrarily deep
+    let mut pending = vec![value];
+    while let Some(value) = pending.pop() {
+        match value {
+            Value::String(s) => size = size.saturating_add(s.len()),
+            Value::Array(items) | Value::Enum { values: items, .. } => {
+                size = size.saturating_add(items.len());
+                if size > max {
+                    break;
+                }
+                pending.extend(items);
+            }
+            Value::Dictionary(dict) => {
+                size = size.saturating_add(dict.len());
+                if size > max {
+                    break;
+                }
+                for (key, value) in dict {
+                    size += key.len();
+                    pending.push(value);
+                }
+            }
+            Value::Struct { fields, .. } => {
+                size = size.saturating_add(fields.len());
+                pending.extend(fields.iter().map(|(_, value)| value));
+            }
+            _ => {}
+        }
+        if size > max {
+            break;
+        }
+    }
+    size
+}
+
+// For checks made while the budget itself can't be borrowed, e.g. when growing a dictionary in place
+pub(crate) fn check_len(max: Option<usize>, len: usize) -> Result<(), LimitError> {
+    match max {
+        Some(max) if len > max => Err(LimitError::CollectionSize(max)),
+        _ => Ok(()),
+    }
+}
@@ -1,16 +1,168 @@
 use std::env;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process;
+use std::thread;
+use std::time::Duration;
+
+// Native stack reserved for the interpreter thread, on top of what each nested call needs
+const BASE_STACK_SIZE: usize = 16 * 1024 * 1024;
+const STACK_PER_CALL: usize = 32 * 1024;
 
 fn print_usage() {
     eprintln!("Usage: alethia <command> [args]");
     eprintln!("Commands:");
-    eprintln!("   run <file>    Run an .at file");
-    eprintln!("   repl          Start an interactive REPL session");
+    eprintln!("   run [options] <file>         Run an .at file");
+    eprintln!("   repl [permissions]           Start an interactive REPL session");
+    eprintln!("Run options:");
+    eprintln!("   --strict                     Error on assignment to undeclared variables");
+    eprintln!("   --vm                         Compile to bytecode and run it on the virtual machine");
+    eprintln!("   -O0, -O1                     Disable (default) or enable the AST optimizer");
+    eprintln!("   --max-depth <n>              Maximum call depth before a stack overflow error (default {})", alethia::DEFAULT_MAX_CALL_DEPTH);
+    eprintln!("   --max-steps <n>              Stop after n evaluation steps");
+    eprintln!("   --timeout <time>             Stop after a time such as 2s, 1.5s or 500ms");
+    eprintln!("   --max-collection-size <n>    Maximum size of a value: its elements and string bytes, nested ones included");
+    eprintln!("   --max-output <bytes>         Maximum bytes printed");
+    eprintln!("Permissions (everything is denied by default):");
+    eprintln!("   --allow-read=<dirs>          Read files under the comma-separated directories");
+    eprintln!("   --allow-write=<dirs>         Create, change and remove files under the directories");
+    eprintln!("   --allow-env                  Read environment variables");
+    eprintln!("   --allow-process              Run other programs");
+    eprintln!("   --allow-clock                Read the current time");
+    eprintln!("   --allow-random               Generate random numbers");
+    eprintln!("   --allow-all                  Grant everything");
+}
+
+struct RunOptions {
+    strict: bool,
+    vm: bool,
+    optimize: bool,
+    max_depth: usize,
+    limits: alethia::Limits,
+    capabilities: alethia::Capabilities,
+}
+
+impl Default for RunOptions {
+    fn default() -> Self {
+        Self {
+            strict: false,
+            vm: false,
+            optimize: false,
+            max_depth: alethia::DEFAULT_MAX_CALL_DEPTH,
+            limits: alethia::Limits::default(),
+            capabilities: alethia::Capabilities::default(),
+        }
+    }
+}
+
+// The value following an option such as `--max-steps 1000`
+fn parse_number<T: std::str::FromStr>(option: &str, value: Option<&String>) -> Result<T, String> {
+    value
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| format!("{} expects a number", option))
+}
+
+// `2s`, `1.5s` or `500ms`; a bare number is seconds
+fn parse_duration(value: Option<&String>) -> Result<Duration, String> {
+    let value = value.ok_or("--timeout expects a time such as 2s or 500ms")?;
+    let (number, unit) = match value.strip_suffix("ms") {
+        Some(number) => (number, 0.001),
+        None => (value.strip_suffix('s').unwrap_or(value), 1.0),
+    };
+    number
+        .parse::<f64>()
+        .ok()
+        .and_then(|number| Duration::try_from_secs_f64(number * unit).ok())
+        .ok_or_else(|| format!("Invalid time for --timeout: {}", value))
 }
 
-fn run_file(path: &Path) -> Result<(), String> {
+// Applies a permission flag such as `--allow-read=./data`. Returns false if `arg` isn't one
+fn parse_permission(arg: &str, capabilities: &mut alethia::Capabilities) -> Result<bool, String> {
+    let directories = |value: &str| value.split(',').filter(|dir| !dir.is_empty()).map(PathBuf::from).collect::<Vec<_>>();
+    match arg.split_once('=') {
+        Some(("--allow-read", dirs)) => capabilities.read.extend(directories(dirs)),
+        Some(("--allow-write", dirs)) => capabilities.write.extend(directories(dirs)),
+        None if arg == "--allow-read" || arg == "--allow-write" => {
+            return Err(format!("{} expects directories, e.g. {}=./data", arg, arg));
+        }
+        _ => match arg {
+            "--allow-env" => capabilities.env = true,
+            "--allow-process" => capabilities.process = true,
+            "--allow-clock" => capabilities.clock = true,
+            "--allow-random" => capabilities.random = true,
+            "--allow-all" => *capabilities = alethia::Capabilities::all(),
+            _ => return Ok(false),
+        },
+    }
+    Ok(true)
+}
+
+fn parse_run_args(args: &[String]) -> Result<(RunOptions, &Path), String> {
+    let mut options = RunOptions::default();
+    let mut file = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--max-depth" => options.max_depth = parse_number(arg, args.next())?,
+            "--max-steps" => options.limits.max_steps = Some(parse_number(arg, args.next())?),
+            "--timeout" => options.limits.timeout = Some(parse_duration(args.next())?),
+            "--max-collection-size" => options.limits.max_collection_size = Some(parse_number(arg, args.next())?),
+            "--max-output" => options.limits.max_output_bytes = Some(parse_number(arg, args.next())?),
+            "--strict" => options.strict = true,
+            "--vm" => options.vm = true,
+            "-O0" => options.optimize = false,
+            "-O1" => options.optimize = true,
+            flag if parse_permission(flag, &mut options.capabilities)? => {}
+            flag if flag.starts_with('-') => return Err(format!("Unknown option: {}", flag)),
+            path => {
+                if file.is_some() {
+                    return Err("Expected a single file to run".to_string());
+                }
+                file = Some(Path::new(path));
+            }
+        }
+    }
+
+    match file {
+        Some(path) => Ok((options, path)),
+        None => Err("Missing file to run".to_string()),
+    }
+}
+
+fn parse_repl_args(args: &[String]) -> Result<alethia::Capabilities, String> {
+    let mut capabilities = alethia::Capabilities::default();
+    for arg in args {
+        if !parse_permission(arg, &mut capabilities)? {
+            return Err(format!("Unknown option: {}", arg));
+        }
+    }
+    Ok(capabilities)
+}
+
+// Every call in a script nests native calls of the tree-walking interpreter, so scripts run on
+// a thread with a stack large enough for the maximum call depth
+fn stack_size(max_depth: usize) -> usize {
+    BASE_STACK_SIZE.saturating_add(max_depth.saturating_mul(STACK_PER_CALL))
+}
+
+fn with_large_stack(stack_size: usize, run: impl FnOnce() -> Result<(), String> + Send + 'static) -> Result<(), String> {
+    thread::Builder::new()
+        .stack_size(stack_size)
+        .spawn(run)
+        .map_err(|e| format!("Cannot start interpreter thread: {}", e))?
+        .join()
+        .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+}
+
+// Shows where an uncaught exception was raised, ahead of its message
+fn print_traceback(interpreter: &mut alethia::Interpreter) {
+    if let Some(traceback) = interpreter.take_traceback() {
+        eprint!("{}", traceback);
+    }
+}
+
+fn run_file(path: &Path, options: RunOptions) -> Result<(), String> {
     if !path.exists() {
         return Err(format!("File not found: {}", path.display()));
     }
@@ -20,16 +172,167 @@ fn run_file(path: &Path) -> Result<(), String> {
     }
 
     match fs::read_to_string(path) {
-        Ok(contents) => alethia::run_source(contents),
+        Ok(contents) if options.vm => {
+            let mut vm = alethia::Vm::new();
+            vm.set_strict(options.strict);
+            vm.set_optimize(options.optimize);
+            vm.set_max_call_depth(options.max_depth);
+            vm.set_limits(options.limits);
+            vm.set_capabilities(options.capabilities);
+            alethia::run_source_vm(contents, &mut vm)
+        }
+        Ok(contents) => with_large_stack(stack_size(options.max_depth), move || {
+            let mut interpreter = alethia::Interpreter::new();
+            interpreter.set_strict(options.strict);
+            interpreter.set_optimize(options.optimize);
+            interpreter.set_max_call_depth(options.max_depth);
+            interpreter.set_native_stack_size(stack_size(options.max_depth));
+            interpreter.set_limits(options.limits);
+            interpreter.set_capabilities(options.capabilities);
+            let result = alethia::run_source_with(contents, &mut interpreter);
+            print_traceback(&mut interpreter);
+            result
+        }),
         Err(e) => Err(format!("Error reading file: {}", e)),
     }
 }
 
-fn run_repl() -> Result<(), String> {
+// Ctrl+C in the REPL interrupts the running statement instead of ending the session
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+mod sigint {
+    use std::ffi::c_int;
+    use std::sync::OnceLock;
+
+    const SIGINT: c_int = 2;
+
+    // the signal handler can only reach the interpreter through a static
+    static HANDLE: OnceLock<alethia::InterruptHandle> = OnceLock::new();
+
+    // sigset_t and struct sigaction as the C library lays them out
+    #[cfg(target_os = "linux")]
+    mod sys {
+        use std::ffi::c_int;
+
+        pub const SIG_BLOCK: c_int = 0;
+        pub const SIG_UNBLOCK: c_int = 1;
+
+        pub type SigSet = [u64; 16];
+
+        pub fn only(signal: c_int) -> SigSet {
+            let mut set = [0; 16];
+            set[0] = 1 << (signal - 1);
+            set
+        }
+
+        #[repr(C)]
+        pub struct SigAction {
+            pub handler: usize,
+            pub mask: SigSet,
+            pub flags: c_int,
+            pub restorer: usize,
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    mod sys {
+        use std::ffi::c_int;
+
+        pub const SIG_BLOCK: c_int = 1;
+        pub const SIG_UNBLOCK: c_int = 2;
+
+        pub type SigSet = u32;
+
+        pub fn only(signal: c_int) -> SigSet {
+            1 << (signal - 1)
+        }
+
+        #[repr(C)]
+        pub struct SigAction {
+            pub handler: usize,
+            pub mask: SigSet,
+            pub flags: c_int,
+        }
+    }
+
+    unsafe extern "C" {
+        fn sigaction(signum: c_int, action: *const sys::SigAction, old: *mut sys::SigAction) -> c_int;
+        fn pthread_sigmask(how: c_int, set: *const sys::SigSet, old: *mut sys::SigSet) -> c_int;
+    }
+
+    extern "C" fn on_sigint(_: c_int) {
+        if let Some(handle) = HANDLE.get() {
+            handle.interrupt();
+        }
+    }
+
+    // Keeps Ctrl+C away from the calling thread, which passes it on to the threads it starts
+    // until one of them installs the handler
+    pub fn block() {
+        // SAFETY: the set is a valid sigset_t and the old mask isn't asked for
+        unsafe {
+            pthread_sigmask(sys::SIG_BLOCK, &sys::only(SIGINT), std::ptr::null_mut());
+        }
+    }
+
+    // Handles Ctrl+C on the calling thread. Its reads from the terminal then fail with EINTR,
+    // since the handler is installed without SA_RESTART
+    pub fn install(handle: alethia::InterruptHandle) {
+        if HANDLE.set(handle).is_ok() {
+            // SAFETY: all zeroes is an empty mask and no flags. The handler only performs an
+            // atomic store
+            unsafe {
+                let mut action: sys::SigAction = std::mem::zeroed();
+                action.handler = on_sigint as extern "C" fn(c_int) as usize;
+                sigaction(SIGINT, &action, std::ptr::null_mut());
+                pthread_sigmask(sys::SIG_UNBLOCK, &sys::only(SIGINT), std::ptr::null_mut());
+            }
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+mod sigint {
+    pub fn block() {}
+
+    pub fn install(_: alethia::InterruptHandle) {}
+}
+
+// Reads a line from stdin like Stdin::read_line, which retries a read a signal interrupted
+// and so would only notice Ctrl+C once Enter is pressed
+fn read_line(line: &mut String) -> std::io::Result<usize> {
+    use std::io::{self, BufRead};
+
+    let mut stdin = io::stdin().lock();
+    let mut bytes = Vec::new();
+    loop {
+        let available = stdin.fill_buf()?;
+        if available.is_empty() {
+            break;
+        }
+        let (taken, done) = match available.iter().position(|&b| b == b'\n') {
+            Some(newline) => (newline + 1, true),
+            None => (available.len(), false),
+        };
+        bytes.extend_from_slice(&available[..taken]);
+        stdin.consume(taken);
+        if done {
+            break;
+        }
+    }
+    let text = String::from_utf8(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    line.push_str(&text);
+    Ok(text.len())
+}
+
+fn run_repl(capabilities: alethia::Capabilities) -> Result<(), String> {
     use std::io::{self, Write};
 
-    println!("alethia REPL (exit with Ctrl+C");
+    println!("alethia REPL (Ctrl+C stops the running statement, exit with Ctrl+D)");
     let mut interpreter = alethia::Interpreter::new();
+    interpreter.set_native_stack_size(stack_size(alethia::DEFAULT_MAX_CALL_DEPTH));
+    interpreter.set_capabilities(capabilities);
+    let interrupt = interpreter.interrupt_handle();
+    sigint::install(interrupt.clone());
 
     loop {
         print!("> ");
@@ -38,18 +341,28 @@ fn run_repl() -> Result<(), String> {
         }
 
         let mut input = String::new();
-        match io::stdin().read_line(&mut input) {
+        match read_line(&mut input) {
+            // end of input
+            Ok(0) => {
+                println!();
+                return Ok(());
+            }
+            // Ctrl+C while typing cancels the line
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {
+                interrupt.reset();
+                println!();
+            }
             Ok(_) => {
-                if input.trim().is_empty() {
+                if interrupt.reset() || input.trim().is_empty() {
                     continue;
                 }
                 if let Err(e) = alethia::run_line(input, &mut interpreter) {
-                eprintln!("Error: {}", e);
+                    print_traceback(&mut interpreter);
+                    eprintln!("Error: {}", e);
                 }
+            }
+            Err(e) => return Err(format!("Error reading input: {}", e)),
         }
-        Err(e) => return Err(format!("Error reading input: {}", e)),
-        
-    }
     }
 }
 
@@ -62,14 +375,26 @@ fn main() {
     }
 
     let result = match args[1].as_str() {
-        "run" => {
-            if args.len() != 3 {
+        "run" => match parse_run_args(&args[2..]) {
+            Ok((options, path)) => run_file(path, options),
+            Err(e) => {
+                eprintln!("Error: {}", e);
                 print_usage();
                 process::exit(1);
             }
-            run_file(Path::new(&args[2]))
-        }
-        "repl" => run_repl(),
+        },
+        "repl" => match parse_repl_args(&args[2..]) {
+            Ok(capabilities) => {
+                // Ctrl+C has to reach the REPL's thread to interrupt its reads
+                sigint::block();
+                with_large_stack(stack_size(alethia::DEFAULT_MAX_CALL_DEPTH), move || run_repl(capabilities))
+            },
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                print_usage();
+                process::exit(1);
+            }
+        },
         _ => {
             print_usage();
             process::exit(1);
@@ -0,0 +1,219 @@
+use crate::ast::{AstNode, InterpolationPart, MatchArm};
+use crate::interpreter::Interpreter;
+use crate::token::Token;
+use crate::value::Value;
+
+// Simplifies a program without changing what it does: folds operators applied to literals,
+// picks the branch of an `if` whose condition is a literal and drops statements after
+// `return`, `break` or `throw`. An operation that would fail at runtime, such as a division
+// by zero, is left in place so the error is still raised if that code runs
+#[derive(Default)]
+pub struct Optimizer;
+
+fn literal_value(node: &AstNode) -> Option<Value> {
+    match node {
+        AstNode::NumberLiteral(n) => Some(Value::Number(*n)),
+        AstNode::StringLiteral(s) => Some(Value::String(s.clone())),
+        AstNode::Bool(b) => Some(Value::Bool(*b)),
+        _ => None,
+    }
+}
+
+fn literal_node(value: Value) -> Option<AstNode> {
+    match value {
+        Value::Number(n) => Some(AstNode::NumberLiteral(n)),
+        Value::String(s) => Some(AstNode::StringLiteral(s)),
+        Value::Bool(b) => Some(AstNode::Bool(b)),
+        _ => None,
+    }
+}
+
+// Statements after one of these never run
+fn ends_flow(node: &AstNode) -> bool {
+    matches!(node, AstNode::Return(_) | AstNode::Break | AstNode::Throw(..))
+}
+
+impl Optimizer {
+    pub fn new() -> Self {
+        Self
+    }
+
+    pub fn optimize_program(mut self, program: AstNode) -> AstNode {
+        self.optimize(program)
+    }
+
+    // Only operands of the same type are folded; mixed operands are left to the runtime's
+    // conversion and error rules
+    fn fold_binary(left: &AstNode, operator: &Token, right: &AstNode) -> Option<AstNode> {
+        let (left, right) = (literal_value(left)?, literal_value(right)?);
+        let foldable = match (&left, &right) {
+            (Value::Number(_), Value::Number(_)) => true,
+            (Value::String(_), Value::String(_)) => matches!(operator, Token::Plus | Token::Equal),
+            (Value::Bool(_), Value::Bool(_)) => matches!(operator, Token::And | Token::Or | Token::Equal),
+            _ => false,
+        };
+        if !foldable {
+            return None;
+        }
+        Interpreter::apply_binary(operator, left, right).ok().and_then(literal_node)
+    }
+
+    fn optimize_statements(&mut self, statements: Vec<AstNode>) -> Vec<AstNode> {
+        let mut optimized = Vec::with_capacity(statements.len());
+        for statement in statements {
+            let statement = self.optimize(statement);
+            let done = ends_flow(&statement);
+            optimized.push(statement);
+            if done {
+                break;
+            }
+        }
+        optimized
+    }
+
+    fn optimize_all(&mut self, nodes: Vec<AstNode>) -> Vec<AstNode> {
+        nodes.into_iter().map(|node| self.optimize(node)).collect()
+    }
+
+    // Optimizes a child node in place, reusing its allocation
+    fn optimize_boxed(&mut self, mut node: Box<AstNode>) -> Box<AstNode> {
+        *node = self.optimize(std::mem::replace(&mut *node, AstNode::Nil));
+        node
+    }
+
+    fn optimize_function(&mut self, function: AstNode) -> AstNode {
+        match function {
+            AstNode::FunctionDeclaration { name, params, body } => {
+                AstNode::FunctionDeclaration { name, params, body: self.optimize_boxed(body) }
+            }
+            other => self.optimize(other),
+        }
+    }
+
+    fn optimize(&mut self, node: AstNode) -> AstNode {
+        match node {
+            AstNode::Program(statements) => AstNode::Program(self.optimize_statements(statements)),
+            AstNode::Block(statements) => AstNode::Block(self.optimize_statements(statements)),
+            AstNode::ArrayLiteral(elements) => AstNode::ArrayLiteral(self.optimize_all(elements)),
+            AstNode::BinaryExpression { left, operator, right, span } => {
+                let left = self.optimize_boxed(left);
+                let right = self.optimize_boxed(right);
+                match Self::fold_binary(&left, &operator, &right) {
+                    Some(folded) => folded,
+                    None => AstNode::BinaryExpression { left, operator, right, span },
+                }
+            }
+            AstNode::UnaryExpression { operator, operand, span } => match (operator, self.optimize(*operand)) {
+                (Token::Minus, AstNode::NumberLiteral(n)) => AstNode::NumberLiteral(-n),
+                (operator, operand) => AstNode::UnaryExpression { operator, operand: Box::new(operand), span },
+            },
+            AstNode::IfStatement { condition, consequence, alternative } => match self.optimize(*condition) {
+                AstNode::Bool(true) => self.optimize(*consequence),
+                // an if without else evaluates to nil when its condition fails
+                AstNode::Bool(false) => match alternative {
+                    Some(alternative) => self.optimize(*alternative),
+                    None => AstNode::Nil,
+                },
+                condition => AstNode::IfStatement {
+                    condition: Box::new(condition),
+                    consequence: self.optimize_boxed(consequence),
+                    alternative: alternative.map(|alternative| self.optimize_boxed(alternative)),
+                },
+            },
+            AstNode::WhileStatement { condition, body } => match self.optimize(*condition) {
+                // the value of a loop statement
+                AstNode::Bool(false) => AstNode::NumberLiteral(0.0),
+                condition => AstNode::WhileStatement { condition: Box::new(condition), body: self.optimize_boxed(body) },
+            },
+            AstNode::VariableDeclaration { name, value, constant } => {
+                AstNode::VariableDeclaration { name, value: self.optimize_boxed(value), constant }
+            }
+            AstNode::DestructuringDeclaration { pattern, value, constant } => {
+                AstNode::DestructuringDeclaration { pattern, value: self.optimize_boxed(value), constant }
+            }
+            AstNode::DestructuringAssignment { pattern, value } => {
+                AstNode::DestructuringAssignment { pattern, value: self.optimize_boxed(value) }
+            }
+            AstNode::Assignment { target, value } => AstNode::Assignment {
+                target: self.optimize_boxed(target),
+                value: self.optimize_boxed(value),
+            },
+            AstNode::CompoundAssignment { target, operator, value } => AstNode::CompoundAssignment {
+                target: self.optimize_boxed(target),
+                operator,
+                value: self.optimize_boxed(value),
+            },
+            AstNode::InterpolatedString(parts) => AstNode::InterpolatedString(
+                parts
+                    .into_iter()
+                    .map(|part| match part {
+                        InterpolationPart::Expression { expr, format } => {
+                            InterpolationPart::Expression { expr: self.optimize_boxed(expr), format }
+                        }
+                        literal => literal,
+                    })
+                    .collect(),
+            ),
+            AstNode::PrintStatement(expr) => AstNode::PrintStatement(self.optimize_boxed(expr)),
+            AstNode::DictionaryLiteral(pairs) => AstNode::DictionaryLiteral(
+                pairs.into_iter().map(|(key, value)| (key, self.optimize(value))).collect(),
+            ),
+            AstNode::IndexExpression(object, index, span) => {
+                AstNode::IndexExpression(self.optimize_boxed(object), self.optimize_boxed(index), span)
+            }
+            function @ AstNode::FunctionDeclaration { .. } => self.optimize_function(function),
+            AstNode::FunctionCall { function, arguments, span } => AstNode::FunctionCall {
+                function: self.optimize_boxed(function),
+                arguments: self.optimize_all(arguments),
+                span,
+            },
+            AstNode::Return(expr) => AstNode::Return(expr.map(|expr| self.optimize_boxed(expr))),
+            AstNode::Match { subject, arms, span } => AstNode::Match {
+                subject: self.optimize_boxed(subject),
+                arms: arms
+                    .into_iter()
+                    .map(|arm| MatchArm {
+                        pattern: arm.pattern,
+                        guard: arm.guard.map(|guard| self.optimize(guard)),
+                        body: self.optimize(arm.body),
+                    })
+                    .collect(),
+                span,
+            },
+            AstNode::StructLiteral { name, fields } => AstNode::StructLiteral {
+                name,
+                fields: fields.into_iter().map(|(field, value)| (field, self.optimize(value))).collect(),
+            },
+            AstNode::FieldAccess { object, field, span } => {
+                AstNode::FieldAccess { object: self.optimize_boxed(object), field, span }
+            }
+            AstNode::ImplBlock { type_name, methods } => AstNode::ImplBlock {
+                type_name,
+                methods: methods.into_iter().map(|method| self.optimize_function(method)).collect(),
+            },
+            AstNode::MethodCall { object, method, arguments, span } => AstNode::MethodCall {
+                object: self.optimize_boxed(object),
+                method,
+                arguments: self.optimize_all(arguments),
+                span,
+            },
+            AstNode::IsPattern { value, pattern } => AstNode::IsPattern { value: self.optimize_boxed(value), pattern },
+            AstNode::Throw(expr, span) => AstNode::Throw(self.optimize_boxed(expr), span),
+            AstNode::Propagate(expr) => AstNode::Propagate(self.optimize_boxed(expr)),
+            AstNode::TryStatement { body, catch, finally } => AstNode::TryStatement {
+                body: self.optimize_boxed(body),
+                catch: catch.map(|(pattern, handler)| (pattern, self.optimize_boxed(handler))),
+                finally: finally.map(|finally| self.optimize_boxed(finally)),
+            },
+            other @ (AstNode::NumberLiteral(_)
+            | AstNode::StringLiteral(_)
+            | AstNode::Identifier(_)
+            | AstNode::Variable { .. }
+            | AstNode::Bool(_)
+            | AstNode::Break
+            | AstNode::StructDeclaration { .. }
+            | AstNode::EnumDeclaration { .. }
+            | AstNode::Nil) => other,
+        }
+    }
+}
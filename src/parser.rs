@@ -1,18 +1,135 @@
-use crate::ast::AstNode;
-use crate::token::Token;
+use std::collections::HashMap;
+use std::fmt;
+use crate::ast::{AstNode, FormatSpec, InterpolationPart, MatchArm, Pattern};
+use crate::token::{Span, SpannedToken, StringPart, Token};
 
 pub struct Parser {
-    tokens: Vec<Token>,
+    tokens: Vec<SpannedToken>,
     position: usize,
+    errors: Vec<ParseError>,
+    warnings: Vec<ParseError>,
+    // set while parsing `if`/`while` conditions and `match` subjects, where
+    // `name {` starts the block rather than a struct literal
+    no_struct_literal: bool,
+    // variant names of the enums declared so far, used to check that a match covers every variant
+    enums: HashMap<String, Vec<String>>,
+    // where the last error was found when that isn't the current token, e.g. inside a string hole
+    error_span: Option<Span>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub message: String,
+    pub span: Span,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Parse error at line {}, column {}: {}", self.span.line, self.span.column, self.message)
+    }
+}
+
+// A (possibly partial) program together with every syntax error found while parsing it,
+// plus warnings that don't stop it from running (e.g. a possibly non-exhaustive match)
+#[derive(Debug)]
+pub struct ParseOutput {
+    pub program: AstNode,
+    pub errors: Vec<ParseError>,
+    pub warnings: Vec<ParseError>,
 }
 
 impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Self {
-        Self { tokens, position: 0 }
+    pub fn new(tokens: Vec<SpannedToken>) -> Self {
+        Self {
+            tokens,
+            position: 0,
+            errors: Vec::new(),
+            warnings: Vec::new(),
+            no_struct_literal: false,
+            // the built-in Result enum produced by ok() and err()
+            enums: HashMap::from([("Result".to_string(), vec!["Ok".to_string(), "Err".to_string()])]),
+            error_span: None,
+        }
     }
 
     pub fn current_token(&self) -> &Token {
-        self.tokens.get(self.position).unwrap_or(&Token::Eof)
+        self.tokens.get(self.position).map_or(&Token::Eof, |t| &t.token)
+    }
+
+    pub fn current_span(&self) -> Span {
+        match self.tokens.get(self.position).or(self.tokens.last()) {
+            Some(t) => t.span,
+            None => Span::default(),
+        }
+    }
+
+    // Where the error just returned by a parse method was found
+    pub fn error_span(&mut self) -> Span {
+        self.error_span.take().unwrap_or_else(|| self.current_span())
+    }
+
+    // Takes the syntax errors recorded so far, e.g. those recovered from inside blocks
+    pub fn take_errors(&mut self) -> Vec<ParseError> {
+        std::mem::take(&mut self.errors)
+    }
+
+    pub fn take_warnings(&mut self) -> Vec<ParseError> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    fn record_error(&mut self, message: String, start: usize, in_block: bool) {
+        let span = self.error_span();
+        self.errors.push(ParseError { message, span });
+        self.synchronize(start, in_block);
+    }
+
+    // Panic-mode recovery: skip tokens until the end of the broken statement so parsing can go on.
+    // Braces are skipped as a whole, including those the statement opened before the error, as in
+    // `func f( { ... }`. Outside them it stops after a ';', before a keyword that starts a new
+    // statement, and at a '}' which is left for the enclosing block to close (or skipped when
+    // already at the top level)
+    fn synchronize(&mut self, start: usize, in_block: bool) {
+        let mut depth = self.tokens[start..self.position.min(self.tokens.len())]
+            .iter()
+            .fold(0usize, |depth, t| match t.token {
+                Token::LeftCurly => depth + 1,
+                Token::RightCurly => depth.saturating_sub(1),
+                _ => depth,
+            });
+        // always make progress past the token the statement started on
+        if self.position == start && !(in_block && *self.current_token() == Token::RightCurly) {
+            if *self.current_token() == Token::LeftCurly {
+                depth += 1;
+            }
+            self.advance();
+        }
+        loop {
+            match self.current_token() {
+                Token::Eof => return,
+                Token::LeftCurly => depth += 1,
+                Token::RightCurly if depth == 0 => {
+                    if !in_block {
+                        self.advance();
+                    }
+                    return;
+                }
+                Token::RightCurly => {
+                    depth -= 1;
+                    if depth == 0 {
+                        self.advance();
+                        return;
+                    }
+                }
+                _ if depth > 0 => {}
+                Token::Semicolon => {
+                    self.advance();
+                    return;
+                }
+                Token::Let | Token::Const | Token::Func | Token::Struct | Token::Enum | Token::Impl | Token::If | Token::While | Token::Try | Token::Throw | Token::Print | Token::Return | Token::Break => return,
+                _ => {}
+            }
+            self.advance();
+        }
     }
 
     pub fn advance(&mut self) {
@@ -35,6 +152,15 @@ impl Parser {
     }
 
     pub fn parse_program(&mut self) -> Result<AstNode, String> {
+        let output = self.parse_program_with_diagnostics();
+        match output.errors.first() {
+            Some(error) => Err(error.message.clone()),
+            None => Ok(output.program),
+        }
+    }
+
+    // Parses the whole input, recovering at statement boundaries after each syntax error
+    pub fn parse_program_with_diagnostics(&mut self) -> ParseOutput {
         let mut statements = Vec::new();
     
         while self.current_token() != &Token::Eof {
@@ -43,12 +169,24 @@ impl Parser {
                 continue;
             }
     
-            let stmt = self.parse_statement()?;
+            let start = self.position;
+            let stmt = match self.parse_statement() {
+                Ok(stmt) => stmt,
+                Err(message) => {
+                    self.record_error(message, start, false);
+                    continue;
+                }
+            };
             let is_block_or_function = matches!(
                 stmt,
                 AstNode::IfStatement { .. }
                     | AstNode::WhileStatement { .. }
+                    | AstNode::TryStatement { .. }
                     | AstNode::FunctionDeclaration { .. }
+                    | AstNode::Match { .. }
+                    | AstNode::StructDeclaration { .. }
+                    | AstNode::EnumDeclaration { .. }
+                    | AstNode::ImplBlock { .. }
             );
     
             statements.push(stmt);
@@ -61,23 +199,42 @@ impl Parser {
             } else {
                 // semicolon is required after everything else
                 if *self.current_token() != Token::Semicolon {
-                    return Err(format!(
+                    let message = format!(
                         "Expected Semicolon, but found {:?}",
                         self.current_token()
-                    ));
+                    );
+                    self.record_error(message, start, false);
+                    continue;
                 }
                 self.advance();
             }
         }
-    
-        Ok(AstNode::Program(statements))
+
+        ParseOutput {
+            program: AstNode::Program(statements),
+            errors: self.take_errors(),
+            warnings: self.take_warnings(),
+        }
     }
     
 
     pub fn parse_statement(&mut self) -> Result<AstNode, String> {
         match self.current_token() {
-            Token::Let => {
+            Token::Let | Token::Const => {
+                let constant = *self.current_token() == Token::Const;
+                let keyword = if constant { "const" } else { "let" };
                 self.advance();
+
+                if matches!(self.current_token(), Token::LeftBracket | Token::LeftCurly) {
+                    let pattern = self.parse_pattern()?;
+                    self.consume(&Token::Assign)?;
+                    let value = self.parse_expression()?;
+                    return Ok(AstNode::DestructuringDeclaration {
+                        pattern,
+                        value: Box::new(value),
+                        constant,
+                    });
+                }
     
                 let name = match self.current_token() {
                     Token::Identifier(ref id) => {
@@ -85,36 +242,21 @@ impl Parser {
                         self.advance();
                         name
                     }
-                    _ => return Err(format!("Expected identifier after 'let', found {:?}", self.current_token())),
+                    _ => return Err(format!("Expected identifier after '{}', found {:?}", keyword, self.current_token())),
                 };
     
                 match self.current_token() {
                     Token::Assign => self.advance(), 
-                    _ => return Err(format!("Expected '=' after identifier in let statement, found {:?}", self.current_token())),
+                    _ => return Err(format!("Expected '=' after identifier in {} statement, found {:?}", keyword, self.current_token())),
                 }
     
                 let value = self.parse_expression()?;
                 Ok(AstNode::VariableDeclaration {
                     name,
                     value: Box::new(value),
+                    constant,
                 })
             }
-            Token::Identifier(ref name) => {
-                // Check if the next token is '=' for assignment
-                if let Some(Token::Assign) = self.tokens.get(self.position + 1) {
-                    let name = name.clone();
-                    self.advance(); 
-                    self.advance(); 
-                    let value = self.parse_expression()?;
-                    Ok(AstNode::VariableDeclaration {
-                        name,
-                        value: Box::new(value),
-                    })
-                } else {
-                    // Otherwise, treat as an expression
-                    self.parse_expression()
-                }
-            }
             Token::Print => {
                 self.advance();
                 let expr = self.parse_expression()?;
@@ -127,15 +269,48 @@ impl Parser {
                 Ok(AstNode::Break)
             }
             Token::Func => self.parse_function_declaration(),
+            Token::Struct => self.parse_struct_declaration(),
+            Token::Enum => self.parse_enum_declaration(),
+            Token::Impl => self.parse_impl_block(),
             Token::Return => self.parse_return_statement(),
-            _ => Err(format!("Unexpected token in statement: {:?}", self.current_token())),
+            Token::Throw => {
+                let span = self.current_span();
+                self.advance();
+                let value = self.parse_expression()?;
+                Ok(AstNode::Throw(Box::new(value), span))
+            }
+            Token::Try => self.parse_try_statement(),
+            // anything else is an expression statement, e.g. the value at the end of a block,
+            // which may turn out to be the target of an assignment
+            _ => {
+                if let Some(assignment) = self.try_parse_destructuring_assignment()? {
+                    return Ok(assignment);
+                }
+                let expr = self.parse_expression()?;
+                self.parse_assignment(expr)
+            }
         }
     }
     
 
 
     pub fn parse_expression(&mut self) -> Result<AstNode, String> {
-        self.parse_or_expression()
+        let value = self.parse_or_expression()?;
+
+        // conditional expression: `value if condition else other`
+        if *self.current_token() == Token::If {
+            self.advance();
+            let condition = self.parse_or_expression()?;
+            self.consume(&Token::Else)?;
+            let other = self.parse_expression()?;
+            return Ok(AstNode::IfStatement {
+                condition: Box::new(condition),
+                consequence: Box::new(value),
+                alternative: Some(Box::new(other)),
+            });
+        }
+
+        Ok(value)
     }
 
     fn parse_or_expression(&mut self) -> Result<AstNode, String> {
@@ -143,12 +318,14 @@ impl Parser {
 
         while matches!(self.current_token(), Token::Or) {
             let operator = self.current_token().clone();
+            let span = self.current_span();
             self.advance();
             let right = self.parse_and_expression()?;
             left = AstNode::BinaryExpression {
                 left: Box::new(left),
                 operator,
                 right: Box::new(right),
+                span,
             };
         }
 
@@ -160,12 +337,14 @@ impl Parser {
 
         while matches!(self.current_token(), Token::And) {
             let operator = self.current_token().clone();
+            let span = self.current_span();
             self.advance();
             let right = self.parse_comparison()?;
             left = AstNode::BinaryExpression {
                 left: Box::new(left),
                 operator,
                 right: Box::new(right),
+                span,
             };
         }
 
@@ -175,18 +354,32 @@ impl Parser {
     fn parse_comparison(&mut self) -> Result<AstNode, String> {
         let mut left = self.parse_sum()?;
 
-        while matches!(self.current_token(), Token::Equal | Token::LessThan | Token::GreaterThan) {
-            let operator = self.current_token().clone();
-            self.advance();
-            let right = self.parse_sum()?;
-            left = AstNode::BinaryExpression {
-                left: Box::new(left),
-                operator,
-                right: Box::new(right),
-            };
+        loop {
+            match self.current_token() {
+                Token::Equal | Token::LessThan | Token::GreaterThan => {
+                    let operator = self.current_token().clone();
+                    let span = self.current_span();
+                    self.advance();
+                    let right = self.parse_sum()?;
+                    left = AstNode::BinaryExpression {
+                        left: Box::new(left),
+                        operator,
+                        right: Box::new(right),
+                        span,
+                    };
+                }
+                // `value is pattern` tests a pattern, binding its names when it matches
+                Token::Is => {
+                    self.advance();
+                    let pattern = self.parse_pattern()?;
+                    left = AstNode::IsPattern {
+                        value: Box::new(left),
+                        pattern,
+                    };
+                }
+                _ => return Ok(left),
+            }
         }
-
-        Ok(left)
     }
 
     fn parse_sum(&mut self) -> Result<AstNode, String> {
@@ -194,12 +387,14 @@ impl Parser {
 
         while matches!(self.current_token(), Token::Plus | Token::Minus) {
             let operator = self.current_token().clone();
+            let span = self.current_span();
             self.advance();
             let right = self.parse_product()?;
             left = AstNode::BinaryExpression {
                 left: Box::new(left),
                 operator,
                 right: Box::new(right),
+                span,
             };
         }
 
@@ -209,14 +404,16 @@ impl Parser {
     fn parse_product(&mut self) -> Result<AstNode, String> {
         let mut left = self.parse_factor()?;
 
-        while matches!(self.current_token(), Token::Multiply | Token::Divide) {
+        while matches!(self.current_token(), Token::Multiply | Token::Divide | Token::Modulo) {
             let operator = self.current_token().clone();
+            let span = self.current_span();
             self.advance();
             let right = self.parse_factor()?;
             left = AstNode::BinaryExpression {
                 left: Box::new(left),
                 operator,
                 right: Box::new(right),
+                span,
             };
         }
 
@@ -224,58 +421,42 @@ impl Parser {
     }
 
     pub fn parse_factor(&mut self) -> Result<AstNode, String> {
+        // calls report where the called expression starts
+        let start = self.current_span();
         match self.current_token().clone() {
-            Token::LeftBracket => self.parse_array_literal(),
+            Token::LeftBracket => {
+                let array = self.parse_array_literal()?;
+                self.parse_postfix(array, start)
+            }
             Token::LeftCurly => self.parse_dictionary_literal(),
             Token::Identifier(name) => {
                 self.advance();
-                // Handle indexing or function calls
-                if *self.current_token() == Token::LeftBracket {
-                    self.advance();
-                    let index = self.parse_expression()?;
-                    self.consume(&Token::RightBracket)?;
-                    Ok(AstNode::IndexExpression(
-                        Box::new(AstNode::Identifier(name)),
-                        Box::new(index),
-                    ))
-                } else if *self.current_token() == Token::LeftParen {
-                    self.advance();
-                    let mut arguments = Vec::new();
-    
-                    if *self.current_token() != Token::RightParen {
-                        loop {
-                            let arg = self.parse_expression()?;
-                            arguments.push(arg);
-    
-                            match self.current_token() {
-                                Token::Comma => {
-                                    self.advance();
-                                    continue;
-                                }
-                                Token::RightParen => break,
-                                _ => {
-                                    return Err(format!(
-                                        "Expected ',' or ')', found {:?}",
-                                        self.current_token()
-                                    ));
-                                }
-                            }
-                        }
-                    }
-                    self.consume(&Token::RightParen)?;
-                    Ok(AstNode::FunctionCall {
-                        function: Box::new(AstNode::Identifier(name)),
-                        arguments,
-                    })
-                } else {
-                    Ok(AstNode::Identifier(name))
+                if *self.current_token() == Token::LeftCurly && !self.no_struct_literal {
+                    let literal = self.parse_struct_literal(name)?;
+                    return self.parse_postfix(literal, start);
                 }
+                self.parse_postfix(AstNode::Identifier(name), start)
+            }
+            Token::If => self.parse_if_statement(),
+            Token::Match => self.parse_match(),
+            Token::Minus => {
+                self.advance();
+                let operand = self.parse_factor()?;
+                Ok(AstNode::UnaryExpression {
+                    operator: Token::Minus,
+                    operand: Box::new(operand),
+                    span: start,
+                })
             }
             Token::LeftParen => {
                 self.advance();
-                let expr = self.parse_expression()?;
+                // struct literals are allowed again inside parentheses
+                let restricted = std::mem::replace(&mut self.no_struct_literal, false);
+                let expr = self.parse_expression();
+                self.no_struct_literal = restricted;
+                let expr = expr?;
                 self.consume(&Token::RightParen)?;
-                Ok(expr)
+                self.parse_postfix(expr, start)
             }
             Token::Nil => {
                 self.advance();
@@ -288,7 +469,12 @@ impl Parser {
             }
             Token::StringLiteral(s) => {
                 self.advance();
-                Ok(AstNode::StringLiteral(s))
+                self.parse_postfix(AstNode::StringLiteral(s), start)
+            }
+            Token::InterpolatedString(parts) => {
+                self.advance();
+                let string = self.parse_interpolated_string(parts)?;
+                self.parse_postfix(string, start)
             }
             Token::True => {
                 self.advance();
@@ -302,6 +488,606 @@ impl Parser {
         }
     }
     
+    // A statement starting with '[' or '{' is either a literal or a pattern being assigned to,
+    // as in `[a, b] = [b, a];`. Tries the pattern first and rewinds if it isn't followed by '='
+    fn try_parse_destructuring_assignment(&mut self) -> Result<Option<AstNode>, String> {
+        if !matches!(self.current_token(), Token::LeftBracket | Token::LeftCurly) {
+            return Ok(None);
+        }
+
+        let start = self.position;
+        match self.parse_pattern() {
+            Ok(pattern) if *self.current_token() == Token::Assign => {
+                self.advance();
+                let value = self.parse_expression()?;
+                Ok(Some(AstNode::DestructuringAssignment {
+                    pattern,
+                    value: Box::new(value),
+                }))
+            }
+            _ => {
+                self.position = start;
+                self.error_span = None;
+                Ok(None)
+            }
+        }
+    }
+
+    // pattern := single ('|' single)*
+    fn parse_pattern(&mut self) -> Result<Pattern, String> {
+        let first = self.parse_single_pattern()?;
+        if *self.current_token() != Token::Pipe {
+            return Ok(first);
+        }
+
+        let mut alternatives = vec![first];
+        while *self.current_token() == Token::Pipe {
+            self.advance();
+            alternatives.push(self.parse_single_pattern()?);
+        }
+
+        let mut expected = alternatives[0].bound_names();
+        expected.sort_unstable();
+        for alternative in &alternatives[1..] {
+            let mut names = alternative.bound_names();
+            names.sort_unstable();
+            if names != expected {
+                return Err("All alternatives in a '|' pattern must bind the same variables".to_string());
+            }
+        }
+        Ok(Pattern::Or(alternatives))
+    }
+
+    // single := identifier | '_' | literal | number ('..' | '..=') number
+    //         | '[' (pattern (',' pattern)* (',' '...' identifier)?)? ']'
+    //         | '{' (key (':' pattern)? (',' key (':' pattern)?)*)? '}'
+    fn parse_single_pattern(&mut self) -> Result<Pattern, String> {
+        match self.current_token().clone() {
+            Token::Identifier(name) => {
+                self.advance();
+                if name == "_" {
+                    return Ok(Pattern::Wildcard);
+                }
+                if *self.current_token() == Token::Dot {
+                    return self.parse_variant_pattern(name);
+                }
+                Ok(Pattern::Identifier(name))
+            }
+            Token::Number(_) | Token::Minus => {
+                let start = self.parse_pattern_number()?;
+                let inclusive = match self.current_token() {
+                    Token::DotDot => false,
+                    Token::DotDotEqual => true,
+                    _ => return Ok(Pattern::Literal(Box::new(AstNode::NumberLiteral(start)))),
+                };
+                self.advance();
+                let end = self.parse_pattern_number()?;
+                Ok(Pattern::Range { start, end, inclusive })
+            }
+            Token::StringLiteral(s) => {
+                self.advance();
+                Ok(Pattern::Literal(Box::new(AstNode::StringLiteral(s))))
+            }
+            Token::True | Token::False => {
+                let value = *self.current_token() == Token::True;
+                self.advance();
+                Ok(Pattern::Literal(Box::new(AstNode::Bool(value))))
+            }
+            Token::Nil => {
+                self.advance();
+                Ok(Pattern::Literal(Box::new(AstNode::Nil)))
+            }
+            Token::LeftBracket => {
+                self.advance();
+                let mut elements = Vec::new();
+                let mut rest = None;
+
+                while *self.current_token() != Token::RightBracket {
+                    if *self.current_token() == Token::Ellipsis {
+                        self.advance();
+                        match self.current_token().clone() {
+                            Token::Identifier(name) => {
+                                self.advance();
+                                rest = Some(Box::new(Pattern::Identifier(name)));
+                            }
+                            other => return Err(format!("Expected identifier after '...', found {:?}", other)),
+                        }
+                        if *self.current_token() == Token::Comma {
+                            self.advance();
+                        }
+                        if *self.current_token() != Token::RightBracket {
+                            return Err("Rest element must be last in an array pattern".to_string());
+                        }
+                        break;
+                    }
+
+                    elements.push(self.parse_pattern()?);
+
+                    match self.current_token() {
+                        Token::Comma => self.advance(),
+                        Token::RightBracket => {}
+                        other => return Err(format!("Expected ',' or ']' in array pattern, found {:?}", other)),
+                    }
+                }
+
+                self.consume(&Token::RightBracket)?;
+                Ok(Pattern::Array { elements, rest })
+            }
+            Token::LeftCurly => {
+                self.advance();
+                let mut entries = Vec::new();
+
+                while *self.current_token() != Token::RightCurly {
+                    let key = match self.current_token().clone() {
+                        Token::Identifier(name) | Token::StringLiteral(name) => {
+                            self.advance();
+                            name
+                        }
+                        other => return Err(format!("Expected key in dictionary pattern, found {:?}", other)),
+                    };
+
+                    // `{name}` is shorthand for `{name: name}`
+                    let pattern = if *self.current_token() == Token::Colon {
+                        self.advance();
+                        self.parse_pattern()?
+                    } else {
+                        Pattern::Identifier(key.clone())
+                    };
+                    entries.push((key, pattern));
+
+                    match self.current_token() {
+                        Token::Comma => self.advance(),
+                        Token::RightCurly => {}
+                        other => return Err(format!("Expected ',' or '}}' in dictionary pattern, found {:?}", other)),
+                    }
+                }
+
+                self.consume(&Token::RightCurly)?;
+                Ok(Pattern::Dictionary(entries))
+            }
+            other => Err(format!("Expected a pattern, found {:?}", other)),
+        }
+    }
+
+    // Enum.Variant ('(' (pattern (',' pattern)*)? ')')?
+    fn parse_variant_pattern(&mut self, enum_name: String) -> Result<Pattern, String> {
+        self.consume(&Token::Dot)?;
+        let variant = match self.current_token().clone() {
+            Token::Identifier(variant) => variant,
+            other => return Err(format!("Expected variant name after '{}.', found {:?}", enum_name, other)),
+        };
+        self.advance();
+
+        let fields = if *self.current_token() == Token::LeftParen {
+            self.advance();
+            let mut fields = Vec::new();
+            while *self.current_token() != Token::RightParen {
+                fields.push(self.parse_pattern()?);
+                match self.current_token() {
+                    Token::Comma => self.advance(),
+                    Token::RightParen => {}
+                    other => return Err(format!("Expected ',' or ')' in variant pattern, found {:?}", other)),
+                }
+            }
+            self.consume(&Token::RightParen)?;
+            Some(fields)
+        } else {
+            None
+        };
+
+        Ok(Pattern::Variant { enum_name, variant, fields })
+    }
+
+    fn parse_pattern_number(&mut self) -> Result<f64, String> {
+        let negative = *self.current_token() == Token::Minus;
+        if negative {
+            self.advance();
+        }
+        match self.current_token().clone() {
+            Token::Number(n) => {
+                self.advance();
+                Ok(if negative { -n } else { n })
+            }
+            other => Err(format!("Expected number in pattern, found {:?}", other)),
+        }
+    }
+
+    // match subject { pattern (if guard)? => statement_or_block, ... }
+    fn parse_match(&mut self) -> Result<AstNode, String> {
+        let span = self.current_span();
+        self.consume(&Token::Match)?;
+        let subject = self.parse_condition()?;
+        self.consume(&Token::LeftCurly)?;
+
+        let mut arms = Vec::new();
+        while *self.current_token() != Token::RightCurly {
+            let pattern = self.parse_pattern()?;
+            let guard = if *self.current_token() == Token::If {
+                self.advance();
+                Some(self.parse_expression()?)
+            } else {
+                None
+            };
+            self.consume(&Token::FatArrow)?;
+
+            let body_is_block = *self.current_token() == Token::LeftCurly;
+            // a non-block body may also be a simple statement such as `print x` or `return y`
+            let body = if body_is_block {
+                self.parse_block()?
+            } else {
+                self.parse_statement()?
+            };
+            arms.push(MatchArm { pattern, guard, body });
+
+            match self.current_token() {
+                Token::Comma => self.advance(),
+                Token::RightCurly => {}
+                // the comma is optional after a block body
+                _ if body_is_block => {}
+                other => return Err(format!("Expected ',' or '}}' after match arm, found {:?}", other)),
+            }
+        }
+        self.consume(&Token::RightCurly)?;
+
+        if !self.is_exhaustive(&arms) {
+            self.warnings.push(ParseError {
+                message: "match may not be exhaustive; add a '_' arm to handle other values".to_string(),
+                span,
+            });
+        }
+
+        Ok(AstNode::Match {
+            subject: Box::new(subject),
+            arms,
+            span,
+        })
+    }
+
+    // A match is known to be exhaustive if an unguarded arm matches anything,
+    // or unguarded arms cover both `true` and `false`, or every variant of a declared enum
+    fn is_exhaustive(&self, arms: &[MatchArm]) -> bool {
+        let unguarded = arms.iter().filter(|arm| arm.guard.is_none());
+        let mut covers_true = false;
+        let mut covers_false = false;
+        let mut variants: HashMap<&str, Vec<&str>> = HashMap::new();
+
+        for arm in unguarded {
+            if arm.pattern.is_irrefutable() {
+                return true;
+            }
+            let alternatives = match &arm.pattern {
+                Pattern::Or(alternatives) => alternatives.as_slice(),
+                pattern => std::slice::from_ref(pattern),
+            };
+            for alternative in alternatives {
+                match alternative {
+                    Pattern::Literal(literal) => match **literal {
+                        AstNode::Bool(true) => covers_true = true,
+                        AstNode::Bool(false) => covers_false = true,
+                        _ => {}
+                    },
+                    Pattern::Variant { enum_name, variant, fields } => {
+                        let whole = fields.iter().flatten().all(Pattern::is_irrefutable);
+                        if whole {
+                            variants.entry(enum_name).or_default().push(variant);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let covers_enum = variants.iter().any(|(enum_name, covered)| {
+            self.enums
+                .get(*enum_name)
+                .is_some_and(|declared| declared.iter().all(|variant| covered.contains(&variant.as_str())))
+        });
+        (covers_true && covers_false) || covers_enum
+    }
+
+    // Indexing, calls, field access and `?` after `expr`, which starts at `start`
+    fn parse_postfix(&mut self, mut expr: AstNode, start: Span) -> Result<AstNode, String> {
+        loop {
+            let span = self.current_span();
+            match self.current_token() {
+                Token::LeftBracket => {
+                    self.advance();
+                    let index = self.parse_expression()?;
+                    self.consume(&Token::RightBracket)?;
+                    expr = AstNode::IndexExpression(Box::new(expr), Box::new(index), span);
+                }
+                Token::LeftParen => {
+                    let arguments = self.parse_arguments()?;
+                    expr = AstNode::FunctionCall {
+                        function: Box::new(expr),
+                        arguments,
+                        span: start,
+                    };
+                }
+                Token::Dot => {
+                    self.advance();
+                    let field = match self.current_token().clone() {
+                        Token::Identifier(field) => field,
+                        other => return Err(format!("Expected field name after '.', found {:?}", other)),
+                    };
+                    self.advance();
+                    expr = if *self.current_token() == Token::LeftParen {
+                        let arguments = self.parse_arguments()?;
+                        AstNode::MethodCall {
+                            object: Box::new(expr),
+                            method: field,
+                            arguments,
+                            span: start,
+                        }
+                    } else {
+                        AstNode::FieldAccess {
+                            object: Box::new(expr),
+                            field,
+                            span,
+                        }
+                    };
+                }
+                Token::Question => {
+                    self.advance();
+                    expr = AstNode::Propagate(Box::new(expr));
+                }
+                _ => return Ok(expr),
+            }
+        }
+    }
+
+    // '(' (expression (',' expression)*)? ')'
+    fn parse_arguments(&mut self) -> Result<Vec<AstNode>, String> {
+        self.consume(&Token::LeftParen)?;
+        let mut arguments = Vec::new();
+
+        if *self.current_token() != Token::RightParen {
+            loop {
+                let arg = self.parse_expression()?;
+                arguments.push(arg);
+
+                match self.current_token() {
+                    Token::Comma => {
+                        self.advance();
+                        continue;
+                    }
+                    Token::RightParen => break,
+                    _ => {
+                        return Err(format!(
+                            "Expected ',' or ')', found {:?}",
+                            self.current_token()
+                        ));
+                    }
+                }
+            }
+        }
+        self.consume(&Token::RightParen)?;
+        Ok(arguments)
+    }
+
+    // impl Name { func method(self, ...) { ... } ... }
+    fn parse_impl_block(&mut self) -> Result<AstNode, String> {
+        self.consume(&Token::Impl)?;
+        let type_name = match self.current_token().clone() {
+            Token::Identifier(name) => name,
+            other => return Err(format!("Expected type name after 'impl', found {:?}", other)),
+        };
+        self.advance();
+        self.consume(&Token::LeftCurly)?;
+
+        let mut methods = Vec::new();
+        while *self.current_token() != Token::RightCurly {
+            match self.current_token() {
+                Token::Func => methods.push(self.parse_function_declaration()?),
+                Token::Semicolon => self.advance(),
+                other => return Err(format!("Expected 'func' in impl block for {}, found {:?}", type_name, other)),
+            }
+        }
+        self.consume(&Token::RightCurly)?;
+
+        Ok(AstNode::ImplBlock { type_name, methods })
+    }
+
+    // Parses an expression where a following '{' opens a block, as in `if cond { ... }`
+    fn parse_condition(&mut self) -> Result<AstNode, String> {
+        let restricted = std::mem::replace(&mut self.no_struct_literal, true);
+        let condition = self.parse_expression();
+        self.no_struct_literal = restricted;
+        condition
+    }
+
+    // struct Name { field, field, ... }
+    fn parse_struct_declaration(&mut self) -> Result<AstNode, String> {
+        self.consume(&Token::Struct)?;
+        let name = match self.current_token().clone() {
+            Token::Identifier(name) => name,
+            other => return Err(format!("Expected struct name after 'struct', found {:?}", other)),
+        };
+        self.advance();
+        self.consume(&Token::LeftCurly)?;
+
+        let mut fields: Vec<String> = Vec::new();
+        while *self.current_token() != Token::RightCurly {
+            match self.current_token().clone() {
+                Token::Identifier(field) => {
+                    if fields.contains(&field) {
+                        return Err(format!("Duplicate field '{}' in struct {}", field, name));
+                    }
+                    fields.push(field);
+                    self.advance();
+                }
+                other => return Err(format!("Expected field name in struct {}, found {:?}", name, other)),
+            }
+            match self.current_token() {
+                Token::Comma => self.advance(),
+                Token::RightCurly => {}
+                other => return Err(format!("Expected ',' or '}}' in struct declaration, found {:?}", other)),
+            }
+        }
+        self.consume(&Token::RightCurly)?;
+
+        Ok(AstNode::StructDeclaration { name, fields })
+    }
+
+    // enum Name { Variant, Variant(field, ...), ... }
+    fn parse_enum_declaration(&mut self) -> Result<AstNode, String> {
+        self.consume(&Token::Enum)?;
+        let name = match self.current_token().clone() {
+            Token::Identifier(name) => name,
+            other => return Err(format!("Expected enum name after 'enum', found {:?}", other)),
+        };
+        self.advance();
+        self.consume(&Token::LeftCurly)?;
+
+        let mut variants: Vec<(String, Vec<String>)> = Vec::new();
+        while *self.current_token() != Token::RightCurly {
+            let variant = match self.current_token().clone() {
+                Token::Identifier(variant) => variant,
+                other => return Err(format!("Expected variant name in enum {}, found {:?}", name, other)),
+            };
+            if variants.iter().any(|(existing, _)| *existing == variant) {
+                return Err(format!("Duplicate variant '{}' in enum {}", variant, name));
+            }
+            self.advance();
+
+            let mut fields = Vec::new();
+            if *self.current_token() == Token::LeftParen {
+                self.advance();
+                while *self.current_token() != Token::RightParen {
+                    match self.current_token().clone() {
+                        Token::Identifier(field) => {
+                            fields.push(field);
+                            self.advance();
+                        }
+                        other => return Err(format!("Expected field name in variant {}.{}, found {:?}", name, variant, other)),
+                    }
+                    match self.current_token() {
+                        Token::Comma => self.advance(),
+                        Token::RightParen => {}
+                        other => return Err(format!("Expected ',' or ')' in variant {}.{}, found {:?}", name, variant, other)),
+                    }
+                }
+                self.consume(&Token::RightParen)?;
+            }
+            variants.push((variant, fields));
+
+            match self.current_token() {
+                Token::Comma => self.advance(),
+                Token::RightCurly => {}
+                other => return Err(format!("Expected ',' or '}}' in enum declaration, found {:?}", other)),
+            }
+        }
+        self.consume(&Token::RightCurly)?;
+
+        self.enums.insert(name.clone(), variants.iter().map(|(variant, _)| variant.clone()).collect());
+        Ok(AstNode::EnumDeclaration { name, variants })
+    }
+
+    // Name { field: value, ... } where `field` alone is shorthand for `field: field`
+    fn parse_struct_literal(&mut self, name: String) -> Result<AstNode, String> {
+        self.consume(&Token::LeftCurly)?;
+        let restricted = std::mem::replace(&mut self.no_struct_literal, false);
+
+        let mut fields = Vec::new();
+        let result = loop {
+            if *self.current_token() == Token::RightCurly {
+                break Ok(());
+            }
+            let field = match self.current_token().clone() {
+                Token::Identifier(field) => field,
+                other => break Err(format!("Expected field name in {} literal, found {:?}", name, other)),
+            };
+            self.advance();
+            let value = if *self.current_token() == Token::Colon {
+                self.advance();
+                match self.parse_expression() {
+                    Ok(value) => value,
+                    Err(e) => break Err(e),
+                }
+            } else {
+                AstNode::Identifier(field.clone())
+            };
+            fields.push((field, value));
+            match self.current_token() {
+                Token::Comma => self.advance(),
+                Token::RightCurly => {}
+                other => break Err(format!("Expected ',' or '}}' in {} literal, found {:?}", name, other)),
+            }
+        };
+
+        self.no_struct_literal = restricted;
+        result?;
+        self.consume(&Token::RightCurly)?;
+        Ok(AstNode::StructLiteral { name, fields })
+    }
+
+    // Turns `target = value`, `target += value` and `target++` into assignment nodes.
+    // Any other expression statement is returned unchanged
+    fn parse_assignment(&mut self, target: AstNode) -> Result<AstNode, String> {
+        let operator = match self.current_token() {
+            Token::Assign => None,
+            Token::PlusAssign | Token::Increment => Some(Token::Plus),
+            Token::MinusAssign | Token::Decrement => Some(Token::Minus),
+            Token::MultiplyAssign => Some(Token::Multiply),
+            Token::DivideAssign => Some(Token::Divide),
+            Token::ModuloAssign => Some(Token::Modulo),
+            _ => return Ok(target),
+        };
+
+        if !matches!(target, AstNode::Identifier(_) | AstNode::IndexExpression(..) | AstNode::FieldAccess { .. }) {
+            return Err(format!("Invalid assignment target: {:?}", target));
+        }
+
+        let is_step = matches!(self.current_token(), Token::Increment | Token::Decrement);
+        self.advance();
+        let value = if is_step {
+            AstNode::NumberLiteral(1.0)
+        } else {
+            self.parse_expression()?
+        };
+
+        Ok(match (operator, target) {
+            (None, target) => AstNode::Assignment {
+                target: Box::new(target),
+                value: Box::new(value),
+            },
+            (Some(operator), target) => AstNode::CompoundAssignment {
+                target: Box::new(target),
+                operator,
+                value: Box::new(value),
+            },
+        })
+    }
+
+    fn parse_interpolated_string(&mut self, parts: Vec<StringPart>) -> Result<AstNode, String> {
+        let mut nodes = Vec::new();
+
+        for part in parts {
+            match part {
+                StringPart::Literal(text) => nodes.push(InterpolationPart::Literal(text)),
+                StringPart::Expression { tokens, format } => {
+                    let mut parser = Parser::new(tokens);
+                    let parsed = parser.parse_expression().and_then(|expr| match parser.current_token() {
+                        Token::Eof => Ok(expr),
+                        token => Err(format!("Unexpected token in string interpolation: {:?}", token)),
+                    });
+                    // errors are reported where they are inside the hole
+                    let expr = parsed.inspect_err(|_| self.error_span = Some(parser.error_span()))?;
+                    let format = match format {
+                        Some(spec) => FormatSpec::parse(&spec)?,
+                        None => FormatSpec::default(),
+                    };
+                    nodes.push(InterpolationPart::Expression {
+                        expr: Box::new(expr),
+                        format,
+                    });
+                }
+            }
+        }
+
+        Ok(AstNode::InterpolatedString(nodes))
+    }
+
     fn parse_array_literal(&mut self) -> Result<AstNode, String> {
         self.consume(&Token::LeftBracket)?;
 
@@ -402,12 +1188,24 @@ impl Parser {
                 continue;
             }
             
-            if *self.current_token() == Token::RightCurly {
-                break;
-            }
-            
-            let stmt = self.parse_statement()?;
-            let is_block = matches!(&stmt, AstNode::IfStatement { .. } | AstNode::WhileStatement { .. });
+            let start = self.position;
+            let stmt = match self.parse_statement() {
+                Ok(stmt) => stmt,
+                Err(message) => {
+                    self.record_error(message, start, true);
+                    continue;
+                }
+            };
+            let is_block = matches!(
+                &stmt,
+                AstNode::IfStatement { .. }
+                    | AstNode::WhileStatement { .. }
+                    | AstNode::TryStatement { .. }
+                    | AstNode::Match { .. }
+                    | AstNode::StructDeclaration { .. }
+                    | AstNode::EnumDeclaration { .. }
+                    | AstNode::ImplBlock { .. }
+            );
             statements.push(stmt);
             
             // Handle semicolons based on statement type
@@ -420,7 +1218,9 @@ impl Parser {
                 // Require semicolons after non-block statements unless followed by }
                 if *self.current_token() != Token::RightCurly {
                     if *self.current_token() != Token::Semicolon {
-                        return Err(format!("Expected Semicolon or }}, found {:?}", self.current_token()));
+                        let message = format!("Expected Semicolon or }}, found {:?}", self.current_token());
+                        self.record_error(message, start, true);
+                        continue;
                     }
                     self.advance();
                 }
@@ -434,12 +1234,17 @@ impl Parser {
     fn parse_if_statement(&mut self) -> Result<AstNode, String> {
         self.consume(&Token::If)?;
         
-        let condition = self.parse_expression()?;
+        let condition = self.parse_condition()?;
         let consequence = self.parse_block()?;
         
         let alternative = if *self.current_token() == Token::Else {
             self.advance();
-            Some(Box::new(self.parse_block()?))
+            // `else if` chains nest the next if statement as the alternative
+            if *self.current_token() == Token::If {
+                Some(Box::new(self.parse_if_statement()?))
+            } else {
+                Some(Box::new(self.parse_block()?))
+            }
         } else {
             None
         };
@@ -454,7 +1259,7 @@ impl Parser {
     fn parse_while_statement(&mut self) -> Result<AstNode, String> {
         self.consume(&Token::While)?;
         
-        let condition = self.parse_expression()?;
+        let condition = self.parse_condition()?;
         let body = self.parse_block()?;
         
         Ok(AstNode::WhileStatement {
@@ -463,6 +1268,42 @@ impl Parser {
         })
     }
 
+    // try block (catch name? block)? (finally block)?, with at least one of catch or finally
+    fn parse_try_statement(&mut self) -> Result<AstNode, String> {
+        self.consume(&Token::Try)?;
+        let body = self.parse_block()?;
+
+        let catch = if *self.current_token() == Token::Catch {
+            self.advance();
+            let name = match self.current_token().clone() {
+                Token::Identifier(name) => {
+                    self.advance();
+                    Some(Pattern::Identifier(name))
+                }
+                _ => None,
+            };
+            Some((name, Box::new(self.parse_block()?)))
+        } else {
+            None
+        };
+
+        let finally = if *self.current_token() == Token::Finally {
+            self.advance();
+            Some(Box::new(self.parse_block()?))
+        } else {
+            None
+        };
+
+        if catch.is_none() && finally.is_none() {
+            return Err(format!("Expected 'catch' or 'finally' after try block, found {:?}", self.current_token()));
+        }
+
+        Ok(AstNode::TryStatement {
+            body: Box::new(body),
+            catch,
+            finally,
+        })
+    }
 
     fn parse_function_declaration(&mut self) -> Result<AstNode, String> {
         self.advance();
@@ -484,9 +1325,8 @@ impl Parser {
         if *self.current_token() != Token::RightParen {
             loop {
                 match self.current_token() {
-                    Token::Identifier(ref param) => {
-                        params.push(param.clone());
-                        self.advance();
+                    Token::Identifier(_) | Token::LeftBracket | Token::LeftCurly => {
+                        params.push(self.parse_pattern()?);
                     }
                     _ => return Err(format!("Expected parameter name, found {:?}", self.current_token())),
                 }
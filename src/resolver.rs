@@ -0,0 +1,558 @@
+use std::collections::{HashMap, HashSet};
+use crate::ast::{AstNode, Binding, InterpolationPart, MatchArm, Pattern};
+use crate::builtins;
+
+// Slots of the global variables. The interpreter keeps one for its whole lifetime
+// so REPL lines resolve against the globals declared by earlier lines
+#[derive(Default)]
+pub struct GlobalScope {
+    slots: HashMap<String, usize>,
+    constants: HashSet<String>,
+    // names of the methods declared in impl blocks, which may shadow built-in ones such as push
+    methods: HashSet<String>,
+}
+
+impl GlobalScope {
+    // The slot of a global, allocating one the first time the name is seen
+    pub fn slot(&mut self, name: &str) -> usize {
+        let next = self.slots.len();
+        *self.slots.entry(name.to_string()).or_insert(next)
+    }
+
+    pub fn get(&self, name: &str) -> Option<usize> {
+        self.slots.get(name).copied()
+    }
+}
+
+pub struct ResolveOutput {
+    pub program: AstNode,
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+struct Local {
+    slot: usize,
+    constant: bool,
+    used: bool,
+    // declared by let/const, so worth a warning when it is never read
+    reportable: bool,
+}
+
+struct FunctionScope {
+    // None for the top level
+    name: Option<String>,
+    // match arms open a block of their own on top of the function body's
+    blocks: Vec<HashMap<String, Local>>,
+    next_slot: usize,
+}
+
+impl FunctionScope {
+    fn new(name: Option<String>) -> Self {
+        Self { name, blocks: vec![HashMap::new()], next_slot: 0 }
+    }
+
+    fn describe(&self) -> String {
+        match &self.name {
+            Some(name) => format!("function {}", name),
+            None => "the top level".to_string(),
+        }
+    }
+}
+
+enum Lookup {
+    Local(usize),
+    // a local of a function enclosing the current one, which functions can't see
+    Enclosing,
+    Global(usize),
+    Missing,
+}
+
+// How the names in a pattern are resolved
+#[derive(Clone, Copy)]
+enum PatternMode {
+    Declare { constant: bool, reportable: bool },
+    Assign,
+}
+
+// Binds every identifier to a local slot of the function it appears in or to a global slot,
+// rewriting declarations into assignments to their resolved variable.
+// Reports undeclared variables and constant misuse as errors, shadowed and unused locals as warnings
+pub struct Resolver<'g> {
+    globals: &'g mut GlobalScope,
+    // names declared by top level statements, usable by functions defined before them
+    top_level: HashSet<String>,
+    functions: Vec<FunctionScope>,
+    strict: bool,
+    errors: Vec<String>,
+    warnings: Vec<String>,
+}
+
+impl<'g> Resolver<'g> {
+    pub fn new(globals: &'g mut GlobalScope, strict: bool) -> Self {
+        Self {
+            globals,
+            top_level: HashSet::new(),
+            functions: vec![FunctionScope::new(None)],
+            strict,
+            errors: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    pub fn resolve_program(mut self, program: AstNode) -> ResolveOutput {
+        if let AstNode::Program(statements) = &program {
+            for statement in statements {
+                self.collect_top_level(statement);
+            }
+        }
+        let program = self.resolve(program);
+        ResolveOutput { program, errors: self.errors, warnings: self.warnings }
+    }
+
+    // Records the names a top level statement declares, looking inside blocks but not functions
+    fn collect_top_level(&mut self, node: &AstNode) {
+        match node {
+            AstNode::VariableDeclaration { name, .. }
+            | AstNode::FunctionDeclaration { name, .. }
+            | AstNode::StructDeclaration { name, .. }
+            | AstNode::EnumDeclaration { name, .. } => {
+                self.top_level.insert(name.clone());
+            }
+            AstNode::DestructuringDeclaration { pattern, .. } => {
+                self.top_level.extend(pattern.bound_names().into_iter().map(str::to_string));
+            }
+            AstNode::DestructuringAssignment { pattern, .. } if !self.strict => {
+                self.top_level.extend(pattern.bound_names().into_iter().map(str::to_string));
+            }
+            AstNode::Assignment { target, .. } if !self.strict => {
+                if let AstNode::Identifier(name) = &**target {
+                    self.top_level.insert(name.clone());
+                }
+            }
+            AstNode::ImplBlock { methods, .. } => {
+                let names = methods.iter().filter_map(|method| match method {
+                    AstNode::FunctionDeclaration { name, .. } => Some(name.clone()),
+                    _ => None,
+                });
+                self.globals.methods.extend(names);
+            }
+            AstNode::Block(statements) => {
+                for statement in statements {
+                    self.collect_top_level(statement);
+                }
+            }
+            AstNode::IfStatement { consequence, alternative, .. } => {
+                self.collect_top_level(consequence);
+                if let Some(alternative) = alternative {
+                    self.collect_top_level(alternative);
+                }
+            }
+            AstNode::WhileStatement { body, .. } => self.collect_top_level(body),
+            AstNode::TryStatement { body, catch, finally } => {
+                self.collect_top_level(body);
+                if let Some((pattern, handler)) = catch {
+                    let names = pattern.iter().flat_map(Pattern::bound_names).map(str::to_string);
+                    self.top_level.extend(names);
+                    self.collect_top_level(handler);
+                }
+                if let Some(finally) = finally {
+                    self.collect_top_level(finally);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn current(&mut self) -> &mut FunctionScope {
+        self.functions.last_mut().expect("resolver always has a function scope")
+    }
+
+    // Declarations outside functions and match arms create globals
+    fn at_top_level(&self) -> bool {
+        self.functions.len() == 1 && self.functions[0].blocks.len() == 1
+    }
+
+    fn lookup(&mut self, name: &str, mark_used: bool) -> Lookup {
+        for block in self.current().blocks.iter_mut().rev() {
+            if let Some(local) = block.get_mut(name) {
+                local.used |= mark_used;
+                return Lookup::Local(local.slot);
+            }
+        }
+        let enclosing = &self.functions[..self.functions.len() - 1];
+        if enclosing.iter().any(|function| function.blocks.iter().any(|block| block.contains_key(name))) {
+            return Lookup::Enclosing;
+        }
+        match self.globals.get(name) {
+            Some(slot) => Lookup::Global(slot),
+            None if self.top_level.contains(name) => Lookup::Global(self.globals.slot(name)),
+            None => Lookup::Missing,
+        }
+    }
+
+    fn is_constant(&self, name: &str, binding: Binding) -> bool {
+        match binding {
+            Binding::Local(_) => {
+                let function = self.functions.last().expect("resolver always has a function scope");
+                function.blocks.iter().rev().find_map(|block| block.get(name)).is_some_and(|local| local.constant)
+            }
+            Binding::Global(_) => self.globals.constants.contains(name),
+        }
+    }
+
+    fn declare(&mut self, name: &str, constant: bool, reportable: bool) -> Binding {
+        if self.at_top_level() {
+            if self.globals.constants.contains(name) {
+                self.errors.push(format!("Cannot redeclare constant '{}'", name));
+            } else if constant {
+                self.globals.constants.insert(name.to_string());
+            }
+            return Binding::Global(self.globals.slot(name));
+        }
+
+        let scope_name = self.current().describe();
+        let mut errors = Vec::new();
+        let mut warnings = Vec::new();
+        let function = self.current();
+        let shadowed = function.blocks.iter().any(|block| block.contains_key(name));
+        if shadowed && reportable {
+            warnings.push(format!("Variable '{}' in {} shadows an earlier declaration", name, scope_name));
+        }
+        let block = function.blocks.last_mut().expect("function scope always has a block");
+        let slot = match block.get_mut(name) {
+            Some(local) => {
+                if local.constant {
+                    errors.push(format!("Cannot redeclare constant '{}'", name));
+                }
+                if let Some(warning) = unused_warning(name, local, &scope_name) {
+                    warnings.push(warning);
+                }
+                *local = Local { slot: local.slot, constant, used: false, reportable };
+                local.slot
+            }
+            None => {
+                let slot = function.next_slot;
+                function.next_slot += 1;
+                let block = function.blocks.last_mut().expect("function scope always has a block");
+                block.insert(name.to_string(), Local { slot, constant, used: false, reportable });
+                slot
+            }
+        };
+        self.errors.extend(errors);
+        self.warnings.extend(warnings);
+        Binding::Local(slot)
+    }
+
+    // Binds a name being read
+    fn use_variable(&mut self, name: String) -> AstNode {
+        let binding = match self.lookup(&name, true) {
+            Lookup::Local(slot) => Binding::Local(slot),
+            Lookup::Global(slot) => Binding::Global(slot),
+            Lookup::Enclosing => {
+                self.errors.push(format!("Cannot use local variable '{}' of an enclosing function", name));
+                Binding::Global(0)
+            }
+            Lookup::Missing => {
+                self.errors.push(format!("Use of undeclared variable '{}'", name));
+                Binding::Global(0)
+            }
+        };
+        AstNode::Variable { name, binding }
+    }
+
+    // Binds a name being assigned to, declaring it if it doesn't exist and strict mode is off
+    fn assign_variable(&mut self, name: String, mark_used: bool) -> Binding {
+        let binding = match self.lookup(&name, mark_used) {
+            Lookup::Local(slot) => Binding::Local(slot),
+            Lookup::Global(slot) => Binding::Global(slot),
+            Lookup::Enclosing => {
+                self.errors.push(format!("Cannot use local variable '{}' of an enclosing function", name));
+                return Binding::Global(0);
+            }
+            // declared anyway so later uses aren't reported too
+            Lookup::Missing if self.strict => {
+                self.errors.push(format!("Assignment to undeclared variable: {}", name));
+                return self.declare(&name, false, false);
+            }
+            Lookup::Missing => return self.declare(&name, false, false),
+        };
+        if self.is_constant(&name, binding) {
+            self.errors.push(format!("Cannot assign to constant '{}'", name));
+        }
+        binding
+    }
+
+    // Resolves an assignment target such as x, grid[i][j] or p.x.
+    // Assigning inside a variable's value counts as reading it
+    fn resolve_target(&mut self, target: AstNode, mark_used: bool) -> AstNode {
+        match target {
+            AstNode::Identifier(name) => {
+                let binding = self.assign_variable(name.clone(), mark_used);
+                AstNode::Variable { name, binding }
+            }
+            AstNode::IndexExpression(object, index, span) => {
+                let object = self.resolve_target(*object, true);
+                AstNode::IndexExpression(Box::new(object), Box::new(self.resolve(*index)), span)
+            }
+            AstNode::FieldAccess { object, field, span } => {
+                let object = self.resolve_target(*object, true);
+                AstNode::FieldAccess { object: Box::new(object), field, span }
+            }
+            other => self.resolve(other),
+        }
+    }
+
+    // Resolves the receiver of a built-in method that changes it, such as xs in xs.push(1). The
+    // receiver must already exist and is written back afterwards, so it can't be a constant
+    fn resolve_receiver(&mut self, receiver: AstNode) -> AstNode {
+        match receiver {
+            AstNode::Identifier(name) => {
+                let variable = self.use_variable(name);
+                if let AstNode::Variable { name, binding } = &variable {
+                    if self.is_constant(name, *binding) {
+                        self.errors.push(format!("Cannot assign to constant '{}'", name));
+                    }
+                }
+                variable
+            }
+            AstNode::IndexExpression(object, index, span) => {
+                let object = self.resolve_receiver(*object);
+                AstNode::IndexExpression(Box::new(object), Box::new(self.resolve(*index)), span)
+            }
+            AstNode::FieldAccess { object, field, span } => {
+                let object = self.resolve_receiver(*object);
+                AstNode::FieldAccess { object: Box::new(object), field, span }
+            }
+            other => self.resolve(other),
+        }
+    }
+
+    fn resolve_pattern(&mut self, pattern: Pattern, mode: PatternMode) -> Pattern {
+        match pattern {
+            Pattern::Identifier(name) => {
+                let binding = match mode {
+                    PatternMode::Declare { constant, reportable } => self.declare(&name, constant, reportable),
+                    PatternMode::Assign => self.assign_variable(name.clone(), false),
+                };
+                Pattern::Variable { name, binding }
+            }
+            Pattern::Array { elements, rest } => Pattern::Array {
+                elements: elements.into_iter().map(|p| self.resolve_pattern(p, mode)).collect(),
+                rest: rest.map(|p| Box::new(self.resolve_pattern(*p, mode))),
+            },
+            Pattern::Dictionary(entries) => Pattern::Dictionary(
+                entries.into_iter().map(|(key, p)| (key, self.resolve_pattern(p, mode))).collect(),
+            ),
+            // every alternative binds the same names, so they resolve to the same slots
+            Pattern::Or(alternatives) => {
+                Pattern::Or(alternatives.into_iter().map(|p| self.resolve_pattern(p, mode)).collect())
+            }
+            Pattern::Variant { enum_name, variant, fields } => Pattern::Variant {
+                enum_name,
+                variant,
+                fields: fields.map(|fields| fields.into_iter().map(|p| self.resolve_pattern(p, mode)).collect()),
+            },
+            other => other,
+        }
+    }
+
+    fn resolve_function(&mut self, name: String, display_name: String, params: Vec<Pattern>, body: AstNode) -> AstNode {
+        self.functions.push(FunctionScope::new(Some(display_name)));
+        let params = params
+            .into_iter()
+            .map(|p| self.resolve_pattern(p, PatternMode::Declare { constant: false, reportable: false }))
+            .collect();
+        let body = self.resolve(body);
+        let function = self.functions.pop().expect("function scope was pushed");
+        let scope_name = function.describe();
+        for block in function.blocks {
+            self.report_unused(block, &scope_name);
+        }
+        AstNode::FunctionDeclaration { name, params, body: Box::new(body) }
+    }
+
+    fn report_unused(&mut self, block: HashMap<String, Local>, scope_name: &str) {
+        let mut unused: Vec<String> = block
+            .iter()
+            .filter_map(|(name, local)| unused_warning(name, local, scope_name))
+            .collect();
+        unused.sort();
+        self.warnings.extend(unused);
+    }
+
+    fn resolve_arm(&mut self, arm: MatchArm) -> MatchArm {
+        self.current().blocks.push(HashMap::new());
+        let pattern = self.resolve_pattern(arm.pattern, PatternMode::Declare { constant: false, reportable: false });
+        let guard = arm.guard.map(|guard| self.resolve(guard));
+        let body = self.resolve(arm.body);
+        let block = self.current().blocks.pop().expect("arm block was pushed");
+        let scope_name = self.current().describe();
+        self.report_unused(block, &scope_name);
+        MatchArm { pattern, guard, body }
+    }
+
+    fn resolve_all(&mut self, nodes: Vec<AstNode>) -> Vec<AstNode> {
+        nodes.into_iter().map(|node| self.resolve(node)).collect()
+    }
+
+    // Resolves a child node in place, reusing its allocation
+    fn resolve_boxed(&mut self, mut node: Box<AstNode>) -> Box<AstNode> {
+        *node = self.resolve(std::mem::replace(&mut *node, AstNode::Nil));
+        node
+    }
+
+    fn resolve(&mut self, node: AstNode) -> AstNode {
+        match node {
+            AstNode::Program(statements) => AstNode::Program(self.resolve_all(statements)),
+            AstNode::Block(statements) => AstNode::Block(self.resolve_all(statements)),
+            AstNode::ArrayLiteral(elements) => AstNode::ArrayLiteral(self.resolve_all(elements)),
+            AstNode::VariableDeclaration { name, value, constant } => {
+                // the value can still refer to a variable of the same name being shadowed
+                let value = self.resolve_boxed(value);
+                let binding = self.declare(&name, constant, true);
+                AstNode::Assignment { target: Box::new(AstNode::Variable { name, binding }), value }
+            }
+            AstNode::DestructuringDeclaration { pattern, value, constant } => {
+                let value = self.resolve_boxed(value);
+                let pattern = self.resolve_pattern(pattern, PatternMode::Declare { constant, reportable: true });
+                AstNode::DestructuringAssignment { pattern, value }
+            }
+            AstNode::DestructuringAssignment { pattern, value } => {
+                let value = self.resolve_boxed(value);
+                let pattern = self.resolve_pattern(pattern, PatternMode::Assign);
+                AstNode::DestructuringAssignment { pattern, value }
+            }
+            AstNode::Assignment { target, value } => {
+                let value = self.resolve_boxed(value);
+                let target = self.resolve_target(*target, false);
+                AstNode::Assignment { target: Box::new(target), value }
+            }
+            AstNode::CompoundAssignment { target, operator, value } => {
+                let target = self.resolve_target(*target, true);
+                let value = self.resolve_boxed(value);
+                AstNode::CompoundAssignment { target: Box::new(target), operator, value }
+            }
+            AstNode::BinaryExpression { left, operator, right, span } => AstNode::BinaryExpression {
+                left: self.resolve_boxed(left),
+                operator,
+                right: self.resolve_boxed(right),
+                span,
+            },
+            AstNode::UnaryExpression { operator, operand, span } => {
+                AstNode::UnaryExpression { operator, operand: self.resolve_boxed(operand), span }
+            }
+            AstNode::InterpolatedString(parts) => AstNode::InterpolatedString(
+                parts
+                    .into_iter()
+                    .map(|part| match part {
+                        InterpolationPart::Expression { expr, format } => {
+                            InterpolationPart::Expression { expr: self.resolve_boxed(expr), format }
+                        }
+                        literal => literal,
+                    })
+                    .collect(),
+            ),
+            AstNode::Identifier(name) => self.use_variable(name),
+            AstNode::PrintStatement(expr) => AstNode::PrintStatement(self.resolve_boxed(expr)),
+            AstNode::DictionaryLiteral(pairs) => AstNode::DictionaryLiteral(
+                pairs.into_iter().map(|(key, value)| (key, self.resolve(value))).collect(),
+            ),
+            AstNode::IndexExpression(object, index, span) => {
+                AstNode::IndexExpression(self.resolve_boxed(object), self.resolve_boxed(index), span)
+            }
+            AstNode::IfStatement { condition, consequence, alternative } => AstNode::IfStatement {
+                condition: self.resolve_boxed(condition),
+                consequence: self.resolve_boxed(consequence),
+                alternative: alternative.map(|alternative| self.resolve_boxed(alternative)),
+            },
+            AstNode::WhileStatement { condition, body } => AstNode::WhileStatement {
+                condition: self.resolve_boxed(condition),
+                body: self.resolve_boxed(body),
+            },
+            AstNode::FunctionDeclaration { name, params, body } => {
+                // declared first so a top level function can call itself
+                let binding = self.declare(&name, false, false);
+                let function = self.resolve_function(name.clone(), name.clone(), params, *body);
+                AstNode::Assignment { target: Box::new(AstNode::Variable { name, binding }), value: Box::new(function) }
+            }
+            AstNode::FunctionCall { function, arguments, span } => AstNode::FunctionCall {
+                function: self.resolve_boxed(function),
+                arguments: self.resolve_all(arguments),
+                span,
+            },
+            AstNode::Return(expr) => AstNode::Return(expr.map(|expr| self.resolve_boxed(expr))),
+            AstNode::Match { subject, arms, span } => AstNode::Match {
+                subject: self.resolve_boxed(subject),
+                arms: arms.into_iter().map(|arm| self.resolve_arm(arm)).collect(),
+                span,
+            },
+            AstNode::StructDeclaration { name, fields } => {
+                let slot = self.globals.slot(&name);
+                let target = AstNode::Variable { name: name.clone(), binding: Binding::Global(slot) };
+                AstNode::Assignment { target: Box::new(target), value: Box::new(AstNode::StructDeclaration { name, fields }) }
+            }
+            AstNode::EnumDeclaration { name, variants } => {
+                let slot = self.globals.slot(&name);
+                let target = AstNode::Variable { name: name.clone(), binding: Binding::Global(slot) };
+                AstNode::Assignment { target: Box::new(target), value: Box::new(AstNode::EnumDeclaration { name, variants }) }
+            }
+            AstNode::StructLiteral { name, fields } => AstNode::StructLiteral {
+                name,
+                fields: fields.into_iter().map(|(field, value)| (field, self.resolve(value))).collect(),
+            },
+            AstNode::FieldAccess { object, field, span } => {
+                AstNode::FieldAccess { object: self.resolve_boxed(object), field, span }
+            }
+            AstNode::ImplBlock { type_name, methods } => {
+                let methods = methods
+                    .into_iter()
+                    .map(|method| match method {
+                        AstNode::FunctionDeclaration { name, params, body } => {
+                            self.globals.methods.insert(name.clone());
+                            let display_name = format!("{}.{}", type_name, name);
+                            self.resolve_function(name, display_name, params, *body)
+                        }
+                        other => other,
+                    })
+                    .collect();
+                AstNode::ImplBlock { type_name, methods }
+            }
+            AstNode::MethodCall { object, method, arguments, span } => {
+                // built-in methods that change their receiver write it back, unless an impl
+                // declares a method of the same name that the call may be meant for
+                let object = if builtins::mutates(&method) && !self.globals.methods.contains(&method) {
+                    self.resolve_receiver(*object)
+                } else {
+                    self.resolve(*object)
+                };
+                AstNode::MethodCall { object: Box::new(object), method, arguments: self.resolve_all(arguments), span }
+            }
+            AstNode::IsPattern { value, pattern } => AstNode::IsPattern {
+                value: self.resolve_boxed(value),
+                pattern: self.resolve_pattern(pattern, PatternMode::Declare { constant: false, reportable: false }),
+            },
+            AstNode::Throw(expr, span) => AstNode::Throw(self.resolve_boxed(expr), span),
+            AstNode::Propagate(expr) => AstNode::Propagate(self.resolve_boxed(expr)),
+            AstNode::TryStatement { body, catch, finally } => AstNode::TryStatement {
+                body: self.resolve_boxed(body),
+                catch: catch.map(|(pattern, handler)| {
+                    let mode = PatternMode::Declare { constant: false, reportable: false };
+                    let pattern = pattern.map(|p| self.resolve_pattern(p, mode));
+                    (pattern, self.resolve_boxed(handler))
+                }),
+                finally: finally.map(|finally| self.resolve_boxed(finally)),
+            },
+            other @ (AstNode::NumberLiteral(_)
+            | AstNode::StringLiteral(_)
+            | AstNode::Variable { .. }
+            | AstNode::Bool(_)
+            | AstNode::Break
+            | AstNode::Nil) => other,
+        }
+    }
+}
+
+fn unused_warning(name: &str, local: &Local, scope_name: &str) -> Option<String> {
+    (local.reportable && !local.used && !name.starts_with('_'))
+        .then(|| format!("Variable '{}' in {} is never used", name, scope_name))
+}
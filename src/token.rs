@@ -1,6 +1,7 @@
 #[derive(Debug, PartialEq, Clone)]
 pub enum Token {
     Let,                // Keyword: let
+    Const,              // Keyword: const
     Print,              // Keyword: print
     If,                 // Keyword: if
     Else,               // Keyword: else
@@ -11,12 +12,21 @@ pub enum Token {
     Identifier(String), // Identifiers: variable names
     Number(f64),        // Numbers/ floats
     StringLiteral(String), // For string literals
+    InterpolatedString(Vec<StringPart>), // String literals containing {expr} holes
     Assign,             // '=' symbol
     Equal,              // '==' symbol
     Plus,               // '+' symbol
     Minus,              // '-' symbol
     Multiply,           // '*' symbol
     Divide,             // '/' symbol
+    Modulo,             // '%' symbol
+    PlusAssign,         // '+=' symbol
+    MinusAssign,        // '-=' symbol
+    MultiplyAssign,     // '*=' symbol
+    DivideAssign,       // '/=' symbol
+    ModuloAssign,       // '%=' symbol
+    Increment,          // '++' symbol
+    Decrement,          // '--' symbol
     LessThan,           // '<' symbol
     GreaterThan,        // '>' symbol
     LeftParen,          // '(' symbol
@@ -28,6 +38,22 @@ pub enum Token {
     Colon,              // ':'
     Comma,              // ','
     Semicolon,          // ';'
+    Ellipsis,           // '...' used for rest patterns
+    DotDot,             // '..' exclusive range pattern
+    DotDotEqual,        // '..=' inclusive range pattern
+    FatArrow,           // '=>' separating a match arm's pattern from its body
+    Pipe,               // '|' between alternative patterns
+    Match,              // Keyword: match
+    Struct,             // Keyword: struct
+    Impl,               // Keyword: impl
+    Enum,               // Keyword: enum
+    Is,                 // Keyword: is
+    Throw,              // Keyword: throw
+    Try,                // Keyword: try
+    Catch,              // Keyword: catch
+    Finally,            // Keyword: finally
+    Dot,                // '.' for field access
+    Question,           // '?' propagating an error result
     True,               // Keyword: true
     False,              // Keyword: false
     Or,                 // Keyword: or
@@ -35,4 +61,27 @@ pub enum Token {
     Nil,                // Keyword: nil
     Eof,                // End of file/input
     Unknown(char),      // Unknown character
-}
\ No newline at end of file
+}
+
+// A piece of an interpolated string literal. Expressions are lexed up front with their
+// positions in the source, the format spec (everything after a top level ':') is kept raw for the parser
+#[derive(Debug, PartialEq, Clone)]
+pub enum StringPart {
+    Literal(String),
+    Expression { tokens: Vec<SpannedToken>, format: Option<String> },
+}
+
+// Byte range of a token in the source plus the line/column it starts on
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
+}
@@ -1,7 +1,11 @@
 use std::fmt;
 use std::collections::HashMap;
 use std::ops::{Add, Sub, Mul, Div};
-use crate::ast::AstNode;
+use crate::ast::{Alignment, AstNode, FormatSpec, Pattern};
+use crate::interpreter::{Interpreter, InterpreterError};
+
+// Signature of functions implemented in Rust and callable from scripts
+pub type NativeFn = fn(&mut Interpreter, Vec<Value>) -> Result<Value, InterpreterError>;
 
 #[derive(Debug, Clone)]
 pub enum Value {
@@ -12,9 +16,50 @@ pub enum Value {
     Bool(bool),
     Function {
         name: String,
-        params: Vec<String>,
+        params: Vec<Pattern>,
         body: Box<AstNode>,
     },
+    NativeFunction {
+        name: String,
+        func: NativeFn,
+    },
+    // a function compiled for the bytecode VM, by its index in the compiled program
+    CompiledFunction {
+        name: String,
+        index: usize,
+    },
+    // the value bound to a struct's name by `struct Name { ... }`
+    StructType {
+        name: String,
+        fields: Vec<String>,
+    },
+    // an instance of a user-defined struct, fields kept in declaration order
+    Struct {
+        name: String,
+        fields: Vec<(String, Value)>,
+    },
+    // the value bound to an enum's name by `enum Name { ... }`, with each variant's payload size
+    EnumType {
+        name: String,
+        variants: Vec<(String, usize)>,
+    },
+    // a variant that takes a payload, before it is called, e.g. `Shape.Circle`
+    EnumConstructor {
+        name: String,
+        variant: String,
+        arity: usize,
+    },
+    // an enum value such as Shape.Circle(2) or Shape.Empty
+    Enum {
+        name: String,
+        variant: String,
+        values: Vec<Value>,
+    },
+    // an object made by a builtin, such as the iterator returned by stdin_lines(). Scripts can
+    // only call the native methods of its type
+    Native {
+        type_name: &'static str,
+    },
     Nil, // for functions that don't return anything
 }
 
@@ -37,69 +82,200 @@ impl fmt::Display for Value {
             }
             Value::Bool(b) => write!(f, "{}", b),
             Value::Nil => write!(f, "nil"),
-            _ => panic!("Cannot display what you are trying to display."),
+            Value::Function { name, .. } | Value::CompiledFunction { name, .. } => write!(f, "<func {}>", name),
+            Value::NativeFunction { name, .. } => write!(f, "<builtin {}>", name),
+            Value::StructType { name, .. } => write!(f, "<struct {}>", name),
+            Value::Struct { name, fields } => {
+                let fields: Vec<String> = fields.iter()
+                    .map(|(field, value)| format!("{}: {}", field, value))
+                    .collect();
+                write!(f, "{} {{ {} }}", name, fields.join(", "))
+            }
+            Value::EnumType { name, .. } => write!(f, "<enum {}>", name),
+            Value::EnumConstructor { name, variant, .. } => write!(f, "<variant {}.{}>", name, variant),
+            Value::Enum { name, variant, values } => {
+                write!(f, "{}.{}", name, variant)?;
+                if !values.is_empty() {
+                    let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+                    write!(f, "({})", values.join(", "))?;
+                }
+                Ok(())
+            }
+            Value::Native { type_name } => write!(f, "<{}>", type_name),
         }
     }
 }
 
+impl Value {
+    // The name reported by `type()`; instances report their struct's or enum's name
+    pub fn type_name(&self) -> &str {
+        match self {
+            Value::Number(_) => "number",
+            Value::String(_) => "string",
+            Value::Array(_) => "array",
+            Value::Dictionary(_) => "dictionary",
+            Value::Bool(_) => "bool",
+            Value::Function { .. }
+            | Value::NativeFunction { .. }
+            | Value::CompiledFunction { .. }
+            | Value::EnumConstructor { .. } => "function",
+            Value::StructType { .. } => "struct",
+            Value::Struct { name, .. } => name,
+            Value::EnumType { .. } => "enum",
+            Value::Enum { name, .. } => name,
+            Value::Native { type_name } => type_name,
+            Value::Nil => "nil",
+        }
+    }
+
+    // The value scripts see for a thrown or built-in error: an `Error` struct with the
+    // message, a kind such as "KeyError", and the names of the functions active when it was raised
+    pub fn error(kind: &str, message: String, stack: Value) -> Value {
+        Value::Struct {
+            name: "Error".to_string(),
+            fields: vec![
+                ("message".to_string(), Value::String(message)),
+                ("kind".to_string(), Value::String(kind.to_string())),
+                ("stack".to_string(), stack),
+            ],
+        }
+    }
+
+    // Values of the built-in `Result` enum, as returned by ok(), err() and fallible builtins
+    pub fn ok(value: Value) -> Value {
+        Value::Enum {
+            name: "Result".to_string(),
+            variant: "Ok".to_string(),
+            values: vec![value],
+        }
+    }
+
+    pub fn err(error: Value) -> Value {
+        Value::Enum {
+            name: "Result".to_string(),
+            variant: "Err".to_string(),
+            values: vec![error],
+        }
+    }
+
+    // The value of one of an error's fields, if this is an error value
+    pub fn error_field(&self, field: &str) -> Option<&Value> {
+        match self {
+            Value::Struct { name, fields } if name == "Error" => {
+                fields.iter().find(|(f, _)| f == field).map(|(_, value)| value)
+            }
+            _ => None,
+        }
+    }
+
+    // Renders the value for an interpolated string hole such as "{price:>8.2}"
+    pub fn format_with(&self, spec: &FormatSpec) -> String {
+        let text = match (self, spec.precision) {
+            (Value::Number(n), Some(precision)) => format!("{:.*}", precision, n),
+            (_, Some(precision)) => self.to_string().chars().take(precision).collect(),
+            (_, None) => self.to_string(),
+        };
+
+        let width = match spec.width {
+            Some(width) => width,
+            None => return text,
+        };
+        let len = text.chars().count();
+        if len >= width {
+            return text;
+        }
+
+        let padding = width - len;
+        let fill = spec.fill.unwrap_or(' ').to_string();
+        // numbers line up on the right by default, everything else on the left
+        let align = spec.align.unwrap_or(match self {
+            Value::Number(_) => Alignment::Right,
+            _ => Alignment::Left,
+        });
+
+        match align {
+            Alignment::Left => text + &fill.repeat(padding),
+            Alignment::Right => fill.repeat(padding) + &text,
+            Alignment::Center => {
+                let left = padding / 2;
+                fill.repeat(left) + &text + &fill.repeat(padding - left)
+            }
+        }
+    }
+}
+
+// Arithmetic fails with a TypeError (or ZeroDivisionError) rather than panicking,
+// so scripts can catch it
 impl Add for Value {
-    type Output = Value;
+    type Output = Result<Value, InterpreterError>;
 
-    fn add(self, other: Value) -> Value {
+    fn add(self, other: Value) -> Self::Output {
         match (self, other) {
-            (Value::Number(a), Value::Number(b)) => Value::Number(a + b),
-            (Value::String(a), Value::String(b)) => Value::String(a + &b),
-            (Value::String(a), Value::Number(b)) => Value::String(a + &b.to_string()),
-            (Value::Number(a), Value::String(b)) => Value::String(a.to_string() + &b),
-            (Value::String(a), Value::Bool(b)) => Value::String(a + &b.to_string()),
-            (Value::Bool(a), Value::String(b)) => Value::String(a.to_string() + &b),
-            (Value::String(a), Value::Nil) | (Value::Nil, Value::String(a)) => Value::String(a + "nil"),
-            _ => panic!("Cannot add in this instance!"),
+            (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a + b)),
+            (Value::String(a), Value::String(b)) => Ok(Value::String(a + &b)),
+            (Value::String(a), Value::Number(b)) => Ok(Value::String(a + &b.to_string())),
+            (Value::Number(a), Value::String(b)) => Ok(Value::String(a.to_string() + &b)),
+            (Value::String(a), Value::Bool(b)) => Ok(Value::String(a + &b.to_string())),
+            (Value::Bool(a), Value::String(b)) => Ok(Value::String(a.to_string() + &b)),
+            (Value::String(a), Value::Nil) | (Value::Nil, Value::String(a)) => Ok(Value::String(a + "nil")),
+            (a, b) => Err(InterpreterError::runtime(
+                "TypeError",
+                format!("Cannot add {} and {}", a.type_name(), b.type_name()),
+            )),
         }
     }
 }
 
 
 impl Sub for Value {
-    type Output = Value;
+    type Output = Result<Value, InterpreterError>;
 
-    fn sub(self, other: Value) -> Value {
+    fn sub(self, other: Value) -> Self::Output {
         match (self, other) {
-            (Value::Number(a), Value::Number(b)) => Value::Number(a - b),
-            _ => panic!("Cannot subtract non-numeric values"),
+            (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a - b)),
+            _ => Err(InterpreterError::runtime("TypeError", "Cannot subtract non-numeric values".to_string())),
         }
     }
 }
 
 impl Mul for Value {
-    type Output = Value;
+    type Output = Result<Value, InterpreterError>;
 
-    fn mul(self, other: Value) -> Value {
+    fn mul(self, other: Value) -> Self::Output {
         match (self, other) {
-            (Value::Number(a), Value::Number(b)) => Value::Number(a * b),
+            (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a * b)),
             (Value::String(s), Value::Number(n)) | (Value::Number(n), Value::String(s)) => {
                 if n < 0.0 {
-                    panic!("Cannot multiply string by negative number");
+                    return Err(InterpreterError::runtime(
+                        "TypeError",
+                        "Cannot multiply string by negative number".to_string(),
+                    ));
+                }
+                let count = n as usize;
+                // repeat() panics when the length overflows
+                match s.len().checked_mul(count) {
+                    Some(len) if len <= isize::MAX as usize => Ok(Value::String(s.repeat(count))),
+                    _ => Err(InterpreterError::runtime("RuntimeError", "Repeated string is too long".to_string())),
                 }
-                Value::String(s.repeat(n as usize))
             }
-            _ => panic!("Cannot multiply two strings"),
+            (a, b) => Err(InterpreterError::runtime(
+                "TypeError",
+                format!("Cannot multiply {} and {}", a.type_name(), b.type_name()),
+            )),
         }
     }
 }
 
 impl Div for Value {
-    type Output = Value;
+    type Output = Result<Value, InterpreterError>;
 
-    fn div(self, other: Value) -> Value {
+    fn div(self, other: Value) -> Self::Output {
         match (self, other) {
-            (Value::Number(a), Value::Number(b)) => {
-                if b == 0.0 {
-                    panic!("Division by zero");
-                }
-                Value::Number(a / b)
+            (Value::Number(_), Value::Number(0.0)) => {
+                Err(InterpreterError::runtime("ZeroDivisionError", "Division by zero".to_string()))
             }
-            _ => panic!("Cannot divide non-numeric values"),
+            (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a / b)),
+            _ => Err(InterpreterError::runtime("TypeError", "Cannot divide non-numeric values".to_string())),
         }
     }
 }
@@ -111,7 +287,14 @@ impl PartialEq for Value {
             (Value::Number(a), Value::Number(b)) => a == b,
             (Value::String(a), Value::String(b)) => a == b,
             (Value::Array(a), Value::Array(b)) => a == b,
+            (Value::Dictionary(a), Value::Dictionary(b)) => a == b,
             (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Struct { name: a, fields: fa }, Value::Struct { name: b, fields: fb }) => a == b && fa == fb,
+            (
+                Value::Enum { name: a, variant: va, values: xa },
+                Value::Enum { name: b, variant: vb, values: xb },
+            ) => a == b && va == vb && xa == xb,
+            (Value::Native { type_name: a }, Value::Native { type_name: b }) => a == b,
             _ => false,
         }
     }
@@ -0,0 +1,483 @@
+use std::rc::Rc;
+use crate::builtins;
+use crate::capabilities::Capabilities;
+use crate::compiler::{Function, Op, Program};
+use crate::ast::AstNode;
+use crate::interpreter::{arity_error, index_value, Interpreter, InterpreterError, Source, DEFAULT_MAX_CALL_DEPTH};
+use crate::resolver::ResolveOutput;
+use crate::limits::{Budget, Limits};
+use crate::output::{Output, Sink};
+use crate::token::Token;
+use crate::value::Value;
+
+struct Frame {
+    function: Rc<Function>,
+    ip: usize,
+    // stack index of the frame's first local; the called function sits just below it
+    base: usize,
+}
+
+// Stack-based virtual machine running bytecode from the `Compiler`
+pub struct Vm {
+    stack: Vec<Value>,
+    frames: Vec<Frame>,
+    // None until the global is first assigned
+    globals: Vec<Option<Value>>,
+    global_names: Vec<String>,
+    functions: Vec<Rc<Function>>,
+    optimize: bool,
+    max_call_depth: usize,
+    budget: Budget,
+    // native functions take the tree-walking interpreter, and the VM shares its operator semantics
+    interpreter: Interpreter,
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// arr[i] = value or dict[key] = value, matching the tree-walker's indexed assignment
+fn set_index(container: &mut Value, index: Value, value: Value) -> Result<(), InterpreterError> {
+    match (container, index) {
+        (Value::Array(arr), Value::Number(idx)) => {
+            let len = arr.len();
+            let slot = arr.get_mut(idx as usize).filter(|_| idx >= 0.0).ok_or_else(|| {
+                InterpreterError::runtime("IndexError", format!("Array index {} out of bounds for length {}", idx, len))
+            })?;
+            *slot = value;
+            Ok(())
+        }
+        (Value::Dictionary(dict), Value::String(key)) => {
+            dict.insert(key, value);
+            Ok(())
+        }
+        _ => Err(InterpreterError::Error("Indexing error: expected array or dictionary with correct key type".to_string())),
+    }
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        Self {
+            stack: Vec::new(),
+            frames: Vec::new(),
+            globals: Vec::new(),
+            global_names: Vec::new(),
+            functions: Vec::new(),
+            optimize: false,
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            budget: Budget::default(),
+            interpreter: Interpreter::new(),
+        }
+    }
+
+    // A VM whose output is kept for take_output() instead of being printed
+    pub fn with_captured_output() -> Self {
+        Self { interpreter: Interpreter::with_captured_output(), ..Self::new() }
+    }
+
+    // Where print statements write
+    pub fn set_stdout(&mut self, sink: Sink) {
+        self.interpreter.set_stdout(sink);
+    }
+
+    // Where warnings are written
+    pub fn set_stderr(&mut self, sink: Sink) {
+        self.interpreter.set_stderr(sink);
+    }
+
+    pub(crate) fn stderr(&mut self) -> &mut Sink {
+        self.interpreter.stderr()
+    }
+
+    // Where the stdin builtins read from instead of the process's stdin
+    pub fn set_stdin(&mut self, source: Source) {
+        self.interpreter.set_stdin(source);
+    }
+
+    // The output captured since the last call, if the VM was made with_captured_output()
+    pub fn take_output(&mut self) -> Option<Output> {
+        self.interpreter.take_output()
+    }
+
+    // In strict mode assigning to a name that was never declared is an error, reported by `check`
+    pub fn set_strict(&mut self, strict: bool) {
+        self.interpreter.set_strict(strict);
+    }
+
+    // Runs the tree-walker's resolver over a program before it is compiled, so both engines report
+    // the same undeclared names, constant misuse and strict-mode errors, and the same warnings
+    pub fn check(&mut self, program: &AstNode) -> ResolveOutput {
+        self.interpreter.resolve(program.clone())
+    }
+
+    // Deeper calls raise a StackOverflowError
+    pub fn set_max_call_depth(&mut self, depth: usize) {
+        self.max_call_depth = depth;
+    }
+
+    // Limits applied to each program run from now on; a step is one instruction
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.budget = Budget::new(limits);
+    }
+
+    // What scripts may access outside the VM; builtins check these
+    pub fn set_capabilities(&mut self, capabilities: Capabilities) {
+        self.interpreter.set_capabilities(capabilities);
+    }
+
+    // Runs the optimizer over programs before they are compiled
+    pub fn set_optimize(&mut self, optimize: bool) {
+        self.optimize = optimize;
+    }
+
+    pub(crate) fn optimizes(&self) -> bool {
+        self.optimize
+    }
+
+    pub fn run(&mut self, program: Program) -> Result<Value, InterpreterError> {
+        let natives = builtins::all();
+        self.globals = program
+            .globals
+            .iter()
+            .map(|name| {
+                natives
+                    .iter()
+                    .find(|(native, _)| native == name)
+                    .map(|(native, func)| Value::NativeFunction { name: native.to_string(), func: *func })
+            })
+            .collect();
+        self.global_names = program.globals;
+        self.functions = program.functions;
+        self.stack.clear();
+        self.frames = vec![Frame { function: program.main, ip: 0, base: 0 }];
+        self.budget.start();
+
+        let result = self.execute();
+        if result.is_err() {
+            self.frames.clear();
+            self.stack.clear();
+        }
+        result
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("compiled code keeps the stack balanced")
+    }
+
+    fn constant(&self, index: usize) -> &Value {
+        &self.frames.last().expect("a frame is running").function.chunk.constants[index]
+    }
+
+    fn global(&self, slot: usize) -> Result<&Value, InterpreterError> {
+        self.globals[slot].as_ref().ok_or_else(|| {
+            InterpreterError::runtime("NameError", format!("Undefined variable: {}", self.global_names[slot]))
+        })
+    }
+
+    fn global_mut(&mut self, slot: usize) -> Result<&mut Value, InterpreterError> {
+        match self.globals[slot] {
+            Some(ref mut value) => Ok(value),
+            None => Err(InterpreterError::runtime(
+                "NameError",
+                format!("Undefined variable: {}", self.global_names[slot]),
+            )),
+        }
+    }
+
+    fn binary(&mut self, op: Op) -> Result<(), InterpreterError> {
+        let right = self.pop();
+        let left = self.pop();
+        // numbers take a fast path; everything else goes through the interpreter's rules
+        let result = match (op, &left, &right) {
+            (Op::Add, Value::Number(a), Value::Number(b)) => Value::Number(a + b),
+            (Op::Subtract, Value::Number(a), Value::Number(b)) => Value::Number(a - b),
+            (Op::Multiply, Value::Number(a), Value::Number(b)) => Value::Number(a * b),
+            (Op::Less, Value::Number(a), Value::Number(b)) => Value::Bool(a < b),
+            (Op::Greater, Value::Number(a), Value::Number(b)) => Value::Bool(a > b),
+            (Op::Equal, _, _) => Value::Bool(left == right),
+            _ => {
+                let operator = match op {
+                    Op::Add => Token::Plus,
+                    Op::Subtract => Token::Minus,
+                    Op::Multiply => Token::Multiply,
+                    Op::Divide => Token::Divide,
+                    Op::Modulo => Token::Modulo,
+                    Op::Less => Token::LessThan,
+                    Op::Greater => Token::GreaterThan,
+                    Op::And => Token::And,
+                    _ => Token::Or,
+                };
+                self.budget.check_binary(&operator, &left, &right)?;
+                Interpreter::apply_binary(&operator, left, right)?
+            }
+        };
+        self.budget.check_size(&result)?;
+        self.stack.push(result);
+        Ok(())
+    }
+
+    fn call(&mut self, argc: usize) -> Result<(), InterpreterError> {
+        let callee_at = self.stack.len() - argc - 1;
+        match &self.stack[callee_at] {
+            Value::CompiledFunction { index, .. } => {
+                let function = Rc::clone(&self.functions[*index]);
+                if argc != function.arity {
+                    return Err(arity_error(&function.name, function.arity, argc));
+                }
+                // the first frame is the top level
+                if self.frames.len() > self.max_call_depth {
+                    return Err(InterpreterError::runtime(
+                        "StackOverflowError",
+                        format!("Stack overflow: maximum call depth of {} exceeded", self.max_call_depth),
+                    ));
+                }
+                let base = callee_at + 1;
+                self.stack.resize(base + function.locals, Value::Nil);
+                self.frames.push(Frame { function, ip: 0, base });
+                Ok(())
+            }
+            Value::NativeFunction { func, .. } => {
+                let func = *func;
+                let args = self.stack.split_off(callee_at + 1);
+                self.stack.pop();
+                let result = func(&mut self.interpreter, args)?;
+                self.budget.check_size(&result)?;
+                self.stack.push(result);
+                Ok(())
+            }
+            other => Err(InterpreterError::runtime("TypeError", format!("{} is not a function", other.type_name()))),
+        }
+    }
+
+    // Built-in methods of core values, and the native methods of objects such as stdin_lines()
+    fn invoke(interpreter: &mut Interpreter, receiver: &mut Value, method: &str, args: Vec<Value>) -> Result<Value, InterpreterError> {
+        if let Value::Native { type_name } = receiver {
+            return match builtins::type_method(type_name, method) {
+                Some(func) => {
+                    let mut full_args = vec![receiver.clone()];
+                    full_args.extend(args);
+                    func(interpreter, full_args)
+                }
+                None => Err(InterpreterError::Error(format!("{} has no method '{}'", type_name, method))),
+            };
+        }
+        match builtins::call_method(receiver, method, args) {
+            Some(result) => result,
+            None => Err(InterpreterError::Error(format!("{} has no method '{}'", receiver.type_name(), method))),
+        }
+    }
+
+    fn method_name(&self, index: usize) -> String {
+        match self.constant(index) {
+            Value::String(name) => name.clone(),
+            other => other.to_string(),
+        }
+    }
+
+    // Pops the running frame, leaving its result in place of the called function.
+    // Returns the result when the top level itself returns
+    fn return_from_frame(&mut self) -> Option<Value> {
+        let result = self.pop();
+        let frame = self.frames.pop().expect("a frame is running");
+        if self.frames.is_empty() {
+            return Some(result);
+        }
+        // drop the frame's locals and the called function
+        self.stack.truncate(frame.base - 1);
+        self.stack.push(result);
+        None
+    }
+
+    fn execute(&mut self) -> Result<Value, InterpreterError> {
+        loop {
+            self.budget.step()?;
+            let (op, base) = {
+                let frame = self.frames.last_mut().expect("a frame is running");
+                let op = frame.function.chunk.code[frame.ip];
+                frame.ip += 1;
+                (op, frame.base)
+            };
+
+            match op {
+                Op::Constant(index) => {
+                    let value = self.constant(index).clone();
+                    self.stack.push(value);
+                }
+                Op::Nil => self.stack.push(Value::Nil),
+                Op::Pop => {
+                    self.stack.pop();
+                }
+                Op::PopN(n) => {
+                    let len = self.stack.len();
+                    self.stack.truncate(len - n);
+                }
+                Op::Dup => {
+                    let value = self.stack.last().expect("value to duplicate").clone();
+                    self.stack.push(value);
+                }
+                Op::GetLocal(slot) => {
+                    let value = self.stack[base + slot].clone();
+                    self.stack.push(value);
+                }
+                Op::SetLocal(slot) => {
+                    self.stack[base + slot] = self.stack.last().expect("value to store").clone();
+                }
+                Op::GetGlobal(slot) => {
+                    let value = self.global(slot)?.clone();
+                    self.stack.push(value);
+                }
+                Op::DefineGlobal(slot) => {
+                    self.globals[slot] = self.stack.last().cloned();
+                }
+                Op::SetGlobal(slot) => {
+                    self.globals[slot] = self.stack.last().cloned();
+                }
+                Op::IndexLocal(slot) => {
+                    let index = self.pop();
+                    let value = index_value(&self.stack[base + slot], &index)?;
+                    self.stack.push(value);
+                }
+                Op::IndexGlobal(slot) => {
+                    let index = self.pop();
+                    let value = index_value(self.global(slot)?, &index)?;
+                    self.stack.push(value);
+                }
+                Op::SetIndexLocal(slot) => {
+                    let value = self.pop();
+                    let index = self.pop();
+                    set_index(&mut self.stack[base + slot], index, value.clone())?;
+                    self.budget.check_size(&self.stack[base + slot])?;
+                    self.stack.push(value);
+                }
+                Op::SetIndexGlobal(slot) => {
+                    let value = self.pop();
+                    let index = self.pop();
+                    set_index(self.global_mut(slot)?, index, value.clone())?;
+                    self.budget.check_size(self.global(slot)?)?;
+                    self.stack.push(value);
+                }
+                Op::Index => {
+                    let index = self.pop();
+                    let container = self.pop();
+                    self.stack.push(index_value(&container, &index)?);
+                }
+                Op::Add
+                | Op::Subtract
+                | Op::Multiply
+                | Op::Divide
+                | Op::Modulo
+                | Op::Equal
+                | Op::Less
+                | Op::Greater
+                | Op::And
+                | Op::Or => self.binary(op)?,
+                Op::Negate => match self.pop() {
+                    Value::Number(n) => self.stack.push(Value::Number(-n)),
+                    value => {
+                        return Err(InterpreterError::runtime(
+                            "TypeError",
+                            format!("Cannot apply {:?} to {}", Token::Minus, value),
+                        ))
+                    }
+                },
+                Op::Array(n) => {
+                    let array = Value::Array(self.stack.split_off(self.stack.len() - n));
+                    self.budget.check_size(&array)?;
+                    self.stack.push(array);
+                }
+                Op::Dictionary(n) => {
+                    let entries = self.stack.split_off(self.stack.len() - 2 * n);
+                    let mut dict = std::collections::HashMap::new();
+                    let mut entries = entries.into_iter();
+                    while let (Some(key), Some(value)) = (entries.next(), entries.next()) {
+                        dict.insert(key.to_string(), value);
+                    }
+                    let dict = Value::Dictionary(dict);
+                    self.budget.check_size(&dict)?;
+                    self.stack.push(dict);
+                }
+                Op::Format(index) => {
+                    let value = self.pop();
+                    let frame = self.frames.last().expect("a frame is running");
+                    let text = Value::String(value.format_with(&frame.function.chunk.formats[index]));
+                    self.budget.check_size(&text)?;
+                    self.stack.push(text);
+                }
+                Op::Concat(n) => {
+                    let parts = self.stack.split_off(self.stack.len() - n);
+                    let text = Value::String(parts.iter().map(|part| part.to_string()).collect());
+                    self.budget.check_size(&text)?;
+                    self.stack.push(text);
+                }
+                Op::Print => {
+                    let value = self.pop();
+                    if value != Value::Nil {
+                        let text = value.to_string();
+                        self.budget.write_output(text.len() + 1)?;
+                        self.interpreter.write_line(&text)?;
+                    }
+                    self.stack.push(Value::Number(0.0));
+                }
+                Op::Jump(target) => {
+                    self.frames.last_mut().expect("a frame is running").ip = target;
+                }
+                Op::JumpIfFalse(target) => {
+                    let condition = self.pop();
+                    if !self.interpreter.is_truthy(&condition)? {
+                        self.frames.last_mut().expect("a frame is running").ip = target;
+                    }
+                }
+                Op::Call(argc) => self.call(argc)?,
+                Op::Invoke { method, argc } => {
+                    let method = self.method_name(method);
+                    let args = self.stack.split_off(self.stack.len() - argc);
+                    let mut receiver = self.pop();
+                    let result = Self::invoke(&mut self.interpreter, &mut receiver, &method, args)?;
+                    self.budget.check_size(&result)?;
+                    self.stack.push(result);
+                }
+                Op::InvokeLocal { slot, method, argc } => {
+                    let method = self.method_name(method);
+                    let args = self.stack.split_off(self.stack.len() - argc);
+                    let result = Self::invoke(&mut self.interpreter, &mut self.stack[base + slot], &method, args)?;
+                    self.budget.check_size(&result)?;
+                    self.budget.check_size(&self.stack[base + slot])?;
+                    self.stack.push(result);
+                }
+                Op::InvokeGlobal { slot, method, argc } => {
+                    let method = self.method_name(method);
+                    let args = self.stack.split_off(self.stack.len() - argc);
+                    self.global(slot)?;
+                    let receiver = self.globals[slot].as_mut().expect("the global was checked to be defined");
+                    let result = Self::invoke(&mut self.interpreter, receiver, &method, args)?;
+                    self.budget.check_size(&result)?;
+                    self.budget.check_size(self.global(slot)?)?;
+                    self.stack.push(result);
+                }
+                Op::Return => {
+                    if let Some(result) = self.return_from_frame() {
+                        return Ok(result);
+                    }
+                }
+                Op::TailCall(argc) => {
+                    let callee_at = self.stack.len() - argc - 1;
+                    if let Value::CompiledFunction { .. } = self.stack[callee_at] {
+                        // move the callee and its arguments over the returning frame, then call it
+                        let call = self.stack.split_off(callee_at);
+                        self.stack.truncate(base - 1);
+                        self.stack.extend(call);
+                        self.frames.pop();
+                        self.call(argc)?;
+                    } else {
+                        self.call(argc)?;
+                        if let Some(result) = self.return_from_frame() {
+                            return Ok(result);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,169 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+use alethia::Capabilities;
+use common::{run, TempDir};
+
+// A directory holding `allowed/` with a file and a subdirectory, and `outside/` with a secret,
+// plus symbolic links from `allowed/` to each
+fn layout(name: &str) -> TempDir {
+    let dir = TempDir::new(name);
+    fs::create_dir_all(dir.0.join("allowed/sub")).unwrap();
+    fs::create_dir_all(dir.0.join("outside")).unwrap();
+    fs::write(dir.0.join("allowed/notes.txt"), "notes").unwrap();
+    fs::write(dir.0.join("outside/secret.txt"), "secret").unwrap();
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink(dir.0.join("outside"), dir.0.join("allowed/escape")).unwrap();
+        std::os::unix::fs::symlink(dir.0.join("allowed/sub"), dir.0.join("allowed/inner")).unwrap();
+    }
+    dir
+}
+
+fn readable(dir: &Path) -> Capabilities {
+    Capabilities { read: vec![dir.to_path_buf()], ..Capabilities::default() }
+}
+
+fn writable(dir: &Path) -> Capabilities {
+    Capabilities { write: vec![dir.to_path_buf()], ..Capabilities::default() }
+}
+
+#[test]
+fn paths_inside_an_allowed_directory_resolve_to_their_absolute_form() {
+    let dir = layout("inside");
+    let capabilities = readable(&dir.0.join("allowed"));
+    let notes = dir.0.join("allowed/notes.txt");
+    assert_eq!(capabilities.check_read(&notes).ok(), Some(notes.clone()));
+    assert_eq!(capabilities.check_read(&dir.0.join("allowed/sub/../notes.txt")).ok(), Some(notes.clone()));
+    assert_eq!(capabilities.check_read(&dir.0.join("allowed/./sub")).ok(), Some(dir.0.join("allowed/sub")));
+}
+
+#[test]
+fn dot_dot_cannot_leave_an_allowed_directory() {
+    let dir = layout("dotdot");
+    let capabilities = readable(&dir.0.join("allowed"));
+    let escape = dir.0.join("allowed/../outside/secret.txt");
+    assert_eq!(
+        capabilities.check_read(&escape).unwrap_err().to_string(),
+        format!("PermissionError: Permission denied: reading '{}' needs the fs-read capability", escape.display())
+    );
+    assert!(capabilities.check_read(&dir.0.join("allowed/sub/../../outside")).is_err());
+    // the allowed directory's own parent is outside it
+    assert!(capabilities.check_read(&dir.0.join("allowed/..")).is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn symbolic_links_are_followed_before_checking() {
+    let dir = layout("symlinks");
+    let capabilities = readable(&dir.0.join("allowed"));
+    assert!(capabilities.check_read(&dir.0.join("allowed/escape/secret.txt")).is_err());
+    assert!(capabilities.check_read(&dir.0.join("allowed/escape")).is_err());
+    assert_eq!(capabilities.check_read(&dir.0.join("allowed/inner")).ok(), Some(dir.0.join("allowed/sub")));
+    // a link to the allowed directory grants what is under its target
+    let through_link = readable(&dir.0.join("allowed/escape"));
+    assert!(through_link.check_read(&dir.0.join("outside/secret.txt")).is_ok());
+}
+
+#[test]
+fn files_that_do_not_exist_yet_resolve_under_their_existing_parent() {
+    let dir = layout("missing");
+    let capabilities = writable(&dir.0.join("allowed"));
+    let new_file = dir.0.join("allowed/new/deeper/file.txt");
+    assert_eq!(capabilities.check_write(&new_file).ok(), Some(new_file));
+    assert!(capabilities.check_write(&dir.0.join("outside/new.txt")).is_err());
+    // `..` after a part that doesn't exist can't be resolved, so it is refused
+    assert!(capabilities.check_write(&dir.0.join("allowed/new/../notes.txt")).is_err());
+    assert!(capabilities.check_write(&dir.0.join("allowed/new/../../outside/x.txt")).is_err());
+    // reading and writing are granted separately
+    assert!(capabilities.check_read(&dir.0.join("allowed/notes.txt")).is_err());
+}
+
+#[test]
+fn nothing_is_allowed_by_default_and_everything_with_all() {
+    let dir = layout("default");
+    let notes = dir.0.join("allowed/notes.txt");
+    assert!(Capabilities::default().check_read(&notes).is_err());
+    assert!(Capabilities::default().check_write(&notes).is_err());
+    // a directory that doesn't exist yet still confines paths to it
+    let later = writable(&dir.0.join("later"));
+    assert_eq!(later.check_write(&dir.0.join("later/file.txt")).ok(), Some(dir.0.join("later/file.txt")));
+    assert!(later.check_write(&dir.0.join("later/../outside/file.txt")).is_err());
+    assert_eq!(Capabilities::all().check_write(&notes).ok(), Some(notes));
+}
+
+// Runs a script that prints the file at `path`, with the given permission flags
+fn read_with_flags(path: &Path, flags: &[&str]) -> common::Run {
+    run(&format!("print read_file(\"{}\");", path.display()), flags)
+}
+
+#[test]
+fn allow_flags_take_comma_separated_directories() {
+    let dir = layout("flags");
+    let notes = dir.0.join("allowed/notes.txt");
+    let secret = dir.0.join("outside/secret.txt");
+    let both = format!("--allow-read={},,{}", dir.path("allowed"), dir.path("outside"));
+    assert_eq!(read_with_flags(&notes, &[&both]).stdout, "notes\n");
+    assert_eq!(read_with_flags(&secret, &[&both]).stdout, "secret\n");
+    let one = format!("--allow-read={}", dir.path("allowed"));
+    assert_eq!(read_with_flags(&notes, &[&one]).stdout, "notes\n");
+    assert_eq!(
+        read_with_flags(&secret, &[&one]).stderr.lines().last(),
+        Some(format!("Error: PermissionError: Permission denied: reading '{}' needs the fs-read capability", secret.display()).as_str())
+    );
+    // writing doesn't grant reading
+    let write = format!("--allow-write={}", dir.path("allowed"));
+    assert!(!read_with_flags(&notes, &[&write]).success);
+    assert_eq!(read_with_flags(&secret, &["--allow-all"]).stdout, "secret\n");
+}
+
+#[test]
+fn malformed_and_unknown_allow_flags_are_rejected() {
+    let dir = layout("bad-flags");
+    let notes = dir.0.join("allowed/notes.txt");
+    let missing = read_with_flags(&notes, &["--allow-read"]);
+    assert_eq!(missing.stderr.lines().next(), Some("Error: --allow-read expects directories, e.g. --allow-read=./data"));
+    let unknown = read_with_flags(&notes, &["--allow-everything"]);
+    assert_eq!(unknown.stderr.lines().next(), Some("Error: Unknown option: --allow-everything"));
+    assert_eq!(read_with_flags(&notes, &["--allow-all=yes"]).stderr.lines().next(), Some("Error: Unknown option: --allow-all=yes"));
+}
+
+// Each flag, a script using the builtin it grants, what the script prints with the flag and
+// the error it stops with without it
+const BUILTIN_FLAGS: [(&str, &str, &str, &str); 4] = [
+    ("--allow-env", "print env(\"ALETHIA_UNSET_VARIABLE\") == nil;", "true\n", "env() needs the env capability"),
+    ("--allow-process", "print run_process(\"echo\", [\"hi\"])[\"stdout\"];", "hi\n\n", "run_process() needs the process capability"),
+    ("--allow-clock", "print now() > 0;", "true\n", "now() needs the clock capability"),
+    ("--allow-random", "let r = random();\nprint [r < 0, r < 1];", "[false, true]\n", "random() needs the random capability"),
+];
+
+#[test]
+fn each_allow_flag_grants_its_builtin() {
+    for (flag, source, granted, denied) in BUILTIN_FLAGS {
+        let allowed = run(source, &[flag]);
+        assert_eq!((allowed.stdout.as_str(), allowed.stderr.as_str()), (granted, ""), "{}", flag);
+        let refused = run(source, &[]);
+        let error = format!("Error: PermissionError: Permission denied: {}", denied);
+        assert_eq!(refused.stderr.lines().last(), Some(error.as_str()), "{}", flag);
+        // one permission doesn't grant another
+        let other = if flag == "--allow-env" { "--allow-clock" } else { "--allow-env" };
+        assert!(!run(source, &[other]).success, "{}", flag);
+        assert_eq!(run(source, &["--allow-all"]).stdout, granted, "{}", flag);
+    }
+}
+
+#[test]
+fn the_repl_takes_the_same_flags() {
+    let dir = layout("repl");
+    let input = format!("print read_file(\"{}\");\n", dir.path("allowed/notes.txt"));
+    let allow = format!("--allow-read={}", dir.path("allowed"));
+    assert!(common::repl(&[&allow], &input).stdout.contains("notes\n"));
+    assert!(common::repl(&[], &input).stderr.contains("PermissionError"));
+    for (flag, source, granted, denied) in BUILTIN_FLAGS {
+        let input = format!("{}\n", source.replace('\n', " "));
+        let allowed = common::repl(&[flag], &input);
+        assert!(allowed.stdout.contains(granted) && allowed.stderr.is_empty(), "{} {:?}", flag, allowed);
+        assert!(common::repl(&[], &input).stderr.contains(denied), "{}", flag);
+    }
+}
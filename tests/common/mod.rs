@@ -0,0 +1,92 @@
+// Helpers shared by the integration tests. Each test binary uses only some of them
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::PathBuf;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use alethia::{run_source_with, Interpreter};
+
+// What a script run through the `alethia` binary printed, and whether it succeeded
+#[derive(Debug, PartialEq)]
+pub struct Run {
+    pub stdout: String,
+    pub stderr: String,
+    pub success: bool,
+}
+
+// A script file of its own for every run, since tests run in parallel
+fn script_path() -> PathBuf {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    let id = NEXT.fetch_add(1, Ordering::Relaxed);
+    std::env::temp_dir().join(format!("alethia-test-{}-{}.at", std::process::id(), id))
+}
+
+// Runs `source` with `alethia run <flags> <file>`
+pub fn run(source: &str, flags: &[&str]) -> Run {
+    run_with_input(source, flags, "")
+}
+
+// Runs `source` like `run`, with `input` piped to its stdin
+pub fn run_with_input(source: &str, flags: &[&str], input: &str) -> Run {
+    let path = script_path();
+    fs::write(&path, source).unwrap();
+    let path_arg = path.to_string_lossy();
+    let args: Vec<&str> = std::iter::once("run").chain(flags.iter().copied()).chain([path_arg.as_ref()]).collect();
+    let run = alethia(&args, input);
+    fs::remove_file(&path).unwrap();
+    run
+}
+
+// Runs `alethia repl <flags>`, typing the lines of `input`
+pub fn repl(flags: &[&str], input: &str) -> Run {
+    let args: Vec<&str> = std::iter::once("repl").chain(flags.iter().copied()).collect();
+    alethia(&args, input)
+}
+
+fn alethia(args: &[&str], input: &str) -> Run {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_alethia"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    Run {
+        stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        success: output.status.success(),
+    }
+}
+
+// Runs `source` on the tree-walking interpreter, returning what it printed or its error
+pub fn interpret(source: &str) -> Result<String, String> {
+    let mut interpreter = Interpreter::with_captured_output();
+    run_source_with(source.to_string(), &mut interpreter)?;
+    Ok(interpreter.take_output().unwrap().stdout)
+}
+
+// A fresh directory for one test, removed when the test ends
+pub struct TempDir(pub PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("alethia-dir-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path.canonicalize().unwrap())
+    }
+
+    pub fn path(&self, name: &str) -> String {
+        self.0.join(name).to_string_lossy().into_owned()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
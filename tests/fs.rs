@@ -0,0 +1,174 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+use alethia::{run_source_with, Capabilities, Interpreter};
+use common::TempDir;
+
+// Runs a script whose `dir` variable holds the directory's path, returning what it printed
+fn run(source: &str, dir: &TempDir, capabilities: Capabilities) -> Result<String, String> {
+    let mut interpreter = Interpreter::with_captured_output();
+    interpreter.set_capabilities(capabilities);
+    let source = format!("let dir = \"{}\";\n{}", dir.0.to_string_lossy(), source);
+    let result = run_source_with(source, &mut interpreter);
+    let stdout = interpreter.take_output().unwrap().stdout;
+    result.map(|_| stdout)
+}
+
+fn read_write(dir: &Path) -> Capabilities {
+    Capabilities { read: vec![dir.to_path_buf()], write: vec![dir.to_path_buf()], ..Capabilities::default() }
+}
+
+#[test]
+fn files_are_written_appended_and_read() {
+    let dir = TempDir::new("write");
+    let source = "let path = path_join(dir, \"notes.txt\");
+    print file_exists(path);
+    write_file(path, \"first\");
+    append_file(path, \", second\");
+    append_file(path, 3);
+    print read_file(path);
+    print file_exists(path);";
+    assert_eq!(run(source, &dir, read_write(&dir.0)), Ok("false\nfirst, second3\ntrue\n".to_string()));
+    assert_eq!(fs::read_to_string(dir.path("notes.txt")).unwrap(), "first, second3");
+}
+
+#[test]
+fn read_lines_drops_line_endings() {
+    let dir = TempDir::new("lines");
+    fs::write(dir.path("lines.txt"), "one\ntwo\r\n\nthree\n").unwrap();
+    let source = "let lines = read_lines(path_join(dir, \"lines.txt\"));
+    print lines.len();
+    print lines;";
+    assert_eq!(run(source, &dir, read_write(&dir.0)), Ok("4\n[one, two, , three]\n".to_string()));
+}
+
+#[test]
+fn directories_are_created_listed_and_files_removed() {
+    let dir = TempDir::new("dirs");
+    let source = "make_dir(path_join(dir, \"a\", \"b\"));
+    write_file(path_join(dir, \"z.txt\"), \"\");
+    write_file(path_join(dir, \"a\", \"b\", \"c.txt\"), \"\");
+    print list_dir(dir);
+    print list_dir(path_join(dir, \"a\", \"b\"));
+    remove_file(path_join(dir, \"z.txt\"));
+    print list_dir(dir);";
+    assert_eq!(run(source, &dir, read_write(&dir.0)), Ok("[a, z.txt]\n[c.txt]\n[a]\n".to_string()));
+}
+
+#[test]
+fn missing_files_raise_file_not_found_errors() {
+    let dir = TempDir::new("missing");
+    let source = "try { read_file(path_join(dir, \"nope.txt\")); } catch e { print e.kind; }
+    try { remove_file(path_join(dir, \"nope.txt\")); } catch e { print e.kind; }
+    try { list_dir(path_join(dir, \"nope\")); } catch e { print e.kind; }";
+    assert_eq!(
+        run(source, &dir, read_write(&dir.0)),
+        Ok("FileNotFoundError\nFileNotFoundError\nFileNotFoundError\n".to_string())
+    );
+}
+
+#[test]
+fn try_read_file_returns_errors_as_results() {
+    let dir = TempDir::new("try-read");
+    fs::write(dir.path("present.txt"), "contents").unwrap();
+    let source = "func shout(name) {
+        let text = try_read_file(path_join(dir, name))?;
+        return ok(text.upper());
+    }
+    print shout(\"present.txt\").unwrap();
+    match shout(\"missing.txt\") {
+        Result.Ok(line) => print line,
+        Result.Err(e) => print e.kind,
+    }";
+    assert_eq!(run(source, &dir, read_write(&dir.0)), Ok("CONTENTS\nFileNotFoundError\n".to_string()));
+    // without permission the PermissionError is returned too
+    let denied = "print try_read_file(path_join(dir, \"present.txt\")).unwrap_or(\"denied\");";
+    assert_eq!(run(denied, &dir, Capabilities::default()), Ok("denied\n".to_string()));
+}
+
+#[test]
+fn uncaught_file_errors_name_the_file() {
+    let dir = TempDir::new("uncaught");
+    let result = run("read_lines(path_join(dir, \"nope.txt\"));", &dir, read_write(&dir.0));
+    let message = format!("FileNotFoundError: Cannot read '{}'", dir.path("nope.txt"));
+    assert!(result.as_ref().is_err_and(|e| e.starts_with(&message)), "{:?}", result);
+}
+
+#[test]
+fn file_access_is_denied_by_default() {
+    let dir = TempDir::new("denied");
+    fs::write(dir.path("data.txt"), "secret").unwrap();
+    let source = "try { read_file(path_join(dir, \"data.txt\")); } catch e { print e.kind; print e.message; }
+    try { write_file(path_join(dir, \"out.txt\"), \"x\"); } catch e { print e.message; }
+    try { file_exists(dir); } catch e { print e.kind; }";
+    let expected = format!(
+        "PermissionError\nPermission denied: reading '{}' needs the fs-read capability\n\
+         Permission denied: writing '{}' needs the fs-write capability\nPermissionError\n",
+        dir.path("data.txt"),
+        dir.path("out.txt")
+    );
+    assert_eq!(run(source, &dir, Capabilities::default()), Ok(expected));
+    assert!(!Path::new(&dir.path("out.txt")).exists());
+}
+
+#[test]
+fn access_is_limited_to_the_allowed_directories() {
+    let dir = TempDir::new("scoped");
+    fs::create_dir_all(dir.0.join("data")).unwrap();
+    fs::write(dir.path("data/in.txt"), "inside").unwrap();
+    fs::write(dir.path("outside.txt"), "outside").unwrap();
+    let capabilities = Capabilities { read: vec![dir.0.join("data")], ..Capabilities::default() };
+    let source = "print read_file(path_join(dir, \"data\", \"in.txt\"));
+    try { read_file(path_join(dir, \"outside.txt\")); } catch e { print e.kind; }
+    try { read_file(path_join(dir, \"data\", \"..\", \"outside.txt\")); } catch e { print e.kind; }
+    try { write_file(path_join(dir, \"data\", \"new.txt\"), \"x\"); } catch e { print e.kind; }";
+    assert_eq!(
+        run(source, &dir, capabilities),
+        Ok("inside\nPermissionError\nPermissionError\nPermissionError\n".to_string())
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn symbolic_links_cannot_leave_the_allowed_directories() {
+    let dir = TempDir::new("symlink");
+    fs::create_dir_all(dir.0.join("data")).unwrap();
+    fs::write(dir.path("secret.txt"), "secret").unwrap();
+    std::os::unix::fs::symlink(dir.0.join("secret.txt"), dir.0.join("data/link.txt")).unwrap();
+    let capabilities = Capabilities { read: vec![dir.0.join("data")], ..Capabilities::default() };
+    let source = "try { print read_file(path_join(dir, \"data\", \"link.txt\")); } catch e { print e.kind; }";
+    assert_eq!(run(source, &dir, capabilities), Ok("PermissionError\n".to_string()));
+}
+
+#[cfg(unix)]
+#[test]
+fn removing_a_symbolic_link_keeps_its_target() {
+    let dir = TempDir::new("remove-link");
+    fs::create_dir_all(dir.0.join("data")).unwrap();
+    fs::write(dir.path("data/target.txt"), "kept").unwrap();
+    fs::write(dir.path("outside.txt"), "kept").unwrap();
+    std::os::unix::fs::symlink(dir.0.join("data/target.txt"), dir.0.join("data/link.txt")).unwrap();
+    std::os::unix::fs::symlink(dir.0.join("outside.txt"), dir.0.join("data/escape.txt")).unwrap();
+    let capabilities = Capabilities { write: vec![dir.0.join("data")], ..Capabilities::default() };
+    let source = "remove_file(path_join(dir, \"data\", \"link.txt\"));\n\
+                  remove_file(path_join(dir, \"data\", \"escape.txt\"));";
+    assert_eq!(run(source, &dir, capabilities), Ok(String::new()));
+    assert!(fs::symlink_metadata(dir.path("data/link.txt")).is_err());
+    assert!(fs::symlink_metadata(dir.path("data/escape.txt")).is_err());
+    assert_eq!(fs::read_to_string(dir.path("data/target.txt")).unwrap(), "kept");
+    assert_eq!(fs::read_to_string(dir.path("outside.txt")).unwrap(), "kept");
+}
+
+#[test]
+fn path_helpers_split_and_join_paths() {
+    let dir = TempDir::new("paths");
+    let source = "print basename(\"data/2024/jan.csv\");
+    print extension(\"data/2024/jan.csv\");
+    print extension(\"README\") == \"\";
+    print basename(path_join(\"data\", \"jan.csv\"));";
+    assert_eq!(
+        run(source, &dir, Capabilities::default()),
+        Ok("jan.csv\ncsv\ntrue\njan.csv\n".to_string())
+    );
+}
@@ -0,0 +1,59 @@
+mod common;
+
+use common::interpret as run;
+
+#[test]
+fn holes_are_evaluated_in_place() {
+    let source = "let name = \"alice\";
+    print \"Hello, {name}! You have {2 * 3} items\";";
+    assert_eq!(run(source), Ok("Hello, alice! You have 6 items\n".to_string()));
+}
+
+#[test]
+fn doubled_braces_are_literal() {
+    assert_eq!(run("print \"{{x}} {1 + 1}}}\";"), Ok("{x} 2}\n".to_string()));
+}
+
+#[test]
+fn format_specs_set_width_precision_and_alignment() {
+    let source = "let price = 3.14159;
+    let name = \"alice\";
+    print \"{price:.2}|{name:>7}|{name:<7}|{name:*^9}\";";
+    assert_eq!(run(source), Ok("3.14|  alice|alice  |**alice**\n".to_string()));
+}
+
+#[test]
+fn only_a_colon_outside_brackets_starts_the_format_spec() {
+    let source = "let d = {\"a:b\": 1};
+    print \"{d[\"a:b\"]:>3}|{type({\"k\": 2})}|{\"x:y\"}\";";
+    assert_eq!(run(source), Ok("  1|dictionary|x:y\n".to_string()));
+}
+
+#[test]
+fn holes_can_contain_interpolated_strings() {
+    assert_eq!(run("let n = 2;\nprint \"<{\"n is {n}\"}>\";"), Ok("<n is 2>\n".to_string()));
+}
+
+#[test]
+fn parse_errors_in_a_hole_point_into_the_string() {
+    assert_eq!(
+        run("print \"{1 +}\";"),
+        Err("Parse error at line 1, column 12: Unexpected token in factor: Eof".to_string())
+    );
+    assert_eq!(
+        run("let x = 1;\nprint \"a {x $ 2} b\";"),
+        Err("Parse error at line 2, column 13: Unexpected token in string interpolation: Unknown('$')".to_string())
+    );
+}
+
+#[test]
+fn malformed_holes_are_lex_errors() {
+    assert_eq!(
+        run("print \"a {} b\";"),
+        Err("Lex error at line 1, column 10: Empty expression in interpolated string".to_string())
+    );
+    assert_eq!(
+        run("print \"a {1 + 2\";"),
+        Err("Lex error at line 1, column 10: Unclosed '{' in interpolated string".to_string())
+    );
+}
@@ -0,0 +1,50 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use alethia::{run_source_with, Interpreter};
+
+// Runs `source` while another thread keeps interrupting it until it stops
+fn run_interrupted(interpreter: &mut Interpreter, source: &str) -> Result<(), String> {
+    let handle = interpreter.interrupt_handle();
+    let stopped = Arc::new(AtomicBool::new(false));
+    let interrupter = {
+        let stopped = Arc::clone(&stopped);
+        thread::spawn(move || {
+            while !stopped.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(20));
+                handle.interrupt();
+            }
+        })
+    };
+    let result = run_source_with(source.to_string(), interpreter);
+    stopped.store(true, Ordering::Relaxed);
+    interrupter.join().unwrap();
+    result
+}
+
+#[test]
+fn an_interrupt_from_another_thread_stops_a_running_loop() {
+    let loops = [
+        "while true { }",
+        "func spin(n) { return spin(n + 1); }\nspin(0);",
+        "try { while true { } } catch e { print \"caught\"; }",
+    ];
+    for source in loops {
+        let mut interpreter = Interpreter::with_captured_output();
+        let start = Instant::now();
+        assert_eq!(run_interrupted(&mut interpreter, source), Err("Interrupted".to_string()), "{}", source);
+        assert!(start.elapsed() < Duration::from_secs(10), "{}", source);
+        assert_eq!(interpreter.take_output().unwrap().stdout, "", "{}", source);
+    }
+}
+
+#[test]
+fn the_interpreter_runs_again_after_an_interrupt() {
+    let mut interpreter = Interpreter::with_captured_output();
+    assert_eq!(run_interrupted(&mut interpreter, "while true { }"), Err("Interrupted".to_string()));
+    // the other thread may have interrupted again after the program stopped
+    interpreter.interrupt_handle().reset();
+    assert_eq!(run_source_with("print 1;".to_string(), &mut interpreter), Ok(()));
+    assert_eq!(interpreter.take_output().unwrap().stdout, "1\n");
+}
@@ -0,0 +1,340 @@
+mod common;
+
+use common::interpret;
+
+// Output of a program that both engines support, checking that they agree
+fn on_both_engines(source: &str) -> String {
+    let output = interpret(source).unwrap();
+    assert_eq!(common::run(source, &["--vm"]).stdout, output, "the VM disagrees");
+    output
+}
+
+#[test]
+fn else_if_chains_pick_the_first_true_branch() {
+    let source = "func classify(n) {
+        if n % 15 == 0 { print \"fizzbuzz\"; }
+        else if n % 3 == 0 { print \"fizz\"; }
+        else if n % 5 == 0 { print \"buzz\"; }
+        else { print n; }
+    }
+    classify(15);
+    classify(9);
+    classify(10);
+    classify(7);";
+    assert_eq!(on_both_engines(source), "fizzbuzz\nfizz\nbuzz\n7\n");
+}
+
+#[test]
+fn if_is_an_expression() {
+    let source = "func sign(x) { return if x < 0 { -1 } else if x == 0 { 0 } else { 1 }; }
+    print sign(-5);
+    print sign(0);
+    print sign(7);
+    let missing = if false { 1 };
+    print missing == nil;";
+    assert_eq!(on_both_engines(source), "-1\n0\n1\ntrue\n");
+}
+
+#[test]
+fn conditional_expressions_evaluate_one_side() {
+    let source = "let parity = \"even\" if 4 % 2 == 0 else \"odd\";
+    print parity;
+    print 1 / 0 if false else \"safe\";";
+    assert_eq!(on_both_engines(source), "even\nsafe\n");
+}
+
+#[test]
+fn compound_assignment_updates_variables_and_elements() {
+    let source = "let counts = [0, 0, 0];
+    counts[1] += 5;
+    counts[1] -= 2;
+    let d = {\"a\": 2};
+    d[\"a\"] *= 10;
+    d[\"a\"] /= 4;
+    d[\"a\"] %= 3;
+    let s = \"ab\";
+    s += \"c\";
+    print counts;
+    print d[\"a\"];
+    print s;";
+    assert_eq!(on_both_engines(source), "[0, 3, 0]\n2\nabc\n");
+}
+
+#[test]
+fn increment_and_decrement_step_by_one() {
+    let source = "let i = 0;
+    i++;
+    i++;
+    i--;
+    let arr = [1];
+    arr[0]++;
+    print i;
+    print arr;";
+    assert_eq!(on_both_engines(source), "1\n[2]\n");
+}
+
+#[test]
+fn only_variables_and_elements_can_be_updated() {
+    assert_eq!(
+        interpret("5++;"),
+        Err("Parse error at line 1, column 2: Invalid assignment target: NumberLiteral(5.0)".to_string())
+    );
+}
+
+#[test]
+fn constants_cannot_be_reassigned_redeclared_or_mutated() {
+    let cases = [
+        ("const c = 1;\nc = 2;", "Cannot assign to constant 'c'"),
+        ("const c = 1;\nc += 1;", "Cannot assign to constant 'c'"),
+        ("const c = 1;\nlet c = 2;", "Cannot redeclare constant 'c'"),
+        ("const c = [1];\nc.push(2);", "Cannot assign to constant 'c'"),
+        ("func f() { const k = 1; k++; }", "Cannot assign to constant 'k'"),
+    ];
+    for (source, error) in cases {
+        assert_eq!(interpret(source), Err(error.to_string()), "{}", source);
+    }
+}
+
+#[test]
+fn constants_are_checked_before_the_program_runs() {
+    let output = common::run("print \"start\";\nconst c = 1;\nc = 2;", &[]);
+    assert_eq!(output.stdout, "");
+    assert_eq!(output.stderr, "Error: Cannot assign to constant 'c'\n");
+}
+
+#[test]
+fn a_copy_of_a_constant_can_change() {
+    let source = "const a = [1];
+    let b = a;
+    b.push(2);
+    print a;
+    print b;";
+    assert_eq!(interpret(source), Ok("[1]\n[1, 2]\n".to_string()));
+}
+
+#[test]
+fn strict_mode_rejects_assignments_to_undeclared_names() {
+    let source = "let declared = 1;\ndeclared = 2;\nundeclared = 3;\nprint undeclared;";
+    assert_eq!(common::run(source, &[]).stdout, "3\n");
+    let strict = common::run(source, &["--strict"]);
+    assert!(!strict.success);
+    assert_eq!(strict.stderr, "Error: Assignment to undeclared variable: undeclared\n");
+}
+
+#[test]
+fn let_destructures_nested_arrays_and_dictionaries() {
+    let source = "let [first, second, ...rest] = [1, 2, 3, 4];
+    let {name, age: years} = {\"name\": \"bob\", \"age\": 42};
+    let [[a, b], {k}] = [[1, 2], {\"k\": 3}];
+    print [first, second];
+    print rest;
+    print \"{name} {years}\";
+    print a + b + k;";
+    assert_eq!(interpret(source), Ok("[1, 2]\n[3, 4]\nbob 42\n6\n".to_string()));
+}
+
+#[test]
+fn assignments_and_parameters_destructure() {
+    let source = "let first = 1;
+    let second = 2;
+    [first, second] = [second, first];
+    print [first, second];
+    func describe({name, age}) { print \"{name} is {age}\"; }
+    describe({\"name\": \"ann\", \"age\": 7});";
+    assert_eq!(interpret(source), Ok("[2, 1]\nann is 7\n".to_string()));
+}
+
+#[test]
+fn mismatched_shapes_are_runtime_errors() {
+    let cases = [
+        ("let [a, b] = [1];", "Array pattern expects 2 elements but the array has 1"),
+        ("let {z} = {\"a\": 1};", "KeyError: Key 'z' not found while destructuring dictionary"),
+        ("let [a] = 5;", "Cannot destructure number with an array pattern"),
+    ];
+    for (source, error) in cases {
+        assert_eq!(interpret(source), Err(error.to_string()), "{}", source);
+    }
+}
+
+#[test]
+fn match_takes_the_first_matching_arm() {
+    let source = "func size(n) {
+        return match n {
+            0 => \"none\",
+            1 | 2 => \"a couple\",
+            3..=9 => \"a few\",
+            x if x < 0 => \"negative\",
+            _ => \"lots\",
+        };
+    }
+    print [size(0), size(2), size(9), size(-3), size(10)];";
+    assert_eq!(interpret(source), Ok("[none, a couple, a few, negative, lots]\n".to_string()));
+}
+
+#[test]
+fn match_patterns_bind_variables() {
+    let source = "print match [1, [2, 3]] { [a, [b, c]] => a + b + c, _ => 0 };
+    print match {\"k\": 5} { {k} => k, _ => 0 };";
+    assert_eq!(interpret(source), Ok("6\n5\n".to_string()));
+}
+
+#[test]
+fn a_match_without_a_catch_all_warns_and_can_fail() {
+    let output = common::run("let x = match 5 { 1 => \"one\" };", &[]);
+    assert_eq!(
+        output.stderr,
+        "Warning at line 1, column 9: match may not be exhaustive; add a '_' arm to handle other values\n\
+         Traceback (most recent call last):\n  line 1, column 9, in <main>\n\
+         Error: No match arm matched value: 5\n"
+    );
+}
+
+#[test]
+fn struct_fields_are_read_and_assigned_with_a_dot() {
+    let source = "struct Point { x, y }
+    let p = Point { x: 1, y: 2 };
+    p.x = 3;
+    print p;
+    print type(p);
+    print p.y;
+    let d = {\"k\": 1};
+    d.k = 4;
+    print d.k;";
+    assert_eq!(interpret(source), Ok("Point { x: 3, y: 2 }\nPoint\n2\n4\n".to_string()));
+}
+
+#[test]
+fn struct_literals_and_field_access_are_checked() {
+    let cases = [
+        ("struct P { x }\nlet p = P { x: 1, z: 2 };", "P has no field 'z'"),
+        ("struct P { x }\nlet p = P { };", "Missing field 'x' in P literal"),
+        ("struct P { x }\nprint P { x: 1 }.q;", "P has no field 'q'"),
+        ("let p = Q { x: 1 };", "Undefined struct: Q"),
+    ];
+    for (source, error) in cases {
+        assert_eq!(interpret(source), Err(error.to_string()), "{}", source);
+    }
+}
+
+#[test]
+fn methods_take_self_and_write_it_back() {
+    let source = "struct Point { x, y }
+    impl Point {
+        func origin() { return Point { x: 0, y: 0 }; }
+        func shift(self, dx) { self.x += dx; }
+        func sum(self) { return self.x + self.y; }
+    }
+    let q = Point.origin();
+    q.shift(2);
+    q.shift(3);
+    print q;
+    print q.sum();";
+    assert_eq!(interpret(source), Ok("Point { x: 5, y: 0 }\n5\n".to_string()));
+    let source = "struct Stack { items }
+    impl Stack {
+        func remove(self) { return self.items[0]; }
+    }
+    const s = Stack { items: [7] };
+    print s.remove();";
+    assert_eq!(interpret(source), Ok("7\n".to_string()));
+    assert_eq!(
+        interpret("struct P { x }\nlet p = P { x: 1 };\np.missing();"),
+        Err("P has no method 'missing'".to_string())
+    );
+}
+
+#[test]
+fn core_values_have_built_in_methods() {
+    let source = "let arr = [3, 1];
+    arr.push(4);
+    print arr.len();
+    print arr.join(\", \");
+    print \"abc\".upper();
+    print \"a,b\".split(\",\");
+    print \"banana\".replace(\"a\", \"o\");
+    let d = {\"k\": 1};
+    print d.keys();
+    print d.has(\"k\");";
+    assert_eq!(on_both_engines(source), "3\n3, 1, 4\nABC\n[a, b]\nbonono\n[k]\ntrue\n");
+}
+
+#[test]
+fn enum_variants_carry_values_and_match_by_variant() {
+    let source = "enum Shape { Circle(r), Rect(w, h), Empty }
+    func area(s) {
+        return match s {
+            Shape.Circle(r) => 3 * r * r,
+            Shape.Rect(w, h) => w * h,
+            Shape.Empty => 0,
+        };
+    }
+    let s = Shape.Rect(3, 4);
+    print [area(s), area(Shape.Circle(2)), area(Shape.Empty)];
+    if s is Shape.Rect(w, h) { print \"{w} by {h}\"; }
+    print s;
+    print Shape.Empty;
+    print s == Shape.Rect(3, 4);
+    print Shape.Circle(1) is Shape.Rect;";
+    let output = common::run(source, &[]);
+    // naming every variant makes the match exhaustive
+    assert_eq!(output.stderr, "");
+    assert_eq!(output.stdout, "[12, 12, 0]\n3 by 4\nShape.Rect(3, 4)\nShape.Empty\ntrue\nfalse\n");
+}
+
+#[test]
+fn enum_variants_are_checked() {
+    let cases = [
+        ("enum E { A(x) }\nlet a = E.A(1, 2);", "E.A takes 1 value(s) but 2 were given"),
+        ("enum E { A }\nlet b = E.B;", "E has no variant 'B'"),
+    ];
+    for (source, error) in cases {
+        assert_eq!(interpret(source), Err(error.to_string()), "{}", source);
+    }
+}
+
+#[test]
+fn thrown_errors_are_caught_with_their_kind_and_stack() {
+    let source = "func risky(n) {
+        if n > 2 { throw error(\"too big\", \"ValueError\"); }
+        return n;
+    }
+    try {
+        print risky(1);
+        print risky(5);
+    } catch e {
+        print \"{e.kind}: {e.message}\";
+        print e.stack;
+    } finally {
+        print \"done\";
+    }
+    try { throw \"plain\"; } catch e { print \"{e.kind}: {e.message}\"; }";
+    assert_eq!(interpret(source), Ok("1\nValueError: too big\n[risky]\ndone\nError: plain\n".to_string()));
+}
+
+#[test]
+fn built_in_runtime_errors_can_be_caught() {
+    let source = "let totals = {\"a\": 1};
+    try { print totals[\"b\"]; } catch e { print e.kind; }
+    try { print 1 / 0; } catch e { print e.kind; }";
+    assert_eq!(interpret(source), Ok("KeyError\nZeroDivisionError\n".to_string()));
+}
+
+#[test]
+fn finally_runs_on_return_and_on_errors_passing_through() {
+    let source = "func f() { try { return 1; } finally { print \"cleanup\"; } }
+    print f();
+    try {
+        try { throw \"inner\"; } finally { print \"inner finally\"; }
+    } catch e {
+        print \"outer caught \" + e.message;
+    }";
+    assert_eq!(interpret(source), Ok("cleanup\n1\ninner finally\nouter caught inner\n".to_string()));
+}
+
+#[test]
+fn an_uncaught_error_stops_the_program() {
+    assert_eq!(
+        interpret("throw error(\"negative amount\", \"ValueError\");\nprint \"unreachable\";"),
+        Err("ValueError: negative amount".to_string())
+    );
+}
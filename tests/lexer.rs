@@ -0,0 +1,82 @@
+use alethia::{LexError, Lexer, Token};
+
+// The tokens of `source` without their spans, up to and including Eof
+fn tokens(source: &str) -> Result<Vec<Token>, LexError> {
+    Lexer::new(source).map(|result| result.map(|t| t.token)).collect()
+}
+
+fn lex_error(source: &str) -> String {
+    tokens(source).unwrap_err().to_string()
+}
+
+#[test]
+fn numbers_can_use_prefixes_separators_and_exponents() {
+    let source = "0xFF 0b1010 0o17 1_000_000 1.5e-3 2E2 0.25";
+    let expected = [255.0, 10.0, 15.0, 1_000_000.0, 1.5e-3, 200.0, 0.25];
+    let mut numbers: Vec<Token> = expected.iter().map(|n| Token::Number(*n)).collect();
+    numbers.push(Token::Eof);
+    assert_eq!(tokens(source), Ok(numbers));
+}
+
+#[test]
+fn a_range_after_a_number_is_not_a_decimal_point() {
+    assert_eq!(tokens("1..3").unwrap().first(), Some(&Token::Number(1.0)));
+}
+
+#[test]
+fn malformed_numbers_are_errors_at_their_position() {
+    assert_eq!(lex_error("let x = 1.2.3;"), "Lex error at line 1, column 9: Unexpected '.' in numeric literal");
+    assert_eq!(lex_error("\n  0b102"), "Lex error at line 2, column 3: Invalid digit '2' for base 2 literal");
+    assert_eq!(lex_error("0x"), "Lex error at line 1, column 1: Expected digits after numeric prefix");
+    assert_eq!(lex_error("1__0"), "Lex error at line 1, column 1: Misplaced '_' in numeric literal");
+    assert_eq!(lex_error("10_"), "Lex error at line 1, column 1: Misplaced '_' in numeric literal");
+    assert_eq!(lex_error("1e+"), "Lex error at line 1, column 1: Expected digit in exponent");
+    assert_eq!(lex_error("1."), "Lex error at line 1, column 1: Expected digit after decimal point");
+    assert_eq!(lex_error("x = 12abc"), "Lex error at line 1, column 5: Invalid character 'a' in numeric literal");
+}
+
+#[test]
+fn unterminated_strings_are_errors_where_they_start() {
+    assert_eq!(lex_error("print 1;\nprint \"abc"), "Lex error at line 2, column 7: Unterminated string literal");
+}
+
+#[test]
+fn tokens_carry_their_byte_range_and_position() {
+    let spans: Vec<(Token, usize, usize, usize, usize)> = Lexer::new("let \"é\" = 10;\n  x")
+        .map(|result| result.unwrap())
+        .map(|t| (t.token, t.span.start, t.span.end, t.span.line, t.span.column))
+        .collect();
+    assert_eq!(
+        spans,
+        vec![
+            (Token::Let, 0, 3, 1, 1),
+            (Token::StringLiteral("é".to_string()), 4, 8, 1, 5),
+            (Token::Assign, 9, 10, 1, 9),
+            (Token::Number(10.0), 11, 13, 1, 11),
+            (Token::Semicolon, 13, 14, 1, 13),
+            (Token::Identifier("x".to_string()), 17, 18, 2, 3),
+            (Token::Eof, 18, 18, 2, 4),
+        ]
+    );
+}
+
+#[test]
+fn the_iterator_stops_after_eof_or_the_first_error() {
+    let mut lexer = Lexer::new("1");
+    assert!(lexer.next().is_some());
+    assert_eq!(lexer.next().map(|t| t.unwrap().token), Some(Token::Eof));
+    assert!(lexer.next().is_none());
+
+    let results: Vec<_> = Lexer::new("1 0b2 3").collect();
+    assert_eq!(results.len(), 2);
+    assert!(results[1].is_err());
+}
+
+#[test]
+fn long_inputs_lex_in_linear_time() {
+    // quadratic lexing of 300,000 tokens would take minutes
+    let source = "x = x + 1;\n".repeat(50_000);
+    let start = std::time::Instant::now();
+    assert_eq!(Lexer::new(&source).count(), 300_001);
+    assert!(start.elapsed() < std::time::Duration::from_secs(5));
+}
@@ -0,0 +1,103 @@
+mod common;
+
+use std::time::{Duration, Instant};
+use alethia::{run_source_with, Interpreter, Limits};
+
+// Runs `source` on the tree-walker with `limits`, returning its result and what it printed
+fn run_limited(source: &str, limits: Limits) -> (Result<(), String>, String) {
+    let mut interpreter = Interpreter::with_captured_output();
+    interpreter.set_limits(limits);
+    let result = run_source_with(source.to_string(), &mut interpreter);
+    (result, interpreter.take_output().unwrap().stdout)
+}
+
+fn size_limit(max: usize) -> Limits {
+    Limits { max_collection_size: Some(max), ..Limits::default() }
+}
+
+#[test]
+fn the_step_limit_stops_a_runaway_loop() {
+    let limits = Limits { max_steps: Some(1000), ..Limits::default() };
+    assert_eq!(run_limited("while true { }", limits).0, Err("Step limit of 1000 exceeded".to_string()));
+    let vm = common::run("while true { }", &["--vm", "--max-steps", "1000"]);
+    assert_eq!(vm.stderr, "Error: Step limit of 1000 exceeded\n");
+}
+
+#[test]
+fn the_timeout_stops_a_runaway_loop() {
+    let limits = Limits { timeout: Some(Duration::from_millis(50)), ..Limits::default() };
+    let start = Instant::now();
+    assert_eq!(run_limited("while true { }", limits).0, Err("Time limit of 50ms exceeded".to_string()));
+    assert!(start.elapsed() < Duration::from_secs(5));
+    let vm = common::run("while true { }", &["--vm", "--timeout", "50ms"]);
+    assert_eq!(vm.stderr, "Error: Time limit of 50ms exceeded\n");
+}
+
+#[test]
+fn every_program_gets_the_full_budget() {
+    let mut interpreter = Interpreter::with_captured_output();
+    interpreter.set_limits(Limits { max_steps: Some(200), ..Limits::default() });
+    for _ in 0..3 {
+        let source = "let i = 0;\nwhile i < 10 { i++; }\nprint i;".to_string();
+        assert_eq!(run_source_with(source, &mut interpreter), Ok(()));
+    }
+    assert_eq!(interpreter.take_output().unwrap().stdout, "10\n10\n10\n");
+}
+
+#[test]
+fn limits_cannot_be_caught() {
+    let source = "try { while true { } } catch e { print \"caught\"; }";
+    let limits = Limits { max_steps: Some(100), ..Limits::default() };
+    assert_eq!(run_limited(source, limits), (Err("Step limit of 100 exceeded".to_string()), String::new()));
+}
+
+#[test]
+fn nested_values_count_towards_the_size_limit() {
+    let error = Err("Collection size limit of 1000 exceeded".to_string());
+    // each of these doubles its value, which a count of only the outer elements would miss
+    let doubling = [
+        "let a = [1];\nwhile true { a = [a, a]; }",
+        "let a = [1];\nwhile true { a[0] = [a, a]; }",
+        "let d = {\"k\": 1};\nwhile true { d[\"k\"] = [d, d]; }",
+    ];
+    for source in doubling {
+        assert_eq!(run_limited(source, size_limit(1000)).0, error, "{}", source);
+        let vm = common::run(source, &["--vm", "--max-collection-size", "1000"]);
+        assert_eq!(vm.stderr, "Error: Collection size limit of 1000 exceeded\n", "{}", source);
+    }
+    // two elements, then two and one nested ones, and three bytes of string
+    let (result, stdout) = run_limited("let a = [[1, 2], [\"abc\"]];\nprint a;", size_limit(8));
+    assert_eq!((result, stdout.as_str()), (Ok(()), "[[1, 2], [abc]]\n"));
+    let (result, _) = run_limited("let a = [[1, 2], [\"abc\"]];", size_limit(7));
+    assert_eq!(result, Err("Collection size limit of 7 exceeded".to_string()));
+}
+
+#[test]
+fn strings_are_checked_before_they_are_built() {
+    let limited = [
+        ("let s = \"ab\" * 1e8;", "Collection size limit of 1000 exceeded"),
+        ("let s = \"ab\" * 1e19;", "Collection size limit of 1000 exceeded"),
+        ("let s = \"x\";\nwhile true { s += s; }", "Collection size limit of 1000 exceeded"),
+        ("let s = \"x\" * 600;\nlet t = s + s;", "Collection size limit of 1000 exceeded"),
+    ];
+    for (source, error) in limited {
+        assert_eq!(run_limited(source, size_limit(1000)).0, Err(error.to_string()), "{}", source);
+        let vm = common::run(source, &["--vm", "--max-collection-size", "1000"]);
+        assert_eq!(vm.stderr, format!("Error: {}\n", error), "{}", source);
+    }
+    // without a limit, a length that can't exist is an error rather than a crash
+    let unlimited = common::run("print (\"ab\" * 1e19).len();", &["--vm"]);
+    assert_eq!(unlimited.stderr, "Error: RuntimeError: Repeated string is too long\n");
+}
+
+#[test]
+fn the_output_limit_stops_printing_before_it_is_passed() {
+    let limits = Limits { max_output_bytes: Some(10), ..Limits::default() };
+    let source = "let i = 0;\nwhile true { print i; i++; }";
+    assert_eq!(
+        run_limited(source, limits),
+        (Err("Output limit of 10 bytes exceeded".to_string()), "0\n1\n2\n3\n4\n".to_string())
+    );
+    let vm = common::run(source, &["--vm", "--max-output", "10"]);
+    assert_eq!((vm.stdout.as_str(), vm.stderr.as_str()), ("0\n1\n2\n3\n4\n", "Error: Output limit of 10 bytes exceeded\n"));
+}
@@ -0,0 +1,117 @@
+mod common;
+
+use alethia::{AstNode, Lexer, Optimizer, Parser, Span, Token};
+
+// Programs whose output must not change when the optimizer runs
+const PROGRAMS: &[(&str, &str)] = &[
+    (
+        "arithmetic",
+        "let day = 60 * 60 * 24;
+        print day;
+        print (2 + 3) * 4 - 10 / 4;
+        print 7 % 3;
+        print -(2 * 3);
+        print \"con\" + \"cat\";
+        print 1 + 2 == 3;
+        print true and false or true;",
+    ),
+    (
+        "branches",
+        "if true { print \"kept\"; } else { print \"pruned\"; }
+        if 1 > 2 { print \"pruned\"; }
+        let x = if false { 1 } else { 2 };
+        print x;
+        print if 2 > 1 { \"yes\" };
+        while false { print \"never\"; }
+        let i = 0;
+        while i < 3 { i += 1; }
+        print i;",
+    ),
+    (
+        "unreachable",
+        "func first(items) {
+            return items[0];
+            print \"unreachable\";
+        }
+        print first([4, 5]);
+        let n = 0;
+        while true {
+            n += 1;
+            if n == 3 { break; print \"unreachable\"; }
+        }
+        print n;
+        try { throw \"stop\"; print \"unreachable\"; } catch e { print e.message; }",
+    ),
+    (
+        "runtime errors are kept",
+        "try { print 1 / 0; } catch e { print e.kind; }
+        try { print 5 % 0; } catch e { print e.kind; }
+        try { print 1 + \"one\"; } catch e { print e.kind; }
+        try { if 1 { print \"no\"; } } catch e { print e.kind; }
+        print 10 / (5 - 5);",
+    ),
+];
+
+#[test]
+fn optimized_programs_produce_the_same_output() {
+    for (name, source) in PROGRAMS {
+        for engine in [&[][..], &["--vm"][..]] {
+            let plain = common::run(source, &[engine, &["-O0"]].concat());
+            let optimized = common::run(source, &[engine, &["-O1"]].concat());
+            assert_eq!(plain, optimized, "output of '{}' changed with -O1 {:?}", name, engine);
+        }
+    }
+}
+
+fn optimize(source: &str) -> Vec<AstNode> {
+    let tokens = Lexer::new(source).collect::<Result<Vec<_>, _>>().unwrap();
+    let output = Parser::new(tokens).parse_program_with_diagnostics();
+    assert!(output.errors.is_empty());
+    match Optimizer::new().optimize_program(output.program) {
+        AstNode::Program(statements) => statements,
+        other => vec![other],
+    }
+}
+
+fn print(value: AstNode) -> AstNode {
+    AstNode::PrintStatement(Box::new(value))
+}
+
+#[test]
+fn folds_constant_arithmetic() {
+    assert_eq!(optimize("print 60 * 60 * 24;"), [print(AstNode::NumberLiteral(86400.0))]);
+    assert_eq!(optimize("print \"a\" + \"b\";"), [print(AstNode::StringLiteral("ab".to_string()))]);
+}
+
+#[test]
+fn keeps_operations_that_fail_at_runtime() {
+    let division = AstNode::BinaryExpression {
+        left: Box::new(AstNode::NumberLiteral(1.0)),
+        operator: Token::Divide,
+        right: Box::new(AstNode::NumberLiteral(0.0)),
+        span: Span { start: 8, end: 9, line: 1, column: 9 },
+    };
+    assert_eq!(optimize("print 1 / 0;"), [print(division)]);
+    let addition = AstNode::BinaryExpression {
+        left: Box::new(AstNode::NumberLiteral(1.0)),
+        operator: Token::Plus,
+        right: Box::new(AstNode::StringLiteral("a".to_string())),
+        span: Span { start: 8, end: 9, line: 1, column: 9 },
+    };
+    assert_eq!(optimize("print 1 + \"a\";"), [print(addition)]);
+}
+
+#[test]
+fn prunes_dead_branches_and_unreachable_statements() {
+    assert_eq!(
+        optimize("if true { print 1; } else { print 2; }"),
+        [AstNode::Block(vec![print(AstNode::NumberLiteral(1.0))])]
+    );
+    assert_eq!(optimize("while false { print 1; }"), [AstNode::NumberLiteral(0.0)]);
+    let function = AstNode::FunctionDeclaration {
+        name: "f".to_string(),
+        params: Vec::new(),
+        body: Box::new(AstNode::Block(vec![AstNode::Return(Some(Box::new(AstNode::NumberLiteral(1.0))))])),
+    };
+    assert_eq!(optimize("func f() { return 1; print 2; }"), [function]);
+}
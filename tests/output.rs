@@ -0,0 +1,91 @@
+use alethia::{run_source_vm, run_source_with, CapturedOutput, Interpreter, Output, Vm};
+
+#[test]
+fn print_statements_are_captured() {
+    let mut interpreter = Interpreter::with_captured_output();
+    run_source_with("print 1 + 2;\nprint \"done\";".to_string(), &mut interpreter).unwrap();
+    assert_eq!(
+        interpreter.take_output(),
+        Some(Output { stdout: "3\ndone\n".to_string(), stderr: String::new() })
+    );
+    // taking the output empties the buffers
+    assert_eq!(interpreter.take_output(), Some(Output::default()));
+}
+
+#[test]
+fn warnings_are_written_to_the_captured_stderr() {
+    let mut interpreter = Interpreter::with_captured_output();
+    let source = "func f() { let unused = 1; return 2; }\nprint f();";
+    run_source_with(source.to_string(), &mut interpreter).unwrap();
+    let output = interpreter.take_output().unwrap();
+    assert_eq!(output.stdout, "2\n");
+    assert_eq!(output.stderr, "Warning: Variable 'unused' in function f is never used\n");
+}
+
+#[test]
+fn output_is_kept_across_runs_on_the_same_interpreter() {
+    let mut interpreter = Interpreter::with_captured_output();
+    run_source_with("let x = 5;\nprint x;".to_string(), &mut interpreter).unwrap();
+    run_source_with("print x * 2;".to_string(), &mut interpreter).unwrap();
+    assert_eq!(interpreter.take_output().unwrap().stdout, "5\n10\n");
+}
+
+#[test]
+fn output_printed_before_an_error_is_captured() {
+    let mut interpreter = Interpreter::with_captured_output();
+    let result = run_source_with("print \"before\";\nprint 1 / 0;".to_string(), &mut interpreter);
+    assert_eq!(result, Err("ZeroDivisionError: Division by zero".to_string()));
+    assert_eq!(interpreter.take_output().unwrap().stdout, "before\n");
+}
+
+#[test]
+fn a_custom_sink_receives_print_output() {
+    let sink = CapturedOutput::default();
+    let mut interpreter = Interpreter::new();
+    interpreter.set_stdout(Box::new(sink.clone()));
+    run_source_with("print [1, 2];".to_string(), &mut interpreter).unwrap();
+    assert_eq!(sink.take(), "[1, 2]\n");
+    // only interpreters made with_captured_output() keep their own buffers
+    assert_eq!(interpreter.take_output(), None);
+}
+
+#[test]
+fn the_vm_writes_prints_and_warnings_to_its_sinks() {
+    let mut vm = Vm::with_captured_output();
+    let source = "func f() { let unused = 1; return 2; }\nprint f();\nlet x = match 1 { 1 => 2 };";
+    let result = run_source_vm(source.to_string(), &mut vm);
+    // the VM rejects match only after the warnings are written
+    assert_eq!(result, Err("Pattern matching is not supported by the bytecode VM".to_string()));
+    assert_eq!(
+        vm.take_output(),
+        Some(Output {
+            stdout: String::new(),
+            stderr: "Warning at line 3, column 9: match may not be exhaustive; add a '_' arm to handle other values\n\
+                     Warning: Variable 'unused' in function f is never used\n"
+                .to_string(),
+        })
+    );
+
+    let sink = CapturedOutput::default();
+    let mut vm = Vm::new();
+    vm.set_stdout(Box::new(sink.clone()));
+    run_source_vm("print [1, 2];".to_string(), &mut vm).unwrap();
+    assert_eq!(sink.take(), "[1, 2]\n");
+    assert_eq!(vm.take_output(), None);
+}
+
+#[test]
+fn failed_logical_operators_print_nothing_and_raise_a_type_error() {
+    let mut interpreter = Interpreter::with_captured_output();
+    let source = "try { print 1 and 2; } catch e { print \"{e.kind}: {e.message}\"; }\nprint true or 3;";
+    let result = run_source_with(source.to_string(), &mut interpreter);
+    assert_eq!(result, Err("TypeError: Cannot apply `or` to non-boolean values".to_string()));
+    assert_eq!(
+        interpreter.take_output(),
+        Some(Output { stdout: "TypeError: Cannot apply `and` to non-boolean values\n".to_string(), stderr: String::new() })
+    );
+    let mut vm = Vm::with_captured_output();
+    let result = run_source_vm("print 1 and 2;".to_string(), &mut vm);
+    assert_eq!(result, Err("TypeError: Cannot apply `and` to non-boolean values".to_string()));
+    assert_eq!(vm.take_output(), Some(Output::default()));
+}
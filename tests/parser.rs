@@ -0,0 +1,52 @@
+use alethia::{Lexer, Parser};
+
+// Every syntax error in `source`, formatted as the CLI prints them
+fn diagnostics(source: &str) -> Vec<String> {
+    let tokens = Lexer::new(source).collect::<Result<Vec<_>, _>>().unwrap();
+    let output = Parser::new(tokens).parse_program_with_diagnostics();
+    output.errors.iter().map(|e| e.to_string()).collect()
+}
+
+#[test]
+fn every_broken_statement_is_reported_once() {
+    let source = "let x = ;\nprint 1 2;\nlet y = 3;\nprint y +;\n";
+    assert_eq!(
+        diagnostics(source),
+        [
+            "Parse error at line 1, column 9: Unexpected token in factor: Semicolon",
+            "Parse error at line 2, column 9: Expected Semicolon, but found Number(2.0)",
+            "Parse error at line 4, column 10: Unexpected token in factor: Semicolon",
+        ]
+    );
+}
+
+#[test]
+fn recovery_skips_the_braces_a_broken_statement_opened() {
+    let source = "func f( {\n    print 1;\n}\nlet x = ;\nprint 2\nlet y = 3;\n";
+    assert_eq!(
+        diagnostics(source),
+        [
+            "Parse error at line 2, column 5: Expected key in dictionary pattern, found Print",
+            "Parse error at line 4, column 9: Unexpected token in factor: Semicolon",
+            "Parse error at line 6, column 1: Expected Semicolon, but found Let",
+        ]
+    );
+}
+
+#[test]
+fn errors_inside_blocks_are_recovered_within_the_block() {
+    let source = "if true {\n    let a = ;\n    print a;\n    print +;\n}\nprint 3 3;\n";
+    assert_eq!(
+        diagnostics(source),
+        [
+            "Parse error at line 2, column 13: Unexpected token in factor: Semicolon",
+            "Parse error at line 4, column 11: Unexpected token in factor: Plus",
+            "Parse error at line 6, column 9: Expected Semicolon, but found Number(3.0)",
+        ]
+    );
+}
+
+#[test]
+fn a_valid_program_has_no_diagnostics() {
+    assert!(diagnostics("func f(a) { return a; }\nprint f(1);").is_empty());
+}
@@ -0,0 +1,109 @@
+mod common;
+
+use alethia::{run_source_with, Interpreter};
+
+const ENGINES: [&[&str]; 2] = [&[], &["--vm"]];
+
+#[test]
+fn tail_recursion_runs_in_constant_stack() {
+    let source = "func count(n, acc) {
+        if n == 0 { return acc; }
+        return count(n - 1, acc + 1);
+    }
+    print count(100000, 0);";
+    for engine in ENGINES {
+        let run = common::run(source, engine);
+        assert_eq!((run.stdout.as_str(), run.stderr.as_str()), ("100000\n", ""), "{:?}", engine);
+    }
+}
+
+#[test]
+fn mutual_tail_recursion_runs_in_constant_stack() {
+    let source = "func is_even(n) { if n == 0 { return true; } return is_odd(n - 1); }
+    func is_odd(n) { if n == 0 { return false; } return is_even(n - 1); }
+    print is_even(100000);
+    print is_odd(100001);";
+    for engine in ENGINES {
+        assert_eq!(common::run(source, engine).stdout, "true\ntrue\n", "{:?}", engine);
+    }
+}
+
+#[test]
+fn deep_recursion_below_the_limit_succeeds() {
+    let source = "func depth(n) { if n == 0 { return 0; } return 1 + depth(n - 1); }
+    print depth(5000);";
+    for engine in ENGINES {
+        assert_eq!(common::run(source, engine).stdout, "5000\n", "{:?}", engine);
+    }
+}
+
+#[test]
+fn exceeding_the_call_depth_is_a_catchable_error() {
+    let source = "func forever(n) { return 1 + forever(n + 1); }
+    try { forever(0); } catch e { print e.kind; print e.message; }
+    print \"still running\";";
+    let stdout = common::run(source, &["--max-depth", "200"]).stdout;
+    assert_eq!(
+        stdout,
+        "StackOverflowError\nStack overflow: maximum call depth of 200 exceeded\nstill running\n"
+    );
+}
+
+#[test]
+fn uncaught_stack_overflow_is_reported() {
+    let source = "func forever(n) { return 1 + forever(n + 1); }
+    print forever(0);";
+    for engine in ENGINES {
+        let stderr = common::run(source, &[engine, &["--max-depth", "100"]].concat()).stderr;
+        // the tree-walker prints a traceback ahead of the error
+        assert_eq!(
+            stderr.lines().last(),
+            Some("Error: StackOverflowError: Stack overflow: maximum call depth of 100 exceeded"),
+            "{:?}",
+            engine
+        );
+    }
+}
+
+#[test]
+fn tail_calls_inside_try_keep_their_frame() {
+    // the try statement has to see errors from the call, so the call is not eliminated
+    let source = "func count(n) {
+        if n == 0 { throw \"bottom\"; }
+        try { return count(n - 1); } catch e { throw e; }
+    }
+    try { count(500); } catch e { print e.kind; }
+    try { count(50); } catch e { print e.message; }";
+    let stdout = common::run(source, &["--max-depth", "100"]).stdout;
+    assert_eq!(stdout, "StackOverflowError\nbottom\n");
+}
+
+// Runs a script on a thread with std's default stack size, as an embedder would
+fn run_on_plain_thread(source: &'static str) -> (Result<(), String>, String) {
+    std::thread::spawn(move || {
+        let mut interpreter = Interpreter::with_captured_output();
+        let result = run_source_with(source.to_string(), &mut interpreter);
+        (result, interpreter.take_output().unwrap().stdout)
+    })
+    .join()
+    .unwrap()
+}
+
+#[test]
+fn recursion_deeper_than_the_native_stack_is_an_error_on_ordinary_threads() {
+    let source = "func depth(n) { if n == 0 { return 0; } return 1 + depth(n - 1); }
+    print depth(9999);";
+    let (result, _) = run_on_plain_thread(source);
+    assert_eq!(
+        result,
+        Err("StackOverflowError: Stack overflow: nesting too deep for the native stack".to_string())
+    );
+}
+
+#[test]
+fn running_out_of_native_stack_can_be_caught() {
+    let source = "func nested(n) { if n == 0 { return 0; } return [[[[1 + nested(n - 1)][0]][0]][0]][0]; }
+    try { nested(9999); } catch e { print e.kind; }
+    print nested(10);";
+    assert_eq!(run_on_plain_thread(source), (Ok(()), "StackOverflowError\n10\n".to_string()));
+}
@@ -0,0 +1,118 @@
+use alethia::{AstNode, Binding, GlobalScope, Lexer, Parser, Pattern, ResolveOutput, Resolver, Span, Token};
+
+fn resolve(source: &str, globals: &mut GlobalScope) -> ResolveOutput {
+    let tokens = Lexer::new(source).collect::<Result<Vec<_>, _>>().unwrap();
+    let program = Parser::new(tokens).parse_program().unwrap();
+    Resolver::new(globals, false).resolve_program(program)
+}
+
+fn variable(name: &str, binding: Binding) -> Box<AstNode> {
+    Box::new(AstNode::Variable { name: name.to_string(), binding })
+}
+
+#[test]
+fn locals_get_frame_slots_and_globals_global_slots() {
+    let mut globals = GlobalScope::default();
+    let output = resolve("let g = 1;\nfunc f(a, b) { let c = a + b; return c + g; }", &mut globals);
+    assert!(output.errors.is_empty() && output.warnings.is_empty(), "{:?} {:?}", output.errors, output.warnings);
+    let AstNode::Program(statements) = output.program else { panic!("not a program") };
+    // declarations become assignments to their slot
+    let body = vec![
+        AstNode::Assignment {
+            target: variable("c", Binding::Local(2)),
+            value: Box::new(AstNode::BinaryExpression {
+                left: variable("a", Binding::Local(0)),
+                operator: Token::Plus,
+                right: variable("b", Binding::Local(1)),
+                span: Span { start: 36, end: 37, line: 2, column: 26 },
+            }),
+        },
+        AstNode::Return(Some(Box::new(AstNode::BinaryExpression {
+            left: variable("c", Binding::Local(2)),
+            operator: Token::Plus,
+            right: variable("g", Binding::Global(0)),
+            span: Span { start: 50, end: 51, line: 2, column: 40 },
+        }))),
+    ];
+    let function = AstNode::Assignment {
+        target: variable("f", Binding::Global(1)),
+        value: Box::new(AstNode::FunctionDeclaration {
+            name: "f".to_string(),
+            params: vec![
+                Pattern::Variable { name: "a".to_string(), binding: Binding::Local(0) },
+                Pattern::Variable { name: "b".to_string(), binding: Binding::Local(1) },
+            ],
+            body: Box::new(AstNode::Block(body)),
+        }),
+    };
+    assert_eq!(statements[1], function);
+}
+
+#[test]
+fn globals_persist_across_programs_resolved_with_the_same_scope() {
+    let mut globals = GlobalScope::default();
+    assert!(resolve("let first = 1;", &mut globals).errors.is_empty());
+    let slot = globals.get("first").unwrap();
+    let output = resolve("print first;\nlet second = 2;", &mut globals);
+    assert!(output.errors.is_empty(), "{:?}", output.errors);
+    assert_eq!(globals.get("first"), Some(slot));
+    assert_ne!(globals.get("second"), Some(slot));
+}
+
+#[test]
+fn functions_cannot_see_their_callers_or_enclosing_functions() {
+    let source = "func outer() {
+        let hidden = 1;
+        func inner() { return hidden; };
+        return inner();
+    }
+    func other() { return nowhere; }";
+    let output = resolve(source, &mut GlobalScope::default());
+    assert_eq!(
+        output.errors,
+        ["Cannot use local variable 'hidden' of an enclosing function", "Use of undeclared variable 'nowhere'"]
+    );
+}
+
+#[test]
+fn unused_and_shadowed_locals_are_warnings() {
+    let source = "func f(x) {
+        let unused = 1;
+        let _ignored = 2;
+        let y = x;
+        let y = y + 1;
+        return y;
+    }";
+    let output = resolve(source, &mut GlobalScope::default());
+    assert!(output.errors.is_empty(), "{:?}", output.errors);
+    assert_eq!(
+        output.warnings,
+        [
+            "Variable 'y' in function f shadows an earlier declaration",
+            "Variable 'unused' in function f is never used",
+        ]
+    );
+}
+
+#[test]
+fn mutating_methods_need_a_declared_receiver() {
+    let output = resolve("xs.push(1);\nfunc f() { ys.pop(); }", &mut GlobalScope::default());
+    assert_eq!(output.errors, ["Use of undeclared variable 'xs'", "Use of undeclared variable 'ys'"]);
+}
+
+#[test]
+fn impl_methods_named_like_mutating_ones_are_not_writes() {
+    let source = "struct Point { x, y }
+    func f() { const q = Point { x: 1, y: 2 }; return q.remove(); }
+    impl Point {
+        func remove(self) { return self.x; }
+    }
+    const p = Point { x: 1, y: 2 };
+    print p.remove();
+    const xs = [1];
+    xs.push(2);";
+    let output = resolve(source, &mut GlobalScope::default());
+    assert!(output.warnings.is_empty(), "{:?}", output.warnings);
+    // push is still the built-in one
+    assert_eq!(output.errors, ["Cannot assign to constant 'xs'"]);
+}
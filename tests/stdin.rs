@@ -0,0 +1,98 @@
+mod common;
+
+use std::io::Cursor;
+use alethia::{run_source_with, Interpreter};
+
+// Runs a script reading `input` as its stdin, returning what it printed
+fn run(source: &str, input: &str) -> String {
+    let mut interpreter = Interpreter::with_captured_output();
+    interpreter.set_stdin(Box::new(Cursor::new(input.to_string())));
+    run_source_with(source.to_string(), &mut interpreter).unwrap();
+    interpreter.take_output().unwrap().stdout
+}
+
+#[test]
+fn input_shows_the_prompt_and_reads_a_line() {
+    let source = "let name = input(\"Name: \");
+    print \"Hello, {name}!\";";
+    assert_eq!(run(source, "Ann\nBob\n"), "Name: Hello, Ann!\n");
+}
+
+#[test]
+fn read_line_strips_line_endings_and_gives_nil_at_the_end() {
+    let source = "print read_line();
+    print read_line();
+    print read_line();
+    print read_line() == nil;";
+    assert_eq!(run(source, "one\r\ntwo\nthree"), "one\ntwo\nthree\ntrue\n");
+}
+
+#[test]
+fn read_all_stdin_returns_the_rest_of_the_input() {
+    let source = "print read_line();
+    print read_all_stdin().split(\"-\");
+    print read_all_stdin() == nil;";
+    assert_eq!(run(source, "first\na-b-c"), "first\n[a, b, c]\ntrue\n");
+}
+
+#[test]
+fn stdin_lines_iterates_until_the_input_ends() {
+    let source = "let lines = stdin_lines();
+    let count = 0;
+    while true {
+        let line = lines.next();
+        if line == nil { break; }
+        count += 1;
+        print \"{count}: {line}\";
+    }
+    print lines.next() == nil;";
+    assert_eq!(run(source, "a\n\nb\n"), "1: a\n2: \n3: b\ntrue\n");
+}
+
+#[test]
+fn an_empty_input_is_at_its_end_immediately() {
+    let source = "print input(\"? \") == nil;
+    print read_all_stdin() == nil;";
+    assert_eq!(run(source, ""), "? true\ntrue\n");
+}
+
+#[test]
+fn stdin_lines_is_its_own_type() {
+    let source = "struct StdinLines {}
+    impl StdinLines {
+        func next(self) { return \"user method\"; }
+    }
+    let lines = stdin_lines();
+    print type(lines);
+    print lines.next();
+    print StdinLines {}.next();";
+    assert_eq!(run(source, "piped\n"), "StdinLines\npiped\nuser method\n");
+}
+
+#[test]
+fn scripts_filter_piped_input() {
+    let source = "let line = read_line();
+    while true {
+        if line == nil { break; }
+        print line.upper();
+        line = read_line();
+    }";
+    for engine in [&[][..], &["--vm"][..]] {
+        let output = common::run_with_input(source, engine, "one\ntwo\n");
+        assert_eq!(output.stdout, "ONE\nTWO\n", "{:?}", engine);
+    }
+}
+
+#[test]
+fn stdin_lines_works_on_both_engines() {
+    let source = "let lines = stdin_lines();
+    while true {
+        let line = lines.next();
+        if line == nil { break; }
+        print \"> {line}\";
+    }";
+    for engine in [&[][..], &["--vm"][..]] {
+        let output = common::run_with_input(source, engine, "a\nb\n");
+        assert_eq!(output.stdout, "> a\n> b\n", "{:?}: {}", engine, output.stderr);
+    }
+}
@@ -0,0 +1,111 @@
+mod common;
+
+use common::run;
+
+#[test]
+fn each_line_gives_where_the_next_call_or_the_error_is() {
+    let source = "func inner(x) {
+    return x / 0;
+}
+func middle(x) {
+    let y = inner(x);
+    return y;
+}
+func outer() {
+    return middle(1) + 1;
+}
+print outer();";
+    assert_eq!(
+        run(source, &[]).stderr,
+        "Traceback (most recent call last):
+  line 11, column 7, in <main>
+  line 9, column 12, in outer
+  line 5, column 13, in middle
+  line 2, column 14, in inner
+Error: ZeroDivisionError: Division by zero
+"
+    );
+}
+
+#[test]
+fn calls_are_reported_where_the_callee_is_written() {
+    let source = "struct Box { items }
+impl Box {
+    func first(self) { return self.items[0] + 1; }
+}
+let b = Box { items: [[]] };
+print   b.first();";
+    assert_eq!(
+        run(source, &[]).stderr,
+        "Traceback (most recent call last):
+  line 6, column 9, in <main>
+  line 3, column 45, in Box.first
+Error: TypeError: Cannot add array and number
+"
+    );
+}
+
+#[test]
+fn errors_outside_functions_have_a_traceback() {
+    assert_eq!(
+        run("let totals = {};\nprint totals[\"march\"];", &[]).stderr,
+        "Traceback (most recent call last):
+  line 2, column 13, in <main>
+Error: KeyError: Key 'march' not found in dictionary
+"
+    );
+    assert_eq!(
+        run("print 1;\n  throw error(\"negative amount\", \"ValueError\");", &[]).stderr,
+        "Traceback (most recent call last):
+  line 2, column 3, in <main>
+Error: ValueError: negative amount
+"
+    );
+}
+
+#[test]
+fn errors_from_built_in_functions_point_at_the_call() {
+    let source = "func load(path) {\n    return read_file(path);\n}\nload(\"missing.txt\");";
+    let stderr = run(source, &[]).stderr;
+    let traceback: Vec<&str> = stderr.lines().take(3).collect();
+    assert_eq!(
+        traceback,
+        ["Traceback (most recent call last):", "  line 4, column 1, in <main>", "  line 2, column 12, in load"]
+    );
+}
+
+#[test]
+fn repeated_lines_are_summarised() {
+    let source = "func forever(n) {\n    return 1 + forever(n + 1);\n}\nforever(0);";
+    assert_eq!(
+        run(source, &["--max-depth", "10"]).stderr,
+        "Traceback (most recent call last):
+  line 4, column 1, in <main>
+  line 2, column 16, in forever
+  line 2, column 16, in forever
+  line 2, column 16, in forever
+  [Previous line repeated 7 more times]
+Error: StackOverflowError: Stack overflow: maximum call depth of 10 exceeded
+"
+    );
+}
+
+#[test]
+fn caught_errors_leave_no_traceback() {
+    let source = "try { print 1 / 0; } catch e { print e.kind; }";
+    let output = run(source, &[]);
+    assert_eq!((output.stdout.as_str(), output.stderr.as_str()), ("ZeroDivisionError\n", ""));
+}
+
+#[test]
+fn errors_without_a_position_of_their_own_name_only_the_function() {
+    let source = "func make() {\n    let [a, b] = [1];\n    return a + b;\n}\nmake();";
+    assert_eq!(
+        run(source, &[]).stderr,
+        "Traceback (most recent call last):
+  line 5, column 1, in <main>
+  in make
+Error: RuntimeError: Array pattern expects 2 elements but the array has 1
+"
+    );
+}
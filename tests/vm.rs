@@ -0,0 +1,101 @@
+mod common;
+
+use common::run;
+
+// Runs `source` on each engine, returning (tree-walker, VM)
+fn on_both_engines(source: &str, flags: &[&str]) -> (common::Run, common::Run) {
+    let vm_flags: Vec<&str> = flags.iter().copied().chain(["--vm"]).collect();
+    (run(source, flags), run(source, &vm_flags))
+}
+
+#[test]
+fn the_vm_runs_functions_loops_and_collections() {
+    let source = "func fib(n) { if n < 2 { return n; } return fib(n - 1) + fib(n - 2); }
+    let total = 0;
+    let i = 0;
+    while i < 5 { total += i; i++; }
+    let d = {\"k\": [1, 2]};
+    let list = d[\"k\"];
+    list.push(fib(10));
+    print total;
+    print [d, list];";
+    let (tree, vm) = on_both_engines(source, &[]);
+    assert_eq!(vm.stdout, "10\n[{\"k\": [1, 2]}, [1, 2, 55]]\n");
+    assert_eq!(vm, tree);
+}
+
+#[test]
+fn mutating_an_element_in_place_is_rejected_by_the_vm() {
+    let vm = run("let d = {\"k\": [1]};\nd[\"k\"].push(2);", &["--vm"]);
+    assert_eq!(vm.stderr, "Error: Calling 'push' on anything but a variable is not supported by the bytecode VM\n");
+}
+
+#[test]
+fn wrong_argument_counts_are_type_errors_on_both_engines() {
+    let source = "func add(a, b) { return a + b; }
+    try { print add(1, 2, 3); } catch e { print \"{e.kind}: {e.message}\"; }
+    try { print add(1); } catch e { print e.message; }";
+    let tree = run(source, &[]);
+    assert_eq!(
+        tree.stdout,
+        "TypeError: add() takes 2 argument(s) but 3 were given\nadd() takes 2 argument(s) but 1 were given\n"
+    );
+    // the VM has no try statement, so the error ends the program
+    let vm = run("func add(a, b) { return a + b; }\nprint add(1, 2, 3);", &["--vm"]);
+    assert_eq!(vm.stderr, "Error: TypeError: add() takes 2 argument(s) but 3 were given\n");
+}
+
+#[test]
+fn strict_mode_applies_inside_functions_on_both_engines() {
+    let source = "func f() { z = 3; return z; }\nprint f();";
+    let (tree, vm) = on_both_engines(source, &["--strict"]);
+    assert_eq!(vm.stderr, "Error: Assignment to undeclared variable: z\n");
+    assert_eq!(vm, tree);
+    let (tree, vm) = on_both_engines(source, &[]);
+    assert_eq!(vm.stdout, "3\n");
+    assert_eq!(vm, tree);
+}
+
+#[test]
+fn the_vm_reports_the_resolver_errors_and_warnings() {
+    let warning = "func f() { let unused = 1; return 2; }\nprint f();";
+    let (tree, vm) = on_both_engines(warning, &[]);
+    assert_eq!(vm.stderr, "Warning: Variable 'unused' in function f is never used\n");
+    assert_eq!(vm, tree);
+    let (tree, vm) = on_both_engines("print missing;", &[]);
+    assert_eq!(vm.stderr, "Error: Use of undeclared variable 'missing'\n");
+    assert_eq!(vm, tree);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn a_failed_write_is_an_error_on_the_vm() {
+    let path = std::env::temp_dir().join(format!("alethia-vm-full-{}.at", std::process::id()));
+    std::fs::write(&path, "print 1;").unwrap();
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_alethia"))
+        .args(["run", "--vm"])
+        .arg(&path)
+        .stdout(std::fs::File::create("/dev/full").unwrap())
+        .output()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "Error: Cannot write output: No space left on device (os error 28)\n"
+    );
+}
+
+#[test]
+fn constants_are_checked_once_for_both_engines() {
+    let source = "const limit = 1;\nfunc f() { let unused = 1; return 2; }\nprint f();\nlimit = 2;";
+    let (tree, vm) = on_both_engines(source, &[]);
+    assert_eq!(
+        vm.stderr,
+        "Warning: Variable 'unused' in function f is never used\nError: Cannot assign to constant 'limit'\n"
+    );
+    assert_eq!(vm, tree);
+    let (tree, vm) = on_both_engines("const items = [1];\nfunc f() { items.push(2); }", &[]);
+    assert_eq!(vm.stderr, "Error: Cannot assign to constant 'items'\n");
+    assert_eq!(vm, tree);
+}